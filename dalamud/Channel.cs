@@ -1,17 +1,24 @@
 ﻿using System.IO.Pipes;
+using Dalamud.Game;
+using Dalamud.Logging;
+using Grebuloff.Dalamud.Messages;
+using MessagePack;
 
 namespace Grebuloff.Dalamud;
 
 public class Channel : IDisposable
 {
+    private const int MaxMessageSize = 64 * 1024;
+
     public string Name { get; }
     private readonly CancellationTokenSource _cts = new();
     private readonly NamedPipeServerStream _server;
-    private StreamWriter? _writer;
-    private StreamReader? _reader;
+    private readonly SigScanner _sigScanner;
 
-    public Channel()
+    public Channel(SigScanner sigScanner)
     {
+        _sigScanner = sigScanner;
+
         // generate a random UUID
         Name = $"grebuloff-dalamud-{Guid.NewGuid()}";
         _server = new NamedPipeServerStream(
@@ -23,18 +30,107 @@ public class Channel : IDisposable
         );
     }
 
-    private async void AwaitConnection()
+    public void Start()
+    {
+        Task.Run(Run, _cts.Token);
+    }
+
+    private async Task Run()
+    {
+        try
+        {
+            await _server.WaitForConnectionAsync(_cts.Token);
+            PluginLog.Information("Grebuloff connected to {Name}", Name);
+
+            while (!_cts.IsCancellationRequested)
+            {
+                var message = await ReadMessage();
+                if (message == null)
+                {
+                    PluginLog.Information("Grebuloff disconnected from {Name}", Name);
+                    break;
+                }
+
+                await HandleMessage(message);
+            }
+        }
+        catch (OperationCanceledException)
+        {
+        }
+        catch (Exception e)
+        {
+            PluginLog.Error(e, "error in Grebuloff channel");
+        }
+    }
+
+    /// <summary>
+    /// Reads a whole message from the pipe, or returns null if the pipe was closed.
+    /// </summary>
+    private async Task<byte[]?> ReadMessage()
+    {
+        using var message = new MemoryStream();
+        var buffer = new byte[4096];
+
+        do
+        {
+            var read = await _server.ReadAsync(buffer, _cts.Token);
+            if (read == 0)
+            {
+                return null;
+            }
+
+            message.Write(buffer, 0, read);
+            if (message.Length > MaxMessageSize)
+            {
+                throw new InvalidDataException($"message exceeds {MaxMessageSize} bytes");
+            }
+        } while (!_server.IsMessageComplete);
+
+        return message.ToArray();
+    }
+
+    private async Task HandleMessage(byte[] message)
+    {
+        var header = MessagePackSerializer.Deserialize<MessageHeader>(message);
+        switch (header.Type)
+        {
+            case ResolveSignatureRequest.MessageType:
+                var request = MessagePackSerializer.Deserialize<ResolveSignatureRequest>(message);
+                await WriteMessage(ResolveSignature(request));
+                break;
+            default:
+                PluginLog.Warning("unknown message type from Grebuloff: {Type}", header.Type);
+                break;
+        }
+    }
+
+    private ResolveSignatureResponse ResolveSignature(ResolveSignatureRequest request)
+    {
+        // ScanText follows calls and jumps, so this is the function itself
+        ulong? address = null;
+        if (_sigScanner.TryScanText(request.Signature, out var result))
+        {
+            address = (ulong)result.ToInt64();
+        }
+        else
+        {
+            PluginLog.Warning("failed to resolve signature for Grebuloff: {Signature}", request.Signature);
+        }
+
+        return new ResolveSignatureResponse { Id = request.Id, Address = address };
+    }
+
+    private async Task WriteMessage<T>(T message)
     {
-        await _server.WaitForConnectionAsync(_cts.Token);
-        _writer = new StreamWriter(_server);
-        _reader = new StreamReader(_server);
+        // written in one go, so that it arrives as a single message
+        var bytes = MessagePackSerializer.Serialize(message);
+        await _server.WriteAsync(bytes, _cts.Token);
+        await _server.FlushAsync(_cts.Token);
     }
 
     public void Dispose()
     {
         _cts.Cancel();
-        _reader?.Dispose();
-        _writer?.Dispose();
         _server.Dispose();
     }
-}
\ No newline at end of file
+}
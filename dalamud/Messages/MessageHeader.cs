@@ -0,0 +1,14 @@
+using MessagePack;
+
+namespace Grebuloff.Dalamud.Messages;
+
+/// <summary>
+/// The fields common to every message, used to work out how to handle a message
+/// before deserializing it fully.
+/// </summary>
+[MessagePackObject]
+public class MessageHeader
+{
+    [Key("type")]
+    public string Type { get; set; } = "";
+}
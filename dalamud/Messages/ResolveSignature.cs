@@ -0,0 +1,37 @@
+using MessagePack;
+
+namespace Grebuloff.Dalamud.Messages;
+
+[MessagePackObject]
+public class ResolveSignatureRequest
+{
+    public const string MessageType = "ResolveSignature";
+
+    [Key("type")]
+    public string Type { get; set; } = MessageType;
+
+    [Key("id")]
+    public uint Id { get; set; }
+
+    /// <summary>
+    /// The signature to scan .text for, as space-separated hex bytes with ?? for wildcards.
+    /// </summary>
+    [Key("signature")]
+    public string Signature { get; set; } = "";
+}
+
+[MessagePackObject]
+public class ResolveSignatureResponse
+{
+    [Key("type")]
+    public string Type { get; set; } = ResolveSignatureRequest.MessageType;
+
+    [Key("id")]
+    public uint Id { get; set; }
+
+    /// <summary>
+    /// The address the signature resolved to, or null if it wasn't found.
+    /// </summary>
+    [Key("address")]
+    public ulong? Address { get; set; }
+}
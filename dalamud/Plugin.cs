@@ -9,12 +9,16 @@ public sealed class Plugin : IDalamudPlugin
 
     public DalamudPluginInterface PluginInterface { get; private set; }
     public Framework Framework { get; private set; }
+    public Channel Channel { get; private set; }
     
-    public Plugin(DalamudPluginInterface pluginInterface, Framework framework)
+    public Plugin(DalamudPluginInterface pluginInterface, Framework framework, SigScanner sigScanner)
     {
         PluginInterface = pluginInterface;
         Framework = framework;
 
+        Channel = new Channel(sigScanner);
+        Channel.Start();
+
         PluginInterface.UiBuilder.Draw += this.DrawUI;
         Framework.Update += OnFrameworkUpdate;
     }
@@ -32,5 +36,6 @@ public sealed class Plugin : IDalamudPlugin
     {
         Framework.Update -= OnFrameworkUpdate;
         PluginInterface.UiBuilder.Draw -= this.DrawUI;
+        Channel.Dispose();
     }
 }
\ No newline at end of file
@@ -5,6 +5,7 @@ import { optimizer } from '@electron-toolkit/utils';
 import { RpcClient } from './rpc/client';
 
 // force a scale factor of 1, even on high-DPI displays, as we will control scaling ourselves
+// (the LLRT sends the DPI and UI scale with each resize, and we apply it as the zoom factor)
 app.commandLine.appendSwitch('high-dpi-support', '1');
 app.commandLine.appendSwitch('force-device-scale-factor', '1');
 
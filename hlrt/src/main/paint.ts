@@ -1,26 +1,91 @@
 import { BrowserWindow, NativeImage, Rectangle } from 'electron';
 import { RpcClient } from './rpc/client';
 
+// offscreen frame rates for when the game window is focused and unfocused
+const FOCUSED_FRAME_RATE = 60;
+const UNFOCUSED_FRAME_RATE = 30;
+
 export class UiPainter {
   private paintData?: PaintData;
   private shouldRepaint = true;
   private sending = false;
 
+  private minimized = false;
+  private visible = true;
+
   constructor(private rpc: RpcClient, private browser: BrowserWindow) {
     browser.webContents.on('paint', this.onPaint.bind(this));
     setInterval(this.tick.bind(this), 1);
   }
 
-  handleResize(width: number, height: number) {
-    console.log(`resize: ${width}x${height}`);
+  /**
+   * Whether painting is paused, as the overlay is not currently displayed.
+   */
+  get paused() {
+    return this.minimized || !this.visible;
+  }
+
+  handleFocus(focused: boolean) {
+    this.browser.webContents.setFrameRate(
+      focused ? FOCUSED_FRAME_RATE : UNFOCUSED_FRAME_RATE,
+    );
+  }
+
+  handleMinimize(minimized: boolean) {
+    this.updatePaused(() => (this.minimized = minimized));
+  }
+
+  handleVisibility(visible: boolean) {
+    this.updatePaused(() => (this.visible = visible));
+  }
+
+  private updatePaused(update: () => void) {
+    const wasPaused = this.paused;
+    update();
+
+    if (this.paused === wasPaused) return;
+
+    console.log(`painting ${this.paused ? 'paused' : 'resumed'}`);
+    if (this.paused) {
+      this.browser.webContents.stopPainting();
+    } else {
+      this.browser.webContents.startPainting();
+      this.browser.webContents.invalidate();
+    }
+  }
+
+  /**
+   * Resizes the UI to the given physical size.
+   * The page is zoomed by the combined scale factor, so that it lays out at the
+   * logical size while still painting at the physical size of the game window.
+   */
+  handleResize(
+    width: number,
+    height: number,
+    dpiScale: number,
+    uiScale: number,
+  ) {
+    const scale = dpiScale * uiScale;
+    console.log(
+      `resize: ${width}x${height} (logical ${Math.round(
+        width / scale,
+      )}x${Math.round(height / scale)}, scale ${scale})`,
+    );
+
     this.paintData = undefined;
     this.browser.setContentSize(width, height);
+    this.browser.webContents.setZoomFactor(scale);
   }
 
   async repaint(): Promise<boolean> {
     if (!this.paintData) return false;
 
-    if (this.rpc.ready && !this.sending && this.shouldRepaint) {
+    if (
+      this.rpc.ready &&
+      !this.sending &&
+      this.shouldRepaint &&
+      !this.paused
+    ) {
       this.sending = true;
 
       this.shouldRepaint = false;
@@ -1,12 +1,26 @@
 import { default as net, Socket } from 'net';
 import EventEmitter from 'events';
-import { RpcMessageType } from './messages';
+import { plainToInstance } from 'class-transformer';
+import {
+  RpcClientboundMessageType,
+  RpcMessageRejectedPaints,
+  RpcServerboundMessageType,
+} from './messages';
 import {
   PackedRpcMessage,
   RpcMessageDecoderStream,
   RpcMessageEncoderStream,
   RpcRawEncoderStream,
 } from './codec';
+import {
+  CallersRequest,
+  CallersResult,
+  ResolutionReport,
+  ScanPatternKind,
+  ScanRequest,
+  ScanResult,
+  ScanSection,
+} from './resolver';
 import { UiPainter } from '../paint';
 import { BrowserWindow } from 'electron';
 
@@ -16,6 +30,7 @@ export class RpcClient extends EventEmitter {
   private encoder: RpcMessageEncoderStream | null = null;
   private rawEncoder: RpcRawEncoderStream | null = null;
   private decoder: RpcMessageDecoderStream | null = null;
+  private nextRequestId = 0;
 
   // downstream services
   // todo: tidy this up
@@ -43,8 +58,8 @@ export class RpcClient extends EventEmitter {
     );
   }
 
-  async send(type: RpcMessageType, data: unknown) {
-    new Promise<void>((resolve, reject) => {
+  async send(type: RpcServerboundMessageType, data: unknown) {
+    return new Promise<void>((resolve, reject) => {
       if (!this.client || !this.encoder) {
         return reject(new Error('client is null'));
       }
@@ -76,6 +91,96 @@ export class RpcClient extends EventEmitter {
     });
   }
 
+  /** Fetches how many of our paints the LLRT has rejected, by reason. */
+  async getRejectedPaints(): Promise<RpcMessageRejectedPaints> {
+    return this.request(
+      RpcServerboundMessageType.GetRejectedPaints,
+      {},
+      RpcClientboundMessageType.RejectedPaints,
+    );
+  }
+
+  /** Fetches the LLRT's signature resolution report. */
+  async getResolutionReport(): Promise<ResolutionReport> {
+    return this.request(
+      RpcServerboundMessageType.GetResolutionReport,
+      {},
+      RpcClientboundMessageType.ResolutionReport,
+    );
+  }
+
+  /**
+   * Scans the game executable, for data that the LLRT doesn't resolve itself.
+   * Results for `.data` may be out of date if cached, as it changes while the
+   * game runs.
+   */
+  async scan(
+    pattern: string,
+    patternKind: ScanPatternKind,
+    section: ScanSection,
+    maxResults: number,
+    useCache = true,
+  ): Promise<ScanResult> {
+    const id = this.nextRequestId++;
+    return this.request(
+      RpcServerboundMessageType.Scan,
+      new ScanRequest(id, pattern, patternKind, section, maxResults, useCache),
+      RpcClientboundMessageType.ScanResult,
+      (result: ScanResult) => result.id === id,
+    );
+  }
+
+  /**
+   * Finds everything that calls the function at `rva` in the game executable,
+   * and optionally the vtable slots pointing to it.
+   */
+  async findCallers(
+    rva: number,
+    includeVTables = false,
+  ): Promise<CallersResult> {
+    const id = this.nextRequestId++;
+    return this.request(
+      RpcServerboundMessageType.FindCallers,
+      new CallersRequest(id, rva, includeVTables),
+      RpcClientboundMessageType.Callers,
+      (result: CallersResult) => result.id === id,
+    );
+  }
+
+  /**
+   * Sends a request, and waits for the first response of the given type that
+   * matches. Fails if the request can't be sent, or the LLRT disconnects first.
+   */
+  private request<T>(
+    type: RpcServerboundMessageType,
+    data: unknown,
+    responseType: RpcClientboundMessageType,
+    matches: (response: T) => boolean = () => true,
+  ): Promise<T> {
+    return new Promise((resolve, reject) => {
+      const onResponse = (response: T) => {
+        if (!matches(response)) return;
+
+        cleanup();
+        resolve(response);
+      };
+      const onClose = () => fail(new Error('disconnected from LLRT pipe'));
+      const fail = (error: Error) => {
+        cleanup();
+        reject(error);
+      };
+      const cleanup = () => {
+        this.off(responseType, onResponse);
+        this.off('close', onClose);
+      };
+
+      // listen first, so the response can't arrive before we do
+      this.on(responseType, onResponse);
+      this.on('close', onClose);
+      this.send(type, data).catch(fail);
+    });
+  }
+
   private onConnect() {
     if (!this.client) {
       throw new Error('client is null');
@@ -113,8 +218,34 @@ export class RpcClient extends EventEmitter {
 
     const data = packed.data;
     switch (packed.type) {
-      case RpcMessageType.Resize:
-        this.uiPainter.handleResize(data.width, data.height);
+      case RpcClientboundMessageType.Resize:
+        this.uiPainter.handleResize(
+          data.width,
+          data.height,
+          data.dpi_scale ?? 1,
+          data.ui_scale ?? 1,
+        );
+        break;
+      case RpcClientboundMessageType.Focus:
+        this.uiPainter.handleFocus(data.focused);
+        break;
+      case RpcClientboundMessageType.Minimize:
+        this.uiPainter.handleMinimize(data.minimized);
+        break;
+      case RpcClientboundMessageType.Visibility:
+        this.uiPainter.handleVisibility(data.visible);
+        break;
+      case RpcClientboundMessageType.RejectedPaints:
+        this.emit(packed.type, plainToInstance(RpcMessageRejectedPaints, data));
+        break;
+      case RpcClientboundMessageType.ResolutionReport:
+        this.emit(packed.type, plainToInstance(ResolutionReport, data));
+        break;
+      case RpcClientboundMessageType.ScanResult:
+        this.emit(packed.type, plainToInstance(ScanResult, data));
+        break;
+      case RpcClientboundMessageType.Callers:
+        this.emit(packed.type, plainToInstance(CallersResult, data));
         break;
     }
   }
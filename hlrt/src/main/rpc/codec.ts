@@ -1,6 +1,6 @@
 import { Packr, Unpackr } from 'msgpackr';
 import { Transform, TransformCallback } from 'stream';
-import { RpcMessageType } from './messages';
+import { RpcClientboundMessageType, RpcMessageType } from './messages';
 
 abstract class LengthDecoderStream extends Transform {
   private incompleteChunk: Buffer | null = null;
@@ -81,7 +81,7 @@ export class RpcMessageDecoderStream extends LengthDecoderStream {
         console.dir(decoded);
 
         // extract the message type
-        const type = Object.keys(decoded.Ui)[0] as RpcMessageType;
+        const type = Object.keys(decoded.Ui)[0] as RpcClientboundMessageType;
 
         // push the decoded message
         this.push(new PackedRpcMessage(type, decoded.Ui[type]));
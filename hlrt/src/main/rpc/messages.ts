@@ -1,18 +1,51 @@
 import { Type } from 'class-transformer';
 
-export enum RpcMessageType {
+// sent by the LLRT
+export enum RpcClientboundMessageType {
   Resize = 'Resize',
+  Focus = 'Focus',
+  Minimize = 'Minimize',
+  Visibility = 'Visibility',
+  RejectedPaints = 'RejectedPaints',
+  ResolutionReport = 'ResolutionReport',
+  ScanResult = 'ScanResult',
+  Callers = 'Callers',
 }
 
+// sent to the LLRT
+export enum RpcServerboundMessageType {
+  ExportSnapshot = 'ExportSnapshot',
+  SetScalePolicy = 'SetScalePolicy',
+  SetUiScale = 'SetUiScale',
+  GetRejectedPaints = 'GetRejectedPaints',
+  GetResolutionReport = 'GetResolutionReport',
+  Scan = 'Scan',
+  FindCallers = 'FindCallers',
+}
+
+export type RpcMessageType =
+  | RpcClientboundMessageType
+  | RpcServerboundMessageType;
+
 export class RpcMessageResize {}
 
+// how many of our paints the LLRT has rejected, by reason
+export class RpcMessageRejectedPaints {
+  public readonly bad_header!: number;
+  public readonly unknown_format!: number;
+  public readonly bad_dimensions!: number;
+  public readonly size_mismatch!: number;
+}
+
 export class PackedRpcMessage {
   public readonly type: RpcMessageType;
 
   @Type(() => Object, {
     discriminator: {
       property: 'type',
-      subTypes: [{ value: RpcMessageResize, name: RpcMessageType.Resize }],
+      subTypes: [
+        { value: RpcMessageResize, name: RpcClientboundMessageType.Resize },
+      ],
     },
   })
   public readonly data: any;
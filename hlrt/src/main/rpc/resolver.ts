@@ -0,0 +1,101 @@
+import { Type } from 'class-transformer';
+
+// msgpack decodes integers that don't fit in 32 bits, such as addresses, as bigints
+export type U64 = number | bigint;
+
+export type ScanPatternKind = 'Signature' | 'Text';
+
+export type ScanSection = 'Text' | 'Data' | 'Rdata';
+
+export class ScanRequest {
+  constructor(
+    public readonly id: number,
+    public readonly pattern: string,
+    public readonly pattern_kind: ScanPatternKind,
+    public readonly section: ScanSection,
+    public readonly max_results: number,
+    public readonly use_cache: boolean,
+  ) {}
+}
+
+export class ScanMatch {
+  public readonly rva!: U64;
+  public readonly address!: U64;
+}
+
+export class ScanResult {
+  public readonly id!: number;
+
+  @Type(() => ScanMatch)
+  public readonly matches!: ScanMatch[];
+
+  public readonly truncated!: boolean;
+  public readonly cached!: boolean;
+  public readonly error!: string | null;
+}
+
+export type CallerKind = 'Call' | 'Jump' | 'VTableSlot';
+
+export class CallersRequest {
+  constructor(
+    public readonly id: number,
+    public readonly rva: number,
+    public readonly include_vtables: boolean,
+  ) {}
+}
+
+export class Caller {
+  public readonly kind!: CallerKind;
+  public readonly rva!: U64;
+  public readonly address!: U64;
+  public readonly function!: U64 | null;
+}
+
+export class CallersResult {
+  public readonly id!: number;
+
+  @Type(() => Caller)
+  public readonly callers!: Caller[];
+
+  public readonly error!: string | null;
+}
+
+export type SignatureKind =
+  | 'VTable'
+  | 'StaticAddress'
+  | 'MemberFunction'
+  | 'StringReference'
+  | 'Export'
+  | 'Import';
+
+export type ResolutionStatus = 'Resolved' | 'NullPointer' | 'NotFound';
+
+export type ResolutionSource = 'Scan' | 'Database' | 'Dalamud' | 'SymbolTable';
+
+export class SignatureResolution {
+  public readonly kind!: SignatureKind;
+  public readonly signature!: string;
+  public readonly status!: ResolutionStatus;
+  public readonly source!: ResolutionSource | null;
+  public readonly address!: U64 | null;
+  public readonly matches!: number | null;
+  public readonly elapsed_us!: U64;
+}
+
+export class PointerPathResolution {
+  public readonly name!: string;
+  public readonly path!: string;
+  public readonly address!: U64 | null;
+  public readonly error!: string | null;
+}
+
+export class ResolutionReport {
+  @Type(() => SignatureResolution)
+  public readonly signatures!: SignatureResolution[];
+
+  public readonly critical_failures!: string[];
+  public readonly elapsed_ms!: U64;
+
+  @Type(() => PointerPathResolution)
+  public readonly pointer_paths!: PointerPathResolution[];
+}
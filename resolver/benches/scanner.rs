@@ -0,0 +1,125 @@
+#![feature(test)]
+
+extern crate test;
+
+use grebuloff_resolver::scanner::{self, Pattern};
+use test::{black_box, Bencher};
+
+/// Roughly the size of the game's .text section.
+const IMAGE_SIZE: usize = 32 * 1024 * 1024;
+
+/// Number of signatures used for the batch benchmark, roughly what we resolve at startup.
+const BATCH_SIZE: usize = 300;
+
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+fn random_image() -> Vec<u8> {
+    let mut rng = XorShift(0x2545F4914F6CDD1D);
+    (0..IMAGE_SIZE).map(|_| rng.next() as u8).collect()
+}
+
+/// Generates a pseudo-random image, with the pattern planted near the end.
+fn synthetic_image(pattern: &Pattern) -> Vec<u8> {
+    let mut image = random_image();
+
+    let at = IMAGE_SIZE - 4096;
+    image[at..at + pattern.len()].copy_from_slice(pattern.bytes());
+    image
+}
+
+/// Generates signatures taken from random locations in the image, with some wildcards.
+fn random_patterns(image: &[u8]) -> Vec<Pattern> {
+    let mut rng = XorShift(0x9E3779B97F4A7C15);
+
+    (0..BATCH_SIZE)
+        .map(|_| {
+            let len = 8 + (rng.next() % 16) as usize;
+            let at = (rng.next() as usize) % (image.len() - len);
+            let mask: Vec<u8> = (0..len)
+                .map(|_| if rng.next() % 4 == 0 { 0x00 } else { 0xFF })
+                .collect();
+
+            Pattern::new(image[at..at + len].to_vec(), mask)
+        })
+        .collect()
+}
+
+/// The byte-by-byte scan the resolvers used previously, for comparison.
+fn find_naive(haystack: &[u8], pattern: &Pattern) -> Option<usize> {
+    (0..=haystack.len() - pattern.len()).find(|&pos| {
+        (0..pattern.len()).all(|i| haystack[pos + i] & pattern.mask()[i] == pattern.bytes()[i])
+    })
+}
+
+fn call_then_cmp() -> Pattern {
+    Pattern::from_byte_mask(
+        &[0xE8, 0, 0, 0, 0, 0x80, 0x7C, 0x24, 0, 0, 0x74, 0, 0xB8],
+        &[1, 0, 0, 0, 0, 1, 1, 1, 0, 0, 1, 0, 1],
+    )
+}
+
+fn lea_rip() -> Pattern {
+    Pattern::from_byte_mask(
+        &[0x48, 0x8D, 0x05, 0, 0, 0, 0, 0x48, 0x89, 0x03],
+        &[1, 1, 1, 0, 0, 0, 0, 1, 1, 1],
+    )
+}
+
+fn bench_find(b: &mut Bencher, pattern: Pattern, find: fn(&[u8], &Pattern) -> Option<usize>) {
+    let image = synthetic_image(&pattern);
+
+    b.bytes = IMAGE_SIZE as u64;
+    b.iter(|| find(black_box(&image), black_box(&pattern)));
+}
+
+#[bench]
+fn find_anchored_call_then_cmp(b: &mut Bencher) {
+    bench_find(b, call_then_cmp(), scanner::find);
+}
+
+#[bench]
+fn find_naive_call_then_cmp(b: &mut Bencher) {
+    bench_find(b, call_then_cmp(), find_naive);
+}
+
+#[bench]
+fn find_anchored_lea_rip(b: &mut Bencher) {
+    bench_find(b, lea_rip(), scanner::find);
+}
+
+#[bench]
+fn find_naive_lea_rip(b: &mut Bencher) {
+    bench_find(b, lea_rip(), find_naive);
+}
+
+#[bench]
+fn find_batch(b: &mut Bencher) {
+    let image = random_image();
+    let patterns = random_patterns(&image);
+
+    b.bytes = IMAGE_SIZE as u64;
+    b.iter(|| scanner::find_batch(black_box(&image), black_box(&patterns)));
+}
+
+#[bench]
+fn find_batch_individually(b: &mut Bencher) {
+    let image = random_image();
+    let patterns = random_patterns(&image);
+
+    b.bytes = IMAGE_SIZE as u64;
+    b.iter(|| {
+        patterns
+            .iter()
+            .map(|pattern| scanner::find(black_box(&image), pattern))
+            .collect::<Vec<_>>()
+    });
+}
@@ -0,0 +1,281 @@
+//! Finds what calls or jumps to a function, and the vtable slots pointing to it,
+//! for working out how a function is used when reversing.
+
+use std::ops::Range;
+
+use iced_x86::{Decoder, DecoderOptions, Instruction};
+use memchr::memmem;
+
+use crate::{
+    pe::{PeImage, RuntimeFunction},
+    xref::{self, XrefError},
+};
+
+const CALL_REL32: u8 = 0xE8;
+const JMP_REL32: u8 = 0xE9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    /// A `call rel32`.
+    Call,
+    /// A `jmp rel32`, such as a tail call.
+    Jump,
+    /// A pointer in `.rdata`, such as a vtable slot.
+    VTableSlot,
+}
+
+/// Somewhere that refers to a function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    /// The RVA of the instruction or pointer.
+    pub rva: usize,
+    pub kind: ReferenceKind,
+    /// The start of the function containing the instruction, if it could be found.
+    pub function: Option<usize>,
+}
+
+/// Finds every direct call and jump in `.text` to `target`, sorted by RVA. With the
+/// address the image is based at, pointers to `target` in `.rdata` are found too.
+/// `pe` must have been parsed from a mapped image.
+pub fn find_callers(
+    pe: &PeImage,
+    target: usize,
+    vtable_base: Option<u64>,
+) -> Result<Vec<Reference>, XrefError> {
+    let image = pe.data();
+    let text = xref::section_range(pe, ".text")?;
+    let functions = pe.runtime_functions().unwrap_or_default();
+
+    let mut references: Vec<_> = find_branches(image, text.clone(), &functions, target)
+        .into_iter()
+        .map(|(rva, kind)| Reference {
+            rva,
+            kind,
+            function: xref::function_start(pe, &functions, text.clone(), rva),
+        })
+        .collect();
+
+    if let Some(base) = vtable_base {
+        let rdata = xref::section_range(pe, ".rdata")?;
+        references.extend(
+            find_pointers(image, rdata, base, target)
+                .into_iter()
+                .map(|rva| Reference {
+                    rva,
+                    kind: ReferenceKind::VTableSlot,
+                    function: None,
+                }),
+        );
+    }
+
+    references.sort_unstable_by_key(|r| r.rva);
+    Ok(references)
+}
+
+/// Finds every `call rel32` and `jmp rel32` in `text` branching to `target`.
+///
+/// The opcode and displacement can also turn up in the middle of other instructions,
+/// so each candidate within a runtime function is checked by decoding the function
+/// up to it. Candidates outside of one can't be checked, and are kept.
+pub fn find_branches(
+    image: &[u8],
+    text: Range<usize>,
+    functions: &[RuntimeFunction],
+    target: usize,
+) -> Vec<(usize, ReferenceKind)> {
+    let Some(code) = image.get(text.clone()) else {
+        return Vec::new();
+    };
+
+    let mut branches = Vec::new();
+    for at in 0..code.len().saturating_sub(4) {
+        let kind = match code[at] {
+            CALL_REL32 => ReferenceKind::Call,
+            JMP_REL32 => ReferenceKind::Jump,
+            _ => continue,
+        };
+
+        let rva = text.start + at;
+        let displacement = i32::from_le_bytes(code[at + 1..at + 5].try_into().unwrap());
+        if (rva + 5) as isize + displacement as isize != target as isize {
+            continue;
+        }
+
+        if is_instruction_start(image, functions, rva) {
+            branches.push((rva, kind));
+        }
+    }
+
+    branches
+}
+
+/// Checks that `rva` is the start of an instruction by decoding the runtime function
+/// containing it from the start. Assumed to be, if there's no such function.
+fn is_instruction_start(image: &[u8], functions: &[RuntimeFunction], rva: usize) -> bool {
+    let Some(function) = xref::containing_function(functions, rva) else {
+        return true;
+    };
+
+    let start = function.begin as usize;
+    let Some(code) = image.get(start..function.end as usize) else {
+        return false;
+    };
+
+    let mut decoder = Decoder::with_ip(64, code, start as u64, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    while decoder.can_decode() {
+        let ip = decoder.ip() as usize;
+        if ip >= rva {
+            return ip == rva;
+        }
+
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() {
+            return false;
+        }
+    }
+
+    false
+}
+
+/// Finds every 8-byte aligned pointer to `target` in `range`, for an image based at `base`.
+pub fn find_pointers(image: &[u8], range: Range<usize>, base: u64, target: usize) -> Vec<usize> {
+    let Some(haystack) = image.get(range.clone()) else {
+        return Vec::new();
+    };
+
+    let pointer = base.wrapping_add(target as u64).to_le_bytes();
+    memmem::find_iter(haystack, &pointer)
+        .map(|pos| range.start + pos)
+        .filter(|rva| rva % 8 == 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::{
+        map_image,
+        tests::{sample_pe, RDATA, TEXT},
+        Layout,
+    };
+
+    const BASE: u64 = 0x1_4000_0000;
+    const TARGET: usize = 0x1080;
+
+    /// Writes a branch to `TARGET` with the given opcode at `rva`.
+    fn put_branch(text: &mut [u8], rva: usize, opcode: u8) {
+        let displacement = TARGET as i32 - (rva as i32 + 5);
+        text[rva - 0x1000] = opcode;
+        text[rva - 0x1000 + 1..rva - 0x1000 + 5].copy_from_slice(&displacement.to_le_bytes());
+    }
+
+    fn sample_image() -> Vec<u8> {
+        let mut text = vec![0xCC; 0x100];
+
+        // a function that calls the target, then tail calls it
+        text[0x10..0x14].copy_from_slice(&[0x48, 0x83, 0xEC, 0x28]);
+        put_branch(&mut text, 0x1014, CALL_REL32);
+        // mov rax, imm64, with what looks like a call to the target in the immediate
+        text[0x19..0x23].copy_from_slice(&[0x48, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0]);
+        put_branch(&mut text, 0x101C, CALL_REL32);
+        text[0x23..0x27].copy_from_slice(&[0x48, 0x83, 0xC4, 0x28]);
+        put_branch(&mut text, 0x1027, JMP_REL32);
+
+        // a call outside any runtime function
+        put_branch(&mut text, 0x1050, CALL_REL32);
+        text[0x55] = 0xC3;
+
+        // the target itself
+        text[0x80] = 0xC3;
+
+        // a vtable slot, and an unaligned pointer that isn't one
+        let mut rdata = vec![0u8; 0x20];
+        let pointer = (BASE + TARGET as u64).to_le_bytes();
+        rdata[0x08..0x10].copy_from_slice(&pointer);
+        rdata[0x13..0x1B].copy_from_slice(&pointer);
+
+        let mut pdata = Vec::new();
+        for value in [0x1010u32, 0x102C, 0x3100] {
+            pdata.extend_from_slice(&value.to_le_bytes());
+        }
+        pdata.resize(0x110, 0);
+
+        let mut file = sample_pe(
+            true,
+            &[
+                (".text", 0x1000, &text, TEXT),
+                (".rdata", 0x2000, &rdata, RDATA),
+                (".pdata", 0x3000, &pdata, RDATA),
+            ],
+        );
+        // the exception directory
+        file[0x120..0x124].copy_from_slice(&0x3000u32.to_le_bytes());
+        file[0x124..0x128].copy_from_slice(&12u32.to_le_bytes());
+
+        map_image(&file).unwrap()
+    }
+
+    #[test]
+    fn test_find_branches() {
+        let image = sample_image();
+        let pe = PeImage::parse(&image, Layout::Mapped).unwrap();
+        let functions = pe.runtime_functions().unwrap();
+
+        assert_eq!(
+            find_branches(&image, 0x1000..0x1100, &functions, TARGET),
+            [
+                (0x1014, ReferenceKind::Call),
+                (0x1027, ReferenceKind::Jump),
+                (0x1050, ReferenceKind::Call)
+            ]
+        );
+        assert_eq!(
+            find_branches(&image, 0x1000..0x1100, &functions, 0x1090),
+            []
+        );
+
+        assert_eq!(
+            find_pointers(&image, 0x2000..0x2020, BASE, TARGET),
+            [0x2008]
+        );
+        assert_eq!(find_pointers(&image, 0x2000..0x2020, 0x40_0000, TARGET), []);
+    }
+
+    #[test]
+    fn test_find_callers() {
+        let image = sample_image();
+        let pe = PeImage::parse(&image, Layout::Mapped).unwrap();
+
+        let callers = find_callers(&pe, TARGET, Some(BASE)).unwrap();
+        assert_eq!(
+            callers,
+            [
+                Reference {
+                    rva: 0x1014,
+                    kind: ReferenceKind::Call,
+                    function: Some(0x1010),
+                },
+                Reference {
+                    rva: 0x1027,
+                    kind: ReferenceKind::Jump,
+                    function: Some(0x1010),
+                },
+                // without unwind info, the start is taken to be after the padding
+                Reference {
+                    rva: 0x1050,
+                    kind: ReferenceKind::Call,
+                    function: Some(0x1050),
+                },
+                Reference {
+                    rva: 0x2008,
+                    kind: ReferenceKind::VTableSlot,
+                    function: None,
+                },
+            ]
+        );
+
+        // without the base, vtable slots aren't looked for
+        assert_eq!(find_callers(&pe, TARGET, None).unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,263 @@
+//! Generates signatures for addresses in an image, so that new signatures don't
+//! need to be worked out by hand in a disassembler.
+
+use std::{fmt, ops::Range};
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, OpKind};
+
+use crate::{
+    pe::Relocation,
+    scanner::{self, Pattern},
+};
+
+/// The default longest signature to generate, in bytes.
+pub const DEFAULT_MAX_LEN: usize = 64;
+
+/// Reasons a signature can't be generated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenerateError {
+    /// The RVA is outside the code being searched.
+    OutsideText { rva: usize },
+    /// An instruction couldn't be decoded before the signature became unique.
+    Undecodable { rva: usize },
+    /// The signature still matched more than once at the maximum length.
+    NotUnique { len: usize, matches: usize },
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerateError::OutsideText { rva } => {
+                write!(f, "RVA 0x{:X} is outside .text", rva)
+            }
+            GenerateError::Undecodable { rva } => {
+                write!(f, "couldn't decode the instruction at 0x{:X}", rva)
+            }
+            GenerateError::NotUnique { len, matches } => write!(
+                f,
+                "no unique signature within {} bytes ({} matches)",
+                len, matches
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+/// Generates the shortest signature that matches only at `rva` within `text`,
+/// covering whole instructions up to `max_len` bytes. The signature is at least
+/// `min_len` bytes long where possible, e.g. to cover an operand past the start.
+///
+/// Bytes that change between builds or at load time are wildcarded: RIP-relative
+/// displacements, relative branch targets, and anything covered by `relocations`,
+/// which must be sorted by RVA as returned by `PeImage::relocations`.
+pub fn generate(
+    image: &[u8],
+    text: Range<usize>,
+    relocations: &[Relocation],
+    rva: usize,
+    min_len: usize,
+    max_len: usize,
+) -> Result<Pattern, GenerateError> {
+    if !text.contains(&rva) || text.end > image.len() {
+        return Err(GenerateError::OutsideText { rva });
+    }
+
+    let (bytes, mask, undecodable) = template(image, text.end, relocations, rva, max_len);
+    if bytes.is_empty() {
+        return Err(GenerateError::Undecodable { rva });
+    }
+
+    let code = &image[text];
+    let matches = |len: usize| {
+        let pattern = Pattern::from_byte_mask(&bytes[..len], &mask[..len]);
+        scanner::count(code, &pattern).count
+    };
+
+    // trailing wildcards don't narrow anything down, so only try lengths ending on
+    // an exact byte; matching a longer prefix only ever rules out matches, so the
+    // shortest unique length can be found by bisecting
+    let lengths: Vec<usize> = (1..=bytes.len())
+        .filter(|&len| mask[len - 1] != 0)
+        .collect();
+    let Some(&longest) = lengths.last() else {
+        // relocations cover everything, so the signature would match anywhere
+        return Err(GenerateError::NotUnique {
+            len: bytes.len(),
+            matches: matches(bytes.len()),
+        });
+    };
+    let count = matches(longest);
+    if count > 1 {
+        return Err(match undecodable {
+            Some(rva) => GenerateError::Undecodable { rva },
+            None => GenerateError::NotUnique {
+                len: longest,
+                matches: count,
+            },
+        });
+    }
+
+    let lengths = &lengths[lengths.partition_point(|&len| len < min_len.min(longest))..];
+    let shortest = lengths[lengths.partition_point(|&len| matches(len) > 1)];
+    Ok(Pattern::from_byte_mask(
+        &bytes[..shortest],
+        &mask[..shortest],
+    ))
+}
+
+/// Decodes whole instructions from `rva` up to `max_len` bytes, returning their bytes,
+/// a byte mask with everything that varies wildcarded, and the RVA of the instruction
+/// that stopped decoding early, if any.
+fn template(
+    image: &[u8],
+    end: usize,
+    relocations: &[Relocation],
+    rva: usize,
+    max_len: usize,
+) -> (Vec<u8>, Vec<u8>, Option<usize>) {
+    let code = &image[rva..end];
+    let mut decoder = Decoder::with_ip(64, code, rva as u64, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut mask = Vec::new();
+
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() {
+            let at = rva + mask.len();
+            return (code[..mask.len()].to_vec(), mask, Some(at));
+        }
+
+        if mask.len() + instruction.len() > max_len {
+            break;
+        }
+
+        let start = mask.len();
+        mask.resize(start + instruction.len(), 1);
+
+        let offsets = decoder.get_constant_offsets(&instruction);
+        if instruction.is_ip_rel_memory_operand() && offsets.has_displacement() {
+            let at = start + offsets.displacement_offset();
+            mask[at..at + offsets.displacement_size()].fill(0);
+        }
+        // relative branch targets are encoded as an immediate
+        let is_branch = (0..instruction.op_count()).any(|i| {
+            matches!(
+                instruction.op_kind(i),
+                OpKind::NearBranch16 | OpKind::NearBranch32 | OpKind::NearBranch64
+            )
+        });
+        if is_branch && offsets.has_immediate() {
+            let at = start + offsets.immediate_offset();
+            mask[at..at + offsets.immediate_size()].fill(0);
+        }
+    }
+
+    // relocations may start before the template and run into it
+    let first = relocations.partition_point(|r| (r.rva as usize) + (r.size as usize) <= rva);
+    for relocation in &relocations[first..] {
+        let start = relocation.rva as usize;
+        if start >= rva + mask.len() {
+            break;
+        }
+
+        let from = start.saturating_sub(rva);
+        let to = (start + relocation.size as usize - rva).min(mask.len());
+        mask[from..to].fill(0);
+    }
+
+    (code[..mask.len()].to_vec(), mask, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small image with two functions that only differ in their fourth instruction.
+    fn sample_image() -> Vec<u8> {
+        let function = |last: &[u8]| {
+            [
+                // sub rsp, 0x28
+                &[0x48, 0x83, 0xEC, 0x28][..],
+                // mov rcx, [rip + 0x1000]
+                &[0x48, 0x8B, 0x0D, 0x00, 0x10, 0x00, 0x00],
+                // call +0x20
+                &[0xE8, 0x20, 0x00, 0x00, 0x00],
+                last,
+                // add rsp, 0x28; ret
+                &[0x48, 0x83, 0xC4, 0x28, 0xC3],
+            ]
+            .concat()
+        };
+
+        let mut image = vec![0xCC; 0x200];
+        // test al, al
+        let a = function(&[0x84, 0xC0]);
+        // test cl, cl
+        let b = function(&[0x84, 0xC9]);
+        image[0x100..0x100 + a.len()].copy_from_slice(&a);
+        image[0x140..0x140 + b.len()].copy_from_slice(&b);
+        image
+    }
+
+    #[test]
+    fn test_generate() {
+        let image = sample_image();
+
+        let pattern = generate(&image, 0x100..0x200, &[], 0x140, 1, DEFAULT_MAX_LEN).unwrap();
+        assert_eq!(
+            pattern.to_string(),
+            "48 83 EC 28 48 8B 0D ?? ?? ?? ?? E8 ?? ?? ?? ?? 84 C9"
+        );
+        assert_eq!(scanner::find(&image[0x100..], &pattern), Some(0x40));
+
+        // shorter, as the other function is outside .text
+        let pattern = generate(&image, 0x120..0x200, &[], 0x140, 1, DEFAULT_MAX_LEN).unwrap();
+        assert_eq!(pattern.to_string(), "48 83 EC");
+
+        // long enough to cover the displacement of the second instruction
+        let pattern = generate(&image, 0x120..0x200, &[], 0x140, 8, DEFAULT_MAX_LEN).unwrap();
+        assert_eq!(pattern.to_string(), "48 83 EC 28 48 8B 0D ?? ?? ?? ?? E8");
+
+        // a relocation over the differing instruction leaves nothing to tell them apart
+        let relocations = [Relocation {
+            rva: 0x150,
+            size: 4,
+        }];
+        assert_eq!(
+            generate(&image, 0x100..0x200, &relocations, 0x140, 1, 32),
+            Err(GenerateError::NotUnique {
+                len: 32,
+                matches: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_generate_errors() {
+        let image = sample_image();
+
+        assert_eq!(
+            generate(&image, 0x100..0x200, &[], 0x80, 1, DEFAULT_MAX_LEN),
+            Err(GenerateError::OutsideText { rva: 0x80 })
+        );
+
+        // too short to reach the differing instruction
+        assert_eq!(
+            generate(&image, 0x100..0x200, &[], 0x140, 1, 16),
+            Err(GenerateError::NotUnique {
+                len: 12,
+                matches: 2
+            })
+        );
+
+        // decoding runs into an invalid instruction first
+        let mut image = image;
+        image[0x150] = 0x06;
+        image[0x110] = 0x06;
+        assert_eq!(
+            generate(&image, 0x100..0x200, &[], 0x140, 1, DEFAULT_MAX_LEN),
+            Err(GenerateError::Undecodable { rva: 0x150 })
+        );
+    }
+}
@@ -0,0 +1,15 @@
+//! Platform-independent signature scanning and image parsing,
+//! used by the LLRT's resolvers.
+
+pub mod calls;
+pub mod generate;
+pub mod module;
+pub mod path;
+pub mod pe;
+pub mod resolve;
+pub mod rtti;
+pub mod scanner;
+pub mod search;
+pub mod signature;
+pub mod symbol;
+pub mod xref;
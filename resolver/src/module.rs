@@ -0,0 +1,177 @@
+//! A module laid out as loaded, with the sections resolvers work on. Resolvers take
+//! one of these rather than reaching for global state, so that any number of images
+//! (the game, system DLLs, test fixtures) can be resolved against at once.
+
+use std::ops::Range;
+
+use crate::pe::{Layout, PeError, PeImage};
+
+/// The sections of a module that resolvers scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SectionKind {
+    Text,
+    Data,
+    Rdata,
+}
+
+impl SectionKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SectionKind::Text => ".text",
+            SectionKind::Data => ".data",
+            SectionKind::Rdata => ".rdata",
+        }
+    }
+}
+
+/// A module laid out as it is in memory, either mapped by the loader or by
+/// `pe::map_image`, and the RVA ranges of its sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModuleImage<'a> {
+    data: &'a [u8],
+    text: Range<usize>,
+    data_section: Option<Range<usize>>,
+    rdata: Option<Range<usize>>,
+}
+
+impl<'a> ModuleImage<'a> {
+    /// Finds the sections of a mapped image from its headers. Every module has code,
+    /// so it's an error for `.text` to be missing.
+    pub fn parse(data: &'a [u8]) -> Result<Self, PeError> {
+        let pe = PeImage::parse(data, Layout::Mapped)?;
+        let range = |kind: SectionKind| {
+            let section = pe.section(kind.name())?;
+            let start = section.virtual_address as usize;
+            Some(start..start + pe.section_data(section).len())
+        };
+
+        Ok(Self {
+            text: range(SectionKind::Text).ok_or(PeError::MissingSection(".text"))?,
+            data_section: range(SectionKind::Data),
+            rdata: range(SectionKind::Rdata),
+            data,
+        })
+    }
+
+    /// Describes an image by its sections alone, such as a test fixture without headers.
+    /// Sections are clamped to the image.
+    pub fn with_sections(
+        data: &'a [u8],
+        text: Range<usize>,
+        data_section: Option<Range<usize>>,
+        rdata: Option<Range<usize>>,
+    ) -> Self {
+        let clamp = |range: Range<usize>| {
+            let end = range.end.min(data.len());
+            range.start.min(end)..end
+        };
+        Self {
+            data,
+            text: clamp(text),
+            data_section: data_section.map(clamp),
+            rdata: rdata.map(clamp),
+        }
+    }
+
+    /// The whole image, indexed by RVA.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// The address the image starts at, which is the module's base address for a
+    /// module loaded in this process.
+    pub fn base(&self) -> *const u8 {
+        self.data.as_ptr()
+    }
+
+    /// The address of an RVA within the image.
+    pub fn address(&self, rva: usize) -> *const u8 {
+        self.base().wrapping_add(rva)
+    }
+
+    /// Parses the image's headers.
+    pub fn pe(&self) -> Result<PeImage<'a>, PeError> {
+        PeImage::parse(self.data, Layout::Mapped)
+    }
+
+    pub fn text_range(&self) -> Range<usize> {
+        self.text.clone()
+    }
+
+    pub fn text(&self) -> &'a [u8] {
+        &self.data[self.text.clone()]
+    }
+
+    /// Returns the RVA range of a section, if the module has it.
+    pub fn section_range(&self, kind: SectionKind) -> Option<Range<usize>> {
+        match kind {
+            SectionKind::Text => Some(self.text.clone()),
+            SectionKind::Data => self.data_section.clone(),
+            SectionKind::Rdata => self.rdata.clone(),
+        }
+    }
+
+    /// Returns the contents of a section, if the module has it.
+    pub fn section(&self, kind: SectionKind) -> Option<&'a [u8]> {
+        self.section_range(kind).map(|range| &self.data[range])
+    }
+
+    /// Reads the pointer stored at an RVA.
+    pub fn read_pointer(&self, rva: usize) -> Option<*const u8> {
+        let bytes = self.data.get(rva..rva.checked_add(8)?)?;
+        Some(u64::from_le_bytes(bytes.try_into().unwrap()) as usize as *const u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::{
+        map_image,
+        tests::{sample_pe, DATA, TEXT},
+    };
+
+    #[test]
+    fn test_parse() {
+        let file = sample_pe(
+            true,
+            &[
+                (".text", 0x1000, &[0x48, 0x8B, 0x05, 0xC3], TEXT),
+                (".data", 0x2000, &0x1234u64.to_le_bytes(), DATA),
+            ],
+        );
+        let mapped = map_image(&file).unwrap();
+        let module = ModuleImage::parse(&mapped).unwrap();
+
+        assert_eq!(module.text_range(), 0x1000..0x1004);
+        assert_eq!(module.text(), [0x48, 0x8B, 0x05, 0xC3]);
+        assert_eq!(
+            module.section_range(SectionKind::Data),
+            Some(0x2000..0x2008)
+        );
+        assert_eq!(module.section(SectionKind::Rdata), None);
+        assert_eq!(module.read_pointer(0x2000), Some(0x1234 as *const u8));
+        assert_eq!(module.read_pointer(mapped.len() - 4), None);
+        assert_eq!(module.address(0x1000), mapped[0x1000..].as_ptr());
+        assert_eq!(module.pe().unwrap().timestamp, 0x6480_1234);
+
+        // a module without code
+        let file = sample_pe(true, &[(".data", 0x1000, &[0; 8], DATA)]);
+        let mapped = map_image(&file).unwrap();
+        assert_eq!(
+            ModuleImage::parse(&mapped),
+            Err(PeError::MissingSection(".text"))
+        );
+    }
+
+    #[test]
+    fn test_with_sections() {
+        let image = [0xCCu8; 0x40];
+        let module = ModuleImage::with_sections(&image, 0x10..0x20, None, Some(0x30..0x50));
+
+        assert_eq!(module.text().len(), 0x10);
+        assert_eq!(module.section_range(SectionKind::Rdata), Some(0x30..0x40));
+        assert_eq!(module.section(SectionKind::Data), None);
+        assert!(module.pe().is_err());
+    }
+}
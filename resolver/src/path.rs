@@ -0,0 +1,350 @@
+//! Pointer paths, for reaching data through a chain of pointers and fields
+//! starting from a static address, e.g. a singleton's field's field.
+
+use std::{fmt, ops::Range, str::FromStr};
+
+use crate::{
+    resolve::{self, SignatureKind},
+    scanner,
+    signature::{Signature, SignatureError},
+};
+
+/// Where a pointer path starts.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathBase {
+    /// The target of the RIP-relative operand at the signature's offset marker,
+    /// as with a static address signature.
+    Signature(Signature),
+    /// A fixed RVA into the module.
+    Rva(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathStep {
+    /// Adds a byte offset to the current address, e.g. to reach a field.
+    Offset(isize),
+    /// Reads the pointer at the current address, and continues from there.
+    Deref,
+}
+
+/// A base address followed by a chain of offsets and dereferences. The base only
+/// needs resolving once, but the steps can be evaluated whenever the pointers
+/// along the way may have changed.
+///
+/// Paths can be built in code, or parsed from text of the form
+/// `sig(48 8B 0D * ?? ?? ?? ??) * +0x10 * +0x28` or `rva(0x1D6B3E0) * -8`,
+/// where `*` is a dereference and `+n`/`-n` are offsets, in hex with `0x` or decimal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PointerPath {
+    pub base: PathBase,
+    pub steps: Vec<PathStep>,
+}
+
+/// Reasons a pointer path can fail to evaluate. Steps are indexed from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathError {
+    /// A dereference read a null pointer.
+    NullPointer { step: usize },
+    /// A dereference tried to read memory that isn't readable.
+    Unreadable { step: usize, address: usize },
+    /// An offset took the address out of range.
+    Overflow { step: usize },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathError::NullPointer { step } => write!(f, "null pointer at step {}", step),
+            PathError::Unreadable { step, address } => {
+                write!(f, "unreadable address 0x{:X} at step {}", address, step)
+            }
+            PathError::Overflow { step } => write!(f, "address overflow at step {}", step),
+        }
+    }
+}
+
+impl std::error::Error for PathError {}
+
+/// Reasons a pointer path can fail to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathParseError {
+    /// The path doesn't start with `sig(...)` or `rva(...)`.
+    InvalidBase(String),
+    /// The base signature is invalid.
+    Signature(SignatureError),
+    /// A step is neither `*` nor an offset.
+    InvalidStep(String),
+}
+
+impl fmt::Display for PathParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathParseError::InvalidBase(base) => write!(
+                f,
+                "invalid base {:?}: expected `sig(<signature>)` or `rva(<rva>)`",
+                base
+            ),
+            PathParseError::Signature(e) => write!(f, "invalid base signature: {}", e),
+            PathParseError::InvalidStep(step) => write!(
+                f,
+                "invalid step {:?}: expected `*` or an offset like `+0x10`",
+                step
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PathParseError {}
+
+impl PointerPath {
+    pub fn new(base: PathBase) -> Self {
+        Self {
+            base,
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn offset(mut self, offset: isize) -> Self {
+        self.steps.push(PathStep::Offset(offset));
+        self
+    }
+
+    pub fn deref(mut self) -> Self {
+        self.steps.push(PathStep::Deref);
+        self
+    }
+
+    /// Resolves the base of the path to an RVA in `image`, scanning `text` for
+    /// signatures. Returns `None` if the signature isn't found, or the base is
+    /// outside the image.
+    pub fn resolve_base(&self, image: &[u8], text: Range<usize>) -> Option<usize> {
+        match &self.base {
+            PathBase::Signature(sig) => {
+                let found = scanner::find(image.get(text.clone())?, &sig.pattern)?;
+                resolve::resolve_match(
+                    image,
+                    text.start + found,
+                    SignatureKind::StaticAddress,
+                    sig.offset as isize,
+                )
+            }
+            PathBase::Rva(rva) => (*rva < image.len()).then_some(*rva),
+        }
+    }
+
+    /// Follows the steps of the path from the resolved base address, using `read`
+    /// to read a pointer from memory. `read` returns `None` if the address can't
+    /// be read.
+    pub fn evaluate(
+        &self,
+        base: usize,
+        mut read: impl FnMut(usize) -> Option<usize>,
+    ) -> Result<usize, PathError> {
+        let mut address = base;
+
+        for (step, &kind) in self.steps.iter().enumerate() {
+            address = match kind {
+                PathStep::Offset(offset) => address
+                    .checked_add_signed(offset)
+                    .ok_or(PathError::Overflow { step })?,
+                PathStep::Deref => match read(address) {
+                    Some(0) => return Err(PathError::NullPointer { step }),
+                    Some(pointer) => pointer,
+                    None => return Err(PathError::Unreadable { step, address }),
+                },
+            };
+        }
+
+        Ok(address)
+    }
+}
+
+impl FromStr for PointerPath {
+    type Err = PathParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (base, rest) = s
+            .split_once(')')
+            .ok_or_else(|| PathParseError::InvalidBase(s.to_string()))?;
+
+        let base = if let Some(sig) = base.strip_prefix("sig(") {
+            PathBase::Signature(Signature::parse(sig).map_err(PathParseError::Signature)?)
+        } else if let Some(rva) = base.strip_prefix("rva(") {
+            let rva = parse_number(rva.trim())
+                .and_then(|rva| usize::try_from(rva).ok())
+                .ok_or_else(|| PathParseError::InvalidBase(format!("{})", base)))?;
+            PathBase::Rva(rva)
+        } else {
+            return Err(PathParseError::InvalidBase(format!("{})", base)));
+        };
+
+        let steps = rest
+            .split_whitespace()
+            .map(|step| match step {
+                "*" => Ok(PathStep::Deref),
+                offset => parse_number(offset)
+                    .filter(|_| offset.starts_with(['+', '-']))
+                    .map(PathStep::Offset)
+                    .ok_or_else(|| PathParseError::InvalidStep(step.to_string())),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { base, steps })
+    }
+}
+
+/// Formats the path so that it parses back to the same path.
+impl fmt::Display for PointerPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.base {
+            PathBase::Signature(sig) => write!(f, "sig({})", sig)?,
+            PathBase::Rva(rva) => write!(f, "rva(0x{:X})", rva)?,
+        }
+
+        for step in &self.steps {
+            match step {
+                PathStep::Offset(offset) if *offset < 0 => {
+                    write!(f, " -0x{:X}", offset.unsigned_abs())?
+                }
+                PathStep::Offset(offset) => write!(f, " +0x{:X}", offset)?,
+                PathStep::Deref => f.write_str(" *")?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses an optionally signed number, in hex with a `0x` prefix or decimal.
+fn parse_number(text: &str) -> Option<isize> {
+    let (negative, unsigned) = match text.as_bytes().first()? {
+        b'-' => (true, &text[1..]),
+        b'+' => (false, &text[1..]),
+        _ => (false, text),
+    };
+
+    let value = match unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix("0X"))
+    {
+        Some(hex) => isize::from_str_radix(hex, 16).ok()?,
+        None => unsigned.parse().ok()?,
+    };
+
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse() {
+        let path: PointerPath = "sig(48 8B 0D * ?? ?? ?? ??) * +0x10 * +40 -8"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            path,
+            PointerPath::new(PathBase::Signature(
+                Signature::parse("48 8B 0D * ?? ?? ?? ??").unwrap()
+            ))
+            .deref()
+            .offset(0x10)
+            .deref()
+            .offset(40)
+            .offset(-8)
+        );
+        assert_eq!(
+            path.to_string(),
+            "sig(48 8B 0D * ?? ?? ?? ??) * +0x10 * +0x28 -0x8"
+        );
+        assert_eq!(path.to_string().parse(), Ok(path));
+
+        let path: PointerPath = "rva(0x1D6B3E0)".parse().unwrap();
+        assert_eq!(path, PointerPath::new(PathBase::Rva(0x1D6B3E0)));
+
+        let errors = [
+            ("", PathParseError::InvalidBase("".to_string())),
+            (
+                "static(0x10) *",
+                PathParseError::InvalidBase("static(0x10)".to_string()),
+            ),
+            (
+                "rva(-1)",
+                PathParseError::InvalidBase("rva(-1)".to_string()),
+            ),
+            ("sig() *", PathParseError::Signature(SignatureError::Empty)),
+            (
+                "rva(0x10) * 0x10",
+                PathParseError::InvalidStep("0x10".to_string()),
+            ),
+            (
+                "rva(0x10) **",
+                PathParseError::InvalidStep("**".to_string()),
+            ),
+        ];
+        for (text, expected) in errors {
+            assert_eq!(
+                text.parse::<PointerPath>(),
+                Err(expected),
+                "parsing {:?}",
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        // a static pointer to an object, whose field at +0x10 points to another object
+        let memory = HashMap::from([(0x1000, 0x2000), (0x2010, 0x3000), (0x2018, 0)]);
+        let read = |address| memory.get(&address).copied();
+
+        let path = PointerPath::new(PathBase::Rva(0))
+            .deref()
+            .offset(0x10)
+            .deref()
+            .offset(0x28);
+        assert_eq!(path.evaluate(0x1000, read), Ok(0x3028));
+
+        // a null field, an unreadable address, and an offset below zero
+        let null = PointerPath::new(PathBase::Rva(0))
+            .deref()
+            .offset(0x18)
+            .deref();
+        assert_eq!(
+            null.evaluate(0x1000, read),
+            Err(PathError::NullPointer { step: 2 })
+        );
+        let unreadable = PointerPath::new(PathBase::Rva(0)).offset(8).deref();
+        assert_eq!(
+            unreadable.evaluate(0x1000, read),
+            Err(PathError::Unreadable {
+                step: 1,
+                address: 0x1008
+            })
+        );
+        let overflow = PointerPath::new(PathBase::Rva(0)).offset(-0x2000);
+        assert_eq!(
+            overflow.evaluate(0x1000, read),
+            Err(PathError::Overflow { step: 0 })
+        );
+    }
+
+    #[test]
+    fn test_resolve_base() {
+        // mov rcx, [rip + 0x100] at the start of .text
+        let mut image = vec![0xCC; 0x300];
+        image[0x100..0x107].copy_from_slice(&[0x48, 0x8B, 0x0D, 0x00, 0x01, 0x00, 0x00]);
+
+        let path: PointerPath = "sig(48 8B 0D * ?? ?? ?? ??) *".parse().unwrap();
+        assert_eq!(path.resolve_base(&image, 0x100..0x200), Some(0x207));
+        assert_eq!(path.resolve_base(&image, 0x110..0x200), None);
+
+        let path = PointerPath::new(PathBase::Rva(0x2FF));
+        assert_eq!(path.resolve_base(&image, 0x100..0x200), Some(0x2FF));
+        let path = PointerPath::new(PathBase::Rva(0x300));
+        assert_eq!(path.resolve_base(&image, 0x100..0x200), None);
+    }
+}
@@ -0,0 +1,1135 @@
+use std::fmt;
+
+use crate::symbol::Symbol;
+
+const DOS_MAGIC: &[u8; 2] = b"MZ";
+const NT_SIGNATURE: &[u8; 4] = b"PE\0\0";
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+
+/// The most `map_image` will allocate, far more than any game or system module needs.
+pub const MAX_MAPPED_SIZE: u64 = 1 << 30;
+
+pub const OPTIONAL_HEADER_MAGIC_PE32: u16 = 0x10B;
+pub const OPTIONAL_HEADER_MAGIC_PE32_PLUS: u16 = 0x20B;
+
+pub const DIRECTORY_EXPORT: usize = 0;
+pub const DIRECTORY_IMPORT: usize = 1;
+pub const DIRECTORY_RESOURCE: usize = 2;
+pub const DIRECTORY_EXCEPTION: usize = 3;
+pub const DIRECTORY_BASERELOC: usize = 5;
+pub const DIRECTORY_DEBUG: usize = 6;
+pub const DIRECTORY_TLS: usize = 9;
+pub const DIRECTORY_IAT: usize = 12;
+
+/// Base relocation types, in the top 4 bits of each relocation entry.
+pub const REL_BASED_ABSOLUTE: u16 = 0;
+pub const REL_BASED_HIGHLOW: u16 = 3;
+pub const REL_BASED_DIR64: u16 = 10;
+
+/// Set in an import lookup table entry when the symbol is imported by ordinal.
+const IMPORT_BY_ORDINAL_32: u64 = 1 << 31;
+const IMPORT_BY_ORDINAL_64: u64 = 1 << 63;
+
+pub const SCN_CNT_CODE: u32 = 0x0000_0020;
+pub const SCN_CNT_INITIALIZED_DATA: u32 = 0x0000_0040;
+pub const SCN_CNT_UNINITIALIZED_DATA: u32 = 0x0000_0080;
+pub const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const SCN_MEM_READ: u32 = 0x4000_0000;
+pub const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// How the image is laid out in the buffer it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Sections are at their virtual addresses, as loaded by the OS.
+    Mapped,
+    /// Sections are at their raw file offsets, as stored on disk.
+    File,
+}
+
+/// Reasons an image can fail to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeError {
+    /// A header extends past the end of the image.
+    Truncated { what: &'static str, offset: usize },
+    /// The image does not start with `MZ`.
+    BadDosMagic,
+    /// The NT headers do not start with `PE\0\0`.
+    BadNtSignature,
+    /// The optional header magic is neither PE32 nor PE32+.
+    UnknownOptionalHeader(u16),
+    /// A section that's required isn't in the image.
+    MissingSection(&'static str),
+    /// The image would take more than `MAX_MAPPED_SIZE` bytes to map.
+    TooLarge(u64),
+}
+
+impl fmt::Display for PeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeError::Truncated { what, offset } => {
+                write!(f, "image truncated reading {} at 0x{:X}", what, offset)
+            }
+            PeError::BadDosMagic => write!(f, "missing DOS header magic"),
+            PeError::BadNtSignature => write!(f, "missing NT headers signature"),
+            PeError::UnknownOptionalHeader(magic) => {
+                write!(f, "unknown optional header magic 0x{:X}", magic)
+            }
+            PeError::MissingSection(name) => write!(f, "image has no {} section", name),
+            PeError::TooLarge(size) => {
+                write!(f, "image is too large to map (0x{:X} bytes)", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PeError {}
+
+/// An entry in the optional header's data directory table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+/// A location the loader fixes up when the image isn't loaded at its preferred base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub rva: u32,
+    /// The number of bytes fixed up.
+    pub size: u8,
+}
+
+/// An entry in the exception directory, giving the extent of a function, or part
+/// of one, and its unwind information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeFunction {
+    pub begin: u32,
+    pub end: u32,
+    pub unwind_info: u32,
+}
+
+/// What an exported symbol refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+    /// Code or data in the image.
+    Rva(u32),
+    /// A symbol in another module, e.g. `NTDLL.RtlAllocateHeap`, which the loader
+    /// resolves in its place.
+    Forwarder(String),
+}
+
+/// An entry in the export directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+    pub ordinal: u32,
+    /// The name it's exported by, if it isn't exported by ordinal only.
+    pub name: Option<String>,
+    pub target: ExportTarget,
+}
+
+/// A symbol imported from another module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub module: String,
+    pub symbol: Symbol,
+    /// The slot in the import address table that the loader writes its address to.
+    pub iat_rva: u32,
+}
+
+/// A section header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub virtual_address: u32,
+    pub virtual_size: u32,
+    pub raw_offset: u32,
+    pub raw_size: u32,
+    pub characteristics: u32,
+}
+
+impl Section {
+    pub fn is_code(&self) -> bool {
+        self.characteristics & SCN_CNT_CODE != 0
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & SCN_MEM_EXECUTE != 0
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.characteristics & SCN_MEM_READ != 0
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.characteristics & SCN_MEM_WRITE != 0
+    }
+
+    /// Returns true if the RVA falls within the section once loaded.
+    pub fn contains_rva(&self, rva: u32) -> bool {
+        rva >= self.virtual_address && rva - self.virtual_address < self.mapped_size()
+    }
+
+    /// The size of the section once loaded, which is the virtual size unless
+    /// the linker left it as zero.
+    pub fn mapped_size(&self) -> u32 {
+        if self.virtual_size == 0 {
+            self.raw_size
+        } else {
+            self.virtual_size
+        }
+    }
+}
+
+/// A parsed PE image, borrowing the buffer it was parsed from.
+#[derive(Debug, Clone)]
+pub struct PeImage<'a> {
+    data: &'a [u8],
+    layout: Layout,
+    pub machine: u16,
+    pub timestamp: u32,
+    pub is_64: bool,
+    pub entry_point: u32,
+    pub image_base: u64,
+    pub section_alignment: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub checksum: u32,
+    pub data_directories: Vec<DataDirectory>,
+    pub sections: Vec<Section>,
+}
+
+impl<'a> PeImage<'a> {
+    /// Parses the headers of a PE image laid out as described by `layout`.
+    pub fn parse(data: &'a [u8], layout: Layout) -> Result<Self, PeError> {
+        let reader = Reader(data);
+
+        if reader.bytes(0, 2, "DOS header")? != DOS_MAGIC {
+            return Err(PeError::BadDosMagic);
+        }
+
+        let nt_offset = reader.u32(0x3C, "DOS header")? as usize;
+        if reader.bytes(nt_offset, 4, "NT headers")? != NT_SIGNATURE {
+            return Err(PeError::BadNtSignature);
+        }
+
+        // IMAGE_FILE_HEADER
+        let file_header = nt_offset + 4;
+        let machine = reader.u16(file_header, "file header")?;
+        let num_sections = reader.u16(file_header + 2, "file header")? as usize;
+        let timestamp = reader.u32(file_header + 4, "file header")?;
+        let optional_header_size = reader.u16(file_header + 16, "file header")? as usize;
+
+        // IMAGE_OPTIONAL_HEADER32/64, which differ in the size of a few fields
+        let optional_header = file_header + FILE_HEADER_SIZE;
+        let is_64 = match reader.u16(optional_header, "optional header")? {
+            OPTIONAL_HEADER_MAGIC_PE32 => false,
+            OPTIONAL_HEADER_MAGIC_PE32_PLUS => true,
+            magic => return Err(PeError::UnknownOptionalHeader(magic)),
+        };
+
+        let entry_point = reader.u32(optional_header + 16, "optional header")?;
+        let image_base = if is_64 {
+            reader.u64(optional_header + 24, "optional header")?
+        } else {
+            reader.u32(optional_header + 28, "optional header")? as u64
+        };
+        let section_alignment = reader.u32(optional_header + 32, "optional header")?;
+        let size_of_image = reader.u32(optional_header + 56, "optional header")?;
+        let size_of_headers = reader.u32(optional_header + 60, "optional header")?;
+        let checksum = reader.u32(optional_header + 64, "optional header")?;
+
+        let (num_directories_at, directories_at) = if is_64 { (108, 112) } else { (92, 96) };
+        let num_directories =
+            reader.u32(optional_header + num_directories_at, "optional header")?;
+
+        // the directory count can't be trusted beyond what fits in the optional header
+        let max_directories = optional_header_size.saturating_sub(directories_at) / 8;
+        let data_directories = (0..(num_directories as usize).min(max_directories))
+            .map(|i| {
+                let at = optional_header + directories_at + i * 8;
+                Ok(DataDirectory {
+                    virtual_address: reader.u32(at, "data directory")?,
+                    size: reader.u32(at + 4, "data directory")?,
+                })
+            })
+            .collect::<Result<_, PeError>>()?;
+
+        // IMAGE_SECTION_HEADER
+        let section_headers = optional_header + optional_header_size;
+        let sections = (0..num_sections)
+            .map(|i| {
+                let at = section_headers + i * SECTION_HEADER_SIZE;
+                let name = reader.bytes(at, 8, "section header")?;
+                let name_len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+
+                Ok(Section {
+                    name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                    virtual_size: reader.u32(at + 8, "section header")?,
+                    virtual_address: reader.u32(at + 12, "section header")?,
+                    raw_size: reader.u32(at + 16, "section header")?,
+                    raw_offset: reader.u32(at + 20, "section header")?,
+                    characteristics: reader.u32(at + 36, "section header")?,
+                })
+            })
+            .collect::<Result<_, PeError>>()?;
+
+        Ok(Self {
+            data,
+            layout,
+            machine,
+            timestamp,
+            is_64,
+            entry_point,
+            image_base,
+            section_alignment,
+            size_of_image,
+            size_of_headers,
+            checksum,
+            data_directories,
+            sections,
+        })
+    }
+
+    /// The buffer the image was parsed from.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Finds the first section with the given name.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Finds the section containing the given RVA.
+    pub fn section_for_rva(&self, rva: u32) -> Option<&Section> {
+        self.sections.iter().find(|s| s.contains_rva(rva))
+    }
+
+    /// Returns a data directory, if it is present and non-empty.
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        self.data_directories
+            .get(index)
+            .copied()
+            .filter(|d| d.virtual_address != 0 && d.size != 0)
+    }
+
+    /// Returns the contents of a section, clamped to what is present in the buffer.
+    /// In a file image, any uninitialized tail of the section is not included.
+    pub fn section_data(&self, section: &Section) -> &'a [u8] {
+        let (start, len) = match self.layout {
+            Layout::Mapped => (section.virtual_address, section.mapped_size()),
+            Layout::File => (
+                section.raw_offset,
+                section.raw_size.min(section.mapped_size()),
+            ),
+        };
+
+        let start = (start as usize).min(self.data.len());
+        let end = start.saturating_add(len as usize).min(self.data.len());
+        &self.data[start..end]
+    }
+
+    /// Converts an RVA to an offset into the buffer.
+    pub fn rva_to_offset(&self, rva: u32) -> Option<usize> {
+        let offset = match self.layout {
+            Layout::Mapped => rva as usize,
+            // headers are stored at the start of the file, as they are when mapped
+            Layout::File if rva < self.size_of_headers => rva as usize,
+            Layout::File => {
+                let section = self.section_for_rva(rva)?;
+                let delta = rva - section.virtual_address;
+                if delta >= section.raw_size {
+                    return None;
+                }
+
+                section.raw_offset.checked_add(delta)? as usize
+            }
+        };
+
+        (offset < self.data.len()).then_some(offset)
+    }
+
+    /// Converts an offset into the buffer to an RVA.
+    pub fn offset_to_rva(&self, offset: usize) -> Option<u32> {
+        let offset = u32::try_from(offset).ok()?;
+        match self.layout {
+            Layout::Mapped => Some(offset),
+            Layout::File if offset < self.size_of_headers => Some(offset),
+            Layout::File => self
+                .sections
+                .iter()
+                .find(|s| offset >= s.raw_offset && offset - s.raw_offset < s.raw_size)
+                .and_then(|s| s.virtual_address.checked_add(offset - s.raw_offset)),
+        }
+    }
+
+    /// Reads `len` bytes starting at an RVA.
+    pub fn read_rva(&self, rva: u32, len: usize) -> Option<&'a [u8]> {
+        let offset = self.rva_to_offset(rva)?;
+        self.data.get(offset..offset.checked_add(len)?)
+    }
+
+    /// Returns the locations fixed up by base relocations, sorted by RVA.
+    pub fn relocations(&self) -> Result<Vec<Relocation>, PeError> {
+        let Some(directory) = self.data_directory(DIRECTORY_BASERELOC) else {
+            return Ok(Vec::new());
+        };
+
+        let start = self
+            .rva_to_offset(directory.virtual_address)
+            .ok_or(PeError::Truncated {
+                what: "relocation block",
+                offset: directory.virtual_address as usize,
+            })?;
+        let end = start.saturating_add(directory.size as usize);
+        let reader = Reader(self.data);
+
+        // each block covers a page, with a 4-bit type and 12-bit page offset per entry
+        let mut relocations = Vec::new();
+        let mut block = start;
+        while block + 8 <= end {
+            let page = reader.u32(block, "relocation block")?;
+            let block_size = reader.u32(block + 4, "relocation block")? as usize;
+            if block_size < 8 {
+                // malformed, and we'd never get past it
+                break;
+            }
+
+            let block_end = block.saturating_add(block_size).min(end);
+            let mut entry = block + 8;
+            while entry + 2 <= block_end {
+                let value = reader.u16(entry, "relocation entry")?;
+                entry += 2;
+
+                let size = match value >> 12 {
+                    REL_BASED_HIGHLOW => 4,
+                    REL_BASED_DIR64 => 8,
+                    // padding, and types that don't occur in x86 images
+                    _ => continue,
+                };
+                relocations.push(Relocation {
+                    rva: page.wrapping_add((value & 0xFFF) as u32),
+                    size,
+                });
+            }
+
+            block = block_end;
+        }
+
+        relocations.sort_unstable_by_key(|r| r.rva);
+        Ok(relocations)
+    }
+
+    /// Returns the entries of the exception directory, sorted by start RVA.
+    pub fn runtime_functions(&self) -> Result<Vec<RuntimeFunction>, PeError> {
+        let Some(directory) = self.data_directory(DIRECTORY_EXCEPTION) else {
+            return Ok(Vec::new());
+        };
+
+        let offset = directory.virtual_address as usize;
+        let entries = self
+            .read_rva(directory.virtual_address, directory.size as usize)
+            .ok_or(PeError::Truncated {
+                what: "exception directory",
+                offset,
+            })?;
+
+        let reader = Reader(entries);
+        let mut functions = (0..entries.len() / 12)
+            .map(|i| {
+                Ok(RuntimeFunction {
+                    begin: reader.u32(i * 12, "runtime function")?,
+                    end: reader.u32(i * 12 + 4, "runtime function")?,
+                    unwind_info: reader.u32(i * 12 + 8, "runtime function")?,
+                })
+            })
+            .collect::<Result<Vec<_>, PeError>>()?;
+
+        functions.sort_unstable_by_key(|f| f.begin);
+        Ok(functions)
+    }
+
+    /// Returns the entries of the export directory, in ordinal order.
+    pub fn exports(&self) -> Result<Vec<Export>, PeError> {
+        let Some(directory) = self.data_directory(DIRECTORY_EXPORT) else {
+            return Ok(Vec::new());
+        };
+
+        let header = self.read_table(directory.virtual_address, 40, "export directory")?;
+        let base = header.u32(16, "export directory")?;
+        let function_count = header.u32(20, "export directory")? as usize;
+        let name_count = header.u32(24, "export directory")? as usize;
+        let functions = self.read_table(
+            header.u32(28, "export directory")?,
+            function_count.saturating_mul(4),
+            "export address table",
+        )?;
+        let names = self.read_table(
+            header.u32(32, "export directory")?,
+            name_count.saturating_mul(4),
+            "export name table",
+        )?;
+        let ordinals = self.read_table(
+            header.u32(36, "export directory")?,
+            name_count.saturating_mul(2),
+            "export ordinal table",
+        )?;
+
+        // names are sorted for binary search, and map to the function they export
+        let mut function_names = vec![None; function_count];
+        for i in 0..name_count {
+            let name = self.read_c_string(names.u32(i * 4, "export name table")?, "export name")?;
+            let index = ordinals.u16(i * 2, "export ordinal table")? as usize;
+            if let Some(slot) = function_names.get_mut(index) {
+                *slot = Some(name);
+            }
+        }
+
+        let directory_end = directory.virtual_address.saturating_add(directory.size);
+        let mut exports = Vec::new();
+        for (i, name) in function_names.into_iter().enumerate() {
+            let rva = functions.u32(i * 4, "export address table")?;
+            if rva == 0 {
+                // gaps between ordinals
+                continue;
+            }
+
+            // forwarders are strings stored within the export directory itself
+            let target = if (directory.virtual_address..directory_end).contains(&rva) {
+                ExportTarget::Forwarder(self.read_c_string(rva, "export forwarder")?)
+            } else {
+                ExportTarget::Rva(rva)
+            };
+
+            exports.push(Export {
+                ordinal: base.wrapping_add(i as u32),
+                name,
+                target,
+            });
+        }
+
+        Ok(exports)
+    }
+
+    /// Returns every symbol imported through the import directory, in table order.
+    pub fn imports(&self) -> Result<Vec<Import>, PeError> {
+        let Some(directory) = self.data_directory(DIRECTORY_IMPORT) else {
+            return Ok(Vec::new());
+        };
+
+        let (entry_size, by_ordinal) = if self.is_64 {
+            (8, IMPORT_BY_ORDINAL_64)
+        } else {
+            (4, IMPORT_BY_ORDINAL_32)
+        };
+
+        let mut imports = Vec::new();
+        let mut descriptor_rva = directory.virtual_address;
+        loop {
+            let descriptor = self.read_table(descriptor_rva, 20, "import descriptor")?;
+            let lookup_table = descriptor.u32(0, "import descriptor")?;
+            let module_rva = descriptor.u32(12, "import descriptor")?;
+            let iat = descriptor.u32(16, "import descriptor")?;
+            if module_rva == 0 || iat == 0 {
+                // the table ends with an empty descriptor
+                break;
+            }
+
+            let module = self.read_c_string(module_rva, "import module name")?;
+
+            // the IAT is overwritten with addresses once loaded, but the lookup table
+            // isn't, so use it unless the linker left it out
+            let table = if lookup_table != 0 { lookup_table } else { iat };
+            for i in 0u32.. {
+                // a table running past the end of the address space is as truncated
+                // as one running past the end of the image
+                let rvas = i.checked_mul(entry_size).and_then(|offset| {
+                    Some((table.checked_add(offset)?, iat.checked_add(offset)?))
+                });
+                let Some((entry_rva, iat_rva)) = rvas else {
+                    return Err(PeError::Truncated {
+                        what: "import entry",
+                        offset: table as usize,
+                    });
+                };
+
+                let entry = self.read_table(entry_rva, entry_size as usize, "import entry")?;
+                let value = if self.is_64 {
+                    entry.u64(0, "import entry")?
+                } else {
+                    entry.u32(0, "import entry")? as u64
+                };
+                if value == 0 {
+                    break;
+                }
+
+                let symbol = if value & by_ordinal != 0 {
+                    Symbol::Ordinal((value & 0xFFFF) as u32)
+                } else {
+                    // skip the hint preceding the name
+                    let name = self.read_c_string((value as u32).wrapping_add(2), "import name")?;
+                    Symbol::Name(name)
+                };
+
+                imports.push(Import {
+                    module: module.clone(),
+                    symbol,
+                    iat_rva,
+                });
+            }
+
+            descriptor_rva = descriptor_rva.checked_add(20).ok_or(PeError::Truncated {
+                what: "import descriptor",
+                offset: descriptor_rva as usize,
+            })?;
+        }
+
+        Ok(imports)
+    }
+
+    /// Reads `len` bytes starting at an RVA, for reading fields from.
+    fn read_table(&self, rva: u32, len: usize, what: &'static str) -> Result<Reader<'a>, PeError> {
+        self.read_rva(rva, len)
+            .map(Reader)
+            .ok_or(PeError::Truncated {
+                what,
+                offset: rva as usize,
+            })
+    }
+
+    /// Reads a null-terminated string starting at an RVA.
+    fn read_c_string(&self, rva: u32, what: &'static str) -> Result<String, PeError> {
+        let truncated = PeError::Truncated {
+            what,
+            offset: rva as usize,
+        };
+        let data = self.rva_to_offset(rva).map(|offset| &self.data[offset..]);
+        let data = data.ok_or(truncated.clone())?;
+        let len = memchr::memchr(0, data).ok_or(truncated)?;
+
+        Ok(String::from_utf8_lossy(&data[..len]).into_owned())
+    }
+}
+
+/// Lays out an image read from disk the way the loader would, with the headers
+/// and each section at their RVAs, so that it can be parsed as `Layout::Mapped`.
+/// Relocations and imports are not applied.
+pub fn map_image(file: &[u8]) -> Result<Vec<u8>, PeError> {
+    let pe = PeImage::parse(file, Layout::File)?;
+
+    // the headers can claim any size, so only trust it as far as the sections reach,
+    // rounded up to the section alignment the way the loader would
+    let extent = pe
+        .sections
+        .iter()
+        .map(|s| s.virtual_address as u64 + s.mapped_size() as u64)
+        .fold(pe.size_of_headers as u64, u64::max);
+    let alignment = (pe.section_alignment as u64).max(1);
+    let size = (pe.size_of_image as u64).min((extent + alignment - 1) / alignment * alignment);
+    if size > MAX_MAPPED_SIZE {
+        return Err(PeError::TooLarge(size));
+    }
+
+    let mut mapped = vec![0u8; size as usize];
+
+    let headers = (pe.size_of_headers as usize)
+        .min(file.len())
+        .min(mapped.len());
+    mapped[..headers].copy_from_slice(&file[..headers]);
+
+    for section in &pe.sections {
+        let data = pe.section_data(section);
+        let start = (section.virtual_address as usize).min(mapped.len());
+        let len = data.len().min(mapped.len() - start);
+        mapped[start..start + len].copy_from_slice(&data[..len]);
+    }
+
+    Ok(mapped)
+}
+
+/// Bounds-checked little-endian reads.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, len: usize, what: &'static str) -> Result<&'a [u8], PeError> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.0.get(offset..end))
+            .ok_or(PeError::Truncated { what, offset })
+    }
+
+    fn u16(&self, offset: usize, what: &'static str) -> Result<u16, PeError> {
+        Ok(u16::from_le_bytes(
+            self.bytes(offset, 2, what)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: usize, what: &'static str) -> Result<u32, PeError> {
+        Ok(u32::from_le_bytes(
+            self.bytes(offset, 4, what)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u64(&self, offset: usize, what: &'static str) -> Result<u64, PeError> {
+        Ok(u64::from_le_bytes(
+            self.bytes(offset, 8, what)?.try_into().unwrap(),
+        ))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A section to include in a sample image: (name, rva, contents, characteristics).
+    pub(crate) type SampleSection<'a> = (&'a str, u32, &'a [u8], u32);
+
+    pub(crate) const TEXT: u32 = SCN_CNT_CODE | SCN_MEM_EXECUTE | SCN_MEM_READ;
+    pub(crate) const RDATA: u32 = SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ;
+    pub(crate) const DATA: u32 = SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ | SCN_MEM_WRITE;
+
+    const FILE_ALIGNMENT: u32 = 0x200;
+    const HEADERS_SIZE: u32 = 0x400;
+
+    fn align(value: u32, to: u32) -> u32 {
+        (value + to - 1) / to * to
+    }
+
+    fn put(buf: &mut [u8], at: usize, bytes: &[u8]) {
+        buf[at..at + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Builds a minimal PE image, laid out as it would be on disk.
+    pub(crate) fn sample_pe(is_64: bool, sections: &[SampleSection]) -> Vec<u8> {
+        let mut file = vec![0u8; HEADERS_SIZE as usize];
+        put(&mut file, 0, DOS_MAGIC);
+        put(&mut file, 0x3C, &0x80u32.to_le_bytes());
+        put(&mut file, 0x80, NT_SIGNATURE);
+
+        let file_header = 0x84;
+        let optional_header_size: u16 = if is_64 { 240 } else { 224 };
+        put(&mut file, file_header, &0x8664u16.to_le_bytes());
+        put(
+            &mut file,
+            file_header + 2,
+            &(sections.len() as u16).to_le_bytes(),
+        );
+        put(&mut file, file_header + 4, &0x6480_1234u32.to_le_bytes());
+        put(
+            &mut file,
+            file_header + 16,
+            &optional_header_size.to_le_bytes(),
+        );
+
+        let size_of_image = sections
+            .iter()
+            .map(|(_, rva, data, _)| align(rva + data.len() as u32, 0x1000))
+            .max()
+            .unwrap_or(0x1000);
+
+        let optional_header = file_header + FILE_HEADER_SIZE;
+        let (magic, directories_at) = if is_64 {
+            (OPTIONAL_HEADER_MAGIC_PE32_PLUS, 112)
+        } else {
+            (OPTIONAL_HEADER_MAGIC_PE32, 96)
+        };
+        put(&mut file, optional_header, &magic.to_le_bytes());
+        put(&mut file, optional_header + 16, &0x1010u32.to_le_bytes());
+        if is_64 {
+            put(
+                &mut file,
+                optional_header + 24,
+                &0x1_4000_0000u64.to_le_bytes(),
+            );
+        } else {
+            put(&mut file, optional_header + 28, &0x40_0000u32.to_le_bytes());
+        }
+        put(&mut file, optional_header + 32, &0x1000u32.to_le_bytes());
+        put(
+            &mut file,
+            optional_header + 56,
+            &size_of_image.to_le_bytes(),
+        );
+        put(&mut file, optional_header + 60, &HEADERS_SIZE.to_le_bytes());
+        put(&mut file, optional_header + 64, &0xABCDu32.to_le_bytes());
+        put(
+            &mut file,
+            optional_header + directories_at - 4,
+            &16u32.to_le_bytes(),
+        );
+
+        // point the import directory somewhere, so there's a directory to find
+        let import_directory = optional_header + directories_at + DIRECTORY_IMPORT * 8;
+        put(&mut file, import_directory, &0x2000u32.to_le_bytes());
+        put(&mut file, import_directory + 4, &0x28u32.to_le_bytes());
+
+        let mut section_header = optional_header + optional_header_size as usize;
+        for &(name, rva, data, characteristics) in sections {
+            let raw_offset = file.len() as u32;
+            let raw_size = align(data.len() as u32, FILE_ALIGNMENT);
+            file.extend_from_slice(data);
+            file.resize((raw_offset + raw_size) as usize, 0);
+
+            put(&mut file, section_header, name.as_bytes());
+            put(
+                &mut file,
+                section_header + 8,
+                &(data.len() as u32).to_le_bytes(),
+            );
+            put(&mut file, section_header + 12, &rva.to_le_bytes());
+            put(&mut file, section_header + 16, &raw_size.to_le_bytes());
+            put(&mut file, section_header + 20, &raw_offset.to_le_bytes());
+            put(
+                &mut file,
+                section_header + 36,
+                &characteristics.to_le_bytes(),
+            );
+            section_header += SECTION_HEADER_SIZE;
+        }
+
+        file
+    }
+
+    fn sample_sections() -> Vec<SampleSection<'static>> {
+        vec![
+            (".text", 0x1000, &[0x48, 0x8B, 0x05, 0xC3], TEXT),
+            (".rdata", 0x2000, b"hello\0", RDATA),
+            (".data", 0x3000, &[1, 2, 3, 4, 5, 6, 7, 8], DATA),
+            (".pdata", 0x4000, &[0xAA; 12], RDATA),
+        ]
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        for is_64 in [true, false] {
+            let file = sample_pe(is_64, &sample_sections());
+            let pe = PeImage::parse(&file, Layout::File).unwrap();
+
+            assert_eq!(pe.is_64, is_64);
+            assert_eq!(pe.machine, 0x8664);
+            assert_eq!(pe.timestamp, 0x6480_1234);
+            assert_eq!(pe.checksum, 0xABCD);
+            assert_eq!(pe.entry_point, 0x1010);
+            assert_eq!(pe.size_of_image, 0x5000);
+            assert_eq!(pe.section_alignment, 0x1000);
+            assert_eq!(pe.image_base, if is_64 { 0x1_4000_0000 } else { 0x40_0000 });
+
+            assert_eq!(pe.data_directories.len(), 16);
+            assert_eq!(
+                pe.data_directory(DIRECTORY_IMPORT),
+                Some(DataDirectory {
+                    virtual_address: 0x2000,
+                    size: 0x28
+                })
+            );
+            assert_eq!(pe.data_directory(DIRECTORY_EXPORT), None);
+        }
+    }
+
+    #[test]
+    fn test_sections() {
+        let file = sample_pe(true, &sample_sections());
+        let pe = PeImage::parse(&file, Layout::File).unwrap();
+
+        let names: Vec<_> = pe.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, [".text", ".rdata", ".data", ".pdata"]);
+
+        let text = pe.section(".text").unwrap();
+        assert!(text.is_code() && text.is_executable() && text.is_readable());
+        assert!(!text.is_writable());
+        assert!(pe.section(".data").unwrap().is_writable());
+        assert!(pe.section(".reloc").is_none());
+
+        assert_eq!(pe.section_for_rva(0x2003).unwrap().name, ".rdata");
+        assert!(pe.section_for_rva(0x2006).is_none());
+    }
+
+    #[test]
+    fn test_layouts_agree() {
+        let file = sample_pe(true, &sample_sections());
+        let mapped = map_image(&file).unwrap();
+
+        let on_disk = PeImage::parse(&file, Layout::File).unwrap();
+        let in_memory = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+
+        for (a, b) in on_disk.sections.iter().zip(&in_memory.sections) {
+            assert_eq!(on_disk.section_data(a), in_memory.section_data(b));
+        }
+
+        assert_eq!(on_disk.read_rva(0x2000, 5), Some(&b"hello"[..]));
+        assert_eq!(in_memory.read_rva(0x2000, 5), Some(&b"hello"[..]));
+        assert_eq!(on_disk.read_rva(0x3004, 4), in_memory.read_rva(0x3004, 4));
+
+        // round trips through file offsets
+        let offset = on_disk.rva_to_offset(0x3004).unwrap();
+        assert_eq!(file[offset], 5);
+        assert_eq!(on_disk.offset_to_rva(offset), Some(0x3004));
+
+        // past the end of the section's raw data, but within the mapped image
+        assert_eq!(on_disk.rva_to_offset(0x4800), None);
+        assert_eq!(in_memory.rva_to_offset(0x4800), Some(0x4800));
+    }
+
+    #[test]
+    fn test_relocations() {
+        // one block for the .text page: two DIR64s out of order, a HIGHLOW, and padding
+        let mut block = Vec::new();
+        for value in [0x1000u32, 16] {
+            block.extend_from_slice(&value.to_le_bytes());
+        }
+        for entry in [0xA008u16, 0x3010, 0xA000, 0x0000] {
+            block.extend_from_slice(&entry.to_le_bytes());
+        }
+
+        let mut sections = sample_sections();
+        sections.push((".reloc", 0x5000, &block, RDATA));
+        let mut file = sample_pe(true, &sections);
+        put(&mut file, 0x130, &0x5000u32.to_le_bytes());
+        put(&mut file, 0x134, &(block.len() as u32).to_le_bytes());
+        let mapped = map_image(&file).unwrap();
+
+        let expected = [
+            Relocation {
+                rva: 0x1000,
+                size: 8,
+            },
+            Relocation {
+                rva: 0x1008,
+                size: 8,
+            },
+            Relocation {
+                rva: 0x1010,
+                size: 4,
+            },
+        ];
+        let on_disk = PeImage::parse(&file, Layout::File).unwrap();
+        let in_memory = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+        assert_eq!(on_disk.relocations().unwrap(), expected);
+        assert_eq!(in_memory.relocations().unwrap(), expected);
+
+        // no relocation directory at all
+        let file = sample_pe(true, &sample_sections());
+        let pe = PeImage::parse(&file, Layout::File).unwrap();
+        assert_eq!(pe.relocations().unwrap(), []);
+    }
+
+    #[test]
+    fn test_exports() {
+        // ordinals 1-4: a function, a gap, a forwarder, and a function without a name
+        let mut edata = vec![0u8; 0xB0];
+        let directory: [u32; 10] = [0, 0, 0, 0x5060, 1, 4, 2, 0x5028, 0x5038, 0x5040];
+        for (i, value) in directory.into_iter().enumerate() {
+            put(&mut edata, i * 4, &value.to_le_bytes());
+        }
+        for (i, rva) in [0x1000u32, 0, 0x5070, 0x1002].into_iter().enumerate() {
+            put(&mut edata, 0x28 + i * 4, &rva.to_le_bytes());
+        }
+        for (i, rva) in [0x5090u32, 0x5098].into_iter().enumerate() {
+            put(&mut edata, 0x38 + i * 4, &rva.to_le_bytes());
+        }
+        for (i, index) in [0u16, 2].into_iter().enumerate() {
+            put(&mut edata, 0x40 + i * 2, &index.to_le_bytes());
+        }
+        put(&mut edata, 0x60, b"sample.dll\0");
+        put(&mut edata, 0x70, b"NTDLL.RtlAllocateHeap\0");
+        put(&mut edata, 0x90, b"Alpha\0");
+        put(&mut edata, 0x98, b"Forwarded\0");
+
+        let mut sections = sample_sections();
+        sections.push((".edata", 0x5000, &edata, RDATA));
+        let mut file = sample_pe(true, &sections);
+        put(&mut file, 0x108, &0x5000u32.to_le_bytes());
+        put(&mut file, 0x10C, &(edata.len() as u32).to_le_bytes());
+        let mapped = map_image(&file).unwrap();
+
+        let expected = [
+            Export {
+                ordinal: 1,
+                name: Some("Alpha".to_string()),
+                target: ExportTarget::Rva(0x1000),
+            },
+            Export {
+                ordinal: 3,
+                name: Some("Forwarded".to_string()),
+                target: ExportTarget::Forwarder("NTDLL.RtlAllocateHeap".to_string()),
+            },
+            Export {
+                ordinal: 4,
+                name: None,
+                target: ExportTarget::Rva(0x1002),
+            },
+        ];
+        let on_disk = PeImage::parse(&file, Layout::File).unwrap();
+        let in_memory = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+        assert_eq!(on_disk.exports().unwrap(), expected);
+        assert_eq!(in_memory.exports().unwrap(), expected);
+
+        // no export directory at all
+        let file = sample_pe(true, &sample_sections());
+        let pe = PeImage::parse(&file, Layout::File).unwrap();
+        assert_eq!(pe.exports().unwrap(), []);
+    }
+
+    #[test]
+    fn test_imports() {
+        // KERNEL32.dll with a lookup table, and dxgi.dll with only an IAT
+        let mut idata = vec![0u8; 0xF0];
+        for (i, value) in [0x6040u32, 0, 0, 0x60A0, 0x6080].into_iter().enumerate() {
+            put(&mut idata, i * 4, &value.to_le_bytes());
+        }
+        for (i, value) in [0u32, 0, 0, 0x60B0, 0x6070].into_iter().enumerate() {
+            put(&mut idata, 0x14 + i * 4, &value.to_le_bytes());
+        }
+        for (i, entry) in [0x60C0u64, IMPORT_BY_ORDINAL_64 | 5]
+            .into_iter()
+            .enumerate()
+        {
+            put(&mut idata, 0x40 + i * 8, &entry.to_le_bytes());
+            put(&mut idata, 0x80 + i * 8, &entry.to_le_bytes());
+        }
+        put(&mut idata, 0x70, &0x60D0u64.to_le_bytes());
+        put(&mut idata, 0xA0, b"KERNEL32.dll\0");
+        put(&mut idata, 0xB0, b"dxgi.dll\0");
+        put(&mut idata, 0xC2, b"Sleep\0");
+        put(&mut idata, 0xD2, b"CreateDXGIFactory1\0");
+
+        let mut sections = sample_sections();
+        sections.push((".idata", 0x6000, &idata, DATA));
+        let mut file = sample_pe(true, &sections);
+        put(&mut file, 0x110, &0x6000u32.to_le_bytes());
+        put(&mut file, 0x114, &0x3Cu32.to_le_bytes());
+        let mapped = map_image(&file).unwrap();
+
+        let import = |module: &str, symbol: Symbol, iat_rva: u32| Import {
+            module: module.to_string(),
+            symbol,
+            iat_rva,
+        };
+        let expected = [
+            import("KERNEL32.dll", Symbol::Name("Sleep".to_string()), 0x6080),
+            import("KERNEL32.dll", Symbol::Ordinal(5), 0x6088),
+            import(
+                "dxgi.dll",
+                Symbol::Name("CreateDXGIFactory1".to_string()),
+                0x6070,
+            ),
+        ];
+        let on_disk = PeImage::parse(&file, Layout::File).unwrap();
+        let in_memory = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+        assert_eq!(on_disk.imports().unwrap(), expected);
+        assert_eq!(in_memory.imports().unwrap(), expected);
+
+        // an IAT at the very end of the address space
+        let descriptor = on_disk.section(".idata").unwrap().raw_offset as usize;
+        put(&mut file, descriptor + 16, &0xFFFF_FFFCu32.to_le_bytes());
+        let pe = PeImage::parse(&file, Layout::File).unwrap();
+        assert!(matches!(
+            pe.imports(),
+            Err(PeError::Truncated {
+                what: "import entry",
+                ..
+            })
+        ));
+
+        // the sample's import directory points at a string followed by nothing
+        let file = sample_pe(true, &sample_sections());
+        let pe = PeImage::parse(&file, Layout::File).unwrap();
+        assert_eq!(pe.imports().unwrap(), []);
+    }
+
+    #[test]
+    fn test_overflowing_sections() {
+        // the .text section header, after the 64-bit optional header
+        let text_header = 0x188;
+        let file = sample_pe(true, &sample_sections());
+
+        // raw data at the end of the address space
+        let mut bad = file.clone();
+        put(&mut bad, text_header + 8, &0x200u32.to_le_bytes());
+        put(&mut bad, text_header + 20, &0xFFFF_FF00u32.to_le_bytes());
+        let pe = PeImage::parse(&bad, Layout::File).unwrap();
+        assert_eq!(pe.rva_to_offset(0x1100), None);
+
+        // mapped at the end of the address space
+        let mut bad = file;
+        put(&mut bad, text_header + 12, &0xFFFF_FF00u32.to_le_bytes());
+        put(&mut bad, text_header + 8, &0x200u32.to_le_bytes());
+        let pe = PeImage::parse(&bad, Layout::File).unwrap();
+        let text = pe.section(".text").unwrap().raw_offset as usize;
+        assert_eq!(pe.offset_to_rva(text + 0xFF), Some(0xFFFF_FFFF));
+        assert_eq!(pe.offset_to_rva(text + 0x100), None);
+    }
+
+    #[test]
+    fn test_map_image_size() {
+        // the image claims to be far larger than its sections
+        let mut file = sample_pe(true, &sample_sections());
+        put(&mut file, 0x98 + 56, &u32::MAX.to_le_bytes());
+        assert_eq!(map_image(&file).unwrap().len(), 0x5000);
+
+        // a section that really is too far out to map
+        let mut sections = sample_sections();
+        sections.push((".far", 0xF000_0000, &[0xCC; 4], DATA));
+        let file = sample_pe(true, &sections);
+        assert_eq!(
+            map_image(&file).unwrap_err(),
+            PeError::TooLarge(0xF000_1000)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let file = sample_pe(true, &sample_sections());
+
+        assert_eq!(
+            PeImage::parse(&[], Layout::File).unwrap_err(),
+            PeError::Truncated {
+                what: "DOS header",
+                offset: 0
+            }
+        );
+
+        let mut bad = file.clone();
+        bad[0] = b'X';
+        assert_eq!(
+            PeImage::parse(&bad, Layout::File).unwrap_err(),
+            PeError::BadDosMagic
+        );
+
+        let mut bad = file.clone();
+        bad[0x80] = b'X';
+        assert_eq!(
+            PeImage::parse(&bad, Layout::File).unwrap_err(),
+            PeError::BadNtSignature
+        );
+
+        let mut bad = file.clone();
+        put(&mut bad, 0x98, &0x107u16.to_le_bytes());
+        assert_eq!(
+            PeImage::parse(&bad, Layout::File).unwrap_err(),
+            PeError::UnknownOptionalHeader(0x107)
+        );
+
+        // cut off in the middle of the section headers
+        assert!(matches!(
+            PeImage::parse(&file[..0x1A0], Layout::File),
+            Err(PeError::Truncated {
+                what: "section header",
+                ..
+            })
+        ));
+
+        // the NT header offset points past the end of the image
+        let mut bad = file;
+        put(&mut bad, 0x3C, &u32::MAX.to_le_bytes());
+        assert!(matches!(
+            PeImage::parse(&bad, Layout::File),
+            Err(PeError::Truncated { .. })
+        ));
+    }
+}
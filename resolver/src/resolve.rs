@@ -0,0 +1,201 @@
+//! Turns signature matches into addresses, shared by the in-process resolvers
+//! and offline tools. All positions are RVAs into a mapped image.
+
+use std::fmt;
+
+use iced_x86::{Decoder, DecoderOptions, Instruction, Mnemonic, OpKind};
+
+/// The longest an x86-64 instruction can be.
+const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// The kinds of signatures we resolve, each interpreting its match differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureKind {
+    /// A RIP-relative reference to a vtable.
+    VTable,
+    /// A RIP-relative reference to a static variable.
+    StaticAddress,
+    /// The start of a function, or a call or jump to it.
+    MemberFunction,
+}
+
+impl fmt::Display for SignatureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            SignatureKind::VTable => "vtable",
+            SignatureKind::StaticAddress => "static address",
+            SignatureKind::MemberFunction => "member function",
+        })
+    }
+}
+
+/// Resolves a signature match at `rva` to the address it identifies. Matches on a
+/// relative call or jump are followed first. Member functions resolve to the
+/// function itself, and vtables and static addresses to the target of the
+/// RIP-relative operand `offset` bytes past the (followed) match.
+pub fn resolve_match(
+    image: &[u8],
+    rva: usize,
+    kind: SignatureKind,
+    offset: isize,
+) -> Option<usize> {
+    let rva = follow_branch(image, rva)?;
+
+    match kind {
+        SignatureKind::MemberFunction => Some(rva),
+        SignatureKind::VTable | SignatureKind::StaticAddress => relative_target(image, rva, offset),
+    }
+}
+
+/// If there is a `call rel32` or `jmp rel32` at `rva`, returns its target.
+/// Otherwise returns `rva` itself, as the match is the function. Short jumps
+/// aren't followed, as they stay within a function rather than leading to one.
+pub fn follow_branch(image: &[u8], rva: usize) -> Option<usize> {
+    let instruction = decode(image, rva)?;
+    let is_branch = matches!(instruction.mnemonic(), Mnemonic::Call | Mnemonic::Jmp)
+        && instruction.op0_kind() == OpKind::NearBranch64
+        && instruction.len() == 5;
+
+    if is_branch {
+        let target = instruction.near_branch_target() as usize;
+        (target < image.len()).then_some(target)
+    } else {
+        Some(rva)
+    }
+}
+
+/// Returns the target of the RIP-relative operand found `offset` bytes past `rva`,
+/// which may be a memory operand (e.g. `lea`, `mov`, `cmp`) or a branch target.
+///
+/// The instructions from `rva` are decoded to find the one containing the operand,
+/// so that the target is relative to the end of that instruction, even if it has
+/// an immediate after the operand. If they can't be decoded, such as when the match
+/// doesn't start on an instruction, the operand is assumed to be a 4-byte
+/// displacement at the end of its instruction.
+pub fn relative_target(image: &[u8], rva: usize, offset: isize) -> Option<usize> {
+    let at = rva.checked_add_signed(offset)?;
+
+    let target = match decoded_target(image, rva, at) {
+        Some(target) => target,
+        None => displacement_target(image, at)?,
+    };
+    (target < image.len()).then_some(target)
+}
+
+/// Decodes the instruction at `rva`, if it is valid.
+pub fn decode(image: &[u8], rva: usize) -> Option<Instruction> {
+    let code = image.get(rva..(rva + MAX_INSTRUCTION_LEN).min(image.len()))?;
+    let instruction = Decoder::with_ip(64, code, rva as u64, DecoderOptions::NONE).decode();
+
+    (!instruction.is_invalid()).then_some(instruction)
+}
+
+/// Decodes instructions from `rva` until reaching the one containing `at`,
+/// and returns the target of its RIP-relative operand.
+fn decoded_target(image: &[u8], rva: usize, at: usize) -> Option<usize> {
+    let mut ip = rva;
+
+    while ip <= at {
+        let instruction = decode(image, ip)?;
+        let next_ip = instruction.next_ip() as usize;
+
+        if at < next_ip {
+            return if instruction.is_ip_rel_memory_operand() {
+                Some(instruction.ip_rel_memory_address() as usize)
+            } else if (0..instruction.op_count())
+                .any(|i| instruction.op_kind(i) == OpKind::NearBranch64)
+            {
+                Some(instruction.near_branch_target() as usize)
+            } else {
+                None
+            };
+        }
+
+        ip = next_ip;
+    }
+
+    None
+}
+
+/// Returns the target of a 4-byte displacement at `at`, assuming it is the last
+/// part of its instruction.
+fn displacement_target(image: &[u8], at: usize) -> Option<usize> {
+    let displacement = i32::from_le_bytes(image.get(at..at + 4)?.try_into().unwrap());
+    (at + 4).checked_add_signed(displacement as isize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follow_branch() {
+        // call +0x10, jmp -0x0A, a regular instruction, then a short jmp +0x02
+        let mut image = vec![0xCC; 0x40];
+        image[0x00..0x05].copy_from_slice(&[0xE8, 0x10, 0x00, 0x00, 0x00]);
+        image[0x20..0x25].copy_from_slice(&[0xE9, 0xF6, 0xFF, 0xFF, 0xFF]);
+        image[0x30] = 0x48;
+        image[0x38..0x3A].copy_from_slice(&[0xEB, 0x02]);
+
+        assert_eq!(follow_branch(&image, 0x00), Some(0x15));
+        assert_eq!(follow_branch(&image, 0x20), Some(0x1B));
+        assert_eq!(follow_branch(&image, 0x30), Some(0x30));
+        assert_eq!(follow_branch(&image, 0x38), Some(0x38));
+
+        // truncated, and out of bounds
+        assert_eq!(follow_branch(&image[..0x23], 0x20), None);
+        assert_eq!(follow_branch(&image, 0x40), None);
+    }
+
+    #[test]
+    fn test_resolve_match() {
+        // call +0x08 to a function starting with lea rcx, [rip + 0x20]
+        let mut image = vec![0xCC; 0x40];
+        image[0x00..0x05].copy_from_slice(&[0xE8, 0x08, 0x00, 0x00, 0x00]);
+        image[0x0D..0x14].copy_from_slice(&[0x48, 0x8D, 0x0D, 0x20, 0x00, 0x00, 0x00]);
+
+        let function = resolve_match(&image, 0x00, SignatureKind::MemberFunction, 0);
+        assert_eq!(function, Some(0x0D));
+        let vtable = resolve_match(&image, 0x00, SignatureKind::VTable, 3);
+        assert_eq!(vtable, Some(0x34));
+        let static_address = resolve_match(&image, 0x0D, SignatureKind::StaticAddress, 3);
+        assert_eq!(static_address, Some(0x34));
+    }
+
+    #[test]
+    fn test_relative_target() {
+        // lea rax, [rip + 0x100]
+        let mut image = vec![0x00; 0x200];
+        image[0x10..0x17].copy_from_slice(&[0x48, 0x8D, 0x05, 0x00, 0x01, 0x00, 0x00]);
+
+        assert_eq!(relative_target(&image, 0x10, 3), Some(0x117));
+
+        // targets outside the image are rejected
+        image[0x13..0x17].copy_from_slice(&(-0x20i32).to_le_bytes());
+        assert_eq!(relative_target(&image, 0x10, 3), None);
+        image[0x13..0x17].copy_from_slice(&0x1000i32.to_le_bytes());
+        assert_eq!(relative_target(&image, 0x10, 3), None);
+    }
+
+    #[test]
+    fn test_relative_target_decoded() {
+        let mut image = vec![0xCC; 0x100];
+        // mov rax, [rcx]; cmp byte ptr [rip + 0x40], 0
+        image[0x00..0x0A].copy_from_slice(&[0x48, 0x8B, 0x01, 0x80, 0x3D, 0x40, 0, 0, 0, 0x00]);
+        // mov dword ptr [rip + 0x40], 1
+        image[0x10..0x1A].copy_from_slice(&[0xC7, 0x05, 0x40, 0, 0, 0, 1, 0, 0, 0]);
+        // mov word ptr [rip + 0x40], ax
+        image[0x20..0x27].copy_from_slice(&[0x66, 0x89, 0x05, 0x40, 0, 0, 0]);
+        // jne +0x40
+        image[0x30..0x36].copy_from_slice(&[0x0F, 0x85, 0x40, 0, 0, 0]);
+
+        // targets are relative to the end of the instruction, past any immediates
+        assert_eq!(relative_target(&image, 0x00, 5), Some(0x4A));
+        assert_eq!(relative_target(&image, 0x10, 2), Some(0x5A));
+        assert_eq!(relative_target(&image, 0x20, 3), Some(0x67));
+        assert_eq!(relative_target(&image, 0x30, 2), Some(0x76));
+
+        // matches that don't start on an instruction fall back to a plain displacement
+        assert_eq!(relative_target(&image, 0x04, 1), Some(0x49));
+    }
+}
@@ -0,0 +1,376 @@
+//! MSVC run-time type information, for finding vtables by class name rather
+//! than by signature. Only 64-bit images are supported, where RTTI structures
+//! refer to each other by RVA.
+
+use std::collections::HashMap;
+
+use crate::pe::{PeImage, Section, SCN_CNT_INITIALIZED_DATA};
+
+/// The signature of a complete object locator in a 64-bit image.
+const LOCATOR_SIGNATURE_64: u32 = 1;
+const LOCATOR_SIZE: usize = 24;
+
+/// Type descriptors start with a pointer to `type_info`'s vtable and a reserved pointer.
+const TYPE_NAME_OFFSET: u32 = 16;
+const MAX_NAME_LEN: usize = 1024;
+
+/// Prefixes of decorated names for classes, structs and unions.
+const TYPE_NAME_PREFIXES: [&str; 3] = [".?AV", ".?AU", ".?AT"];
+
+/// A polymorphic class found through RTTI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Class {
+    /// The decorated name from the type descriptor, e.g. `.?AVFramework@System@Client@@`.
+    pub mangled_name: String,
+    /// The name as written in C++, e.g. `Client::System::Framework`, if it could be demangled.
+    pub name: Option<String>,
+    /// The class's vtables, one per polymorphic subobject, sorted by offset.
+    pub vtables: Vec<VTable>,
+    /// The classes this class derives from, directly or indirectly, in the order
+    /// the compiler lists them.
+    pub bases: Vec<BaseClass>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VTable {
+    pub rva: usize,
+    /// The offset of the subobject using this vtable within the complete object.
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BaseClass {
+    pub mangled_name: String,
+    /// The offset of the base within the derived class.
+    pub offset: i32,
+    /// The number of further bases listed after this one that it derives from.
+    pub contained_bases: u32,
+}
+
+/// Every class with RTTI in an image, indexed by mangled and demangled name.
+#[derive(Debug, Default)]
+pub struct Rtti {
+    classes: Vec<Class>,
+    by_name: HashMap<String, usize>,
+}
+
+/// A complete object locator, which sits just before a vtable.
+struct Locator {
+    offset: u32,
+    type_descriptor: u32,
+    class_descriptor: u32,
+}
+
+impl Rtti {
+    /// Finds every class with RTTI in the image's data sections. `base` is the address
+    /// the image is loaded at, or its preferred base if it hasn't been relocated, as
+    /// vtables point to their locators by address.
+    pub fn parse(pe: &PeImage, base: u64) -> Self {
+        if !pe.is_64 {
+            return Self::default();
+        }
+
+        let sections: Vec<&Section> = pe
+            .sections
+            .iter()
+            .filter(|s| {
+                s.is_readable()
+                    && !s.is_executable()
+                    && s.characteristics & SCN_CNT_INITIALIZED_DATA != 0
+            })
+            .collect();
+
+        // locators refer to themselves, which makes them easy to pick out
+        let mut locators = HashMap::new();
+        for section in &sections {
+            let data = pe.section_data(section);
+            for at in (0..data.len().saturating_sub(LOCATOR_SIZE - 1)).step_by(4) {
+                let rva = section.virtual_address + at as u32;
+                if u32_at(data, at) != LOCATOR_SIGNATURE_64 || u32_at(data, at + 20) != rva {
+                    continue;
+                }
+
+                let locator = Locator {
+                    offset: u32_at(data, at + 4),
+                    type_descriptor: u32_at(data, at + 12),
+                    class_descriptor: u32_at(data, at + 16),
+                };
+                if type_name(pe, locator.type_descriptor).is_some() {
+                    locators.insert(rva, locator);
+                }
+            }
+        }
+
+        // each vtable is preceded by a pointer to its locator
+        let mut vtables: HashMap<u32, Vec<VTable>> = HashMap::new();
+        for section in &sections {
+            let data = pe.section_data(section);
+            for at in (0..data.len().saturating_sub(7)).step_by(8) {
+                let pointer = u64::from_le_bytes(data[at..at + 8].try_into().unwrap());
+                let Some(locator_rva) = pointer
+                    .checked_sub(base)
+                    .and_then(|rva| u32::try_from(rva).ok())
+                else {
+                    continue;
+                };
+
+                if let Some(locator) = locators.get(&locator_rva) {
+                    vtables
+                        .entry(locator.type_descriptor)
+                        .or_default()
+                        .push(VTable {
+                            rva: section.virtual_address as usize + at + 8,
+                            offset: locator.offset,
+                        });
+                }
+            }
+        }
+
+        // classes are identified by their type descriptor, and may have several locators
+        let mut class_descriptors = HashMap::new();
+        for locator in locators.values() {
+            class_descriptors
+                .entry(locator.type_descriptor)
+                .or_insert(locator.class_descriptor);
+        }
+
+        let mut classes: Vec<Class> = class_descriptors
+            .into_iter()
+            .filter_map(|(type_descriptor, class_descriptor)| {
+                let mangled_name = type_name(pe, type_descriptor)?;
+                let mut vtables = vtables.remove(&type_descriptor).unwrap_or_default();
+                vtables.sort_unstable_by_key(|v| (v.offset, v.rva));
+
+                Some(Class {
+                    name: demangle(&mangled_name),
+                    mangled_name,
+                    vtables,
+                    bases: bases(pe, class_descriptor).unwrap_or_default(),
+                })
+            })
+            .collect();
+        classes.sort_unstable_by(|a, b| a.mangled_name.cmp(&b.mangled_name));
+
+        let mut by_name = HashMap::new();
+        for (index, class) in classes.iter().enumerate() {
+            by_name.insert(class.mangled_name.clone(), index);
+            if let Some(name) = &class.name {
+                by_name.entry(name.clone()).or_insert(index);
+            }
+        }
+
+        Self { classes, by_name }
+    }
+
+    /// Every class found, sorted by mangled name.
+    pub fn classes(&self) -> &[Class] {
+        &self.classes
+    }
+
+    /// Looks up a class by mangled or demangled name.
+    pub fn class(&self, name: &str) -> Option<&Class> {
+        self.by_name.get(name).map(|&index| &self.classes[index])
+    }
+
+    /// Returns the RVA of a class's primary vtable, the one at offset 0.
+    pub fn vtable(&self, name: &str) -> Option<usize> {
+        let class = self.class(name)?;
+        class.vtables.iter().find(|v| v.offset == 0).map(|v| v.rva)
+    }
+}
+
+/// Demangles a type descriptor name such as `.?AVFramework@System@Client@@` into
+/// `Client::System::Framework`. Templates, anonymous namespaces and other names
+/// that need a full demangler aren't supported.
+pub fn demangle(mangled: &str) -> Option<String> {
+    let name = TYPE_NAME_PREFIXES
+        .iter()
+        .find_map(|prefix| mangled.strip_prefix(prefix))?
+        .strip_suffix("@@")?;
+
+    let parts: Vec<&str> = name.split('@').collect();
+    let is_identifier = |part: &&str| {
+        part.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !parts.iter().all(is_identifier) {
+        return None;
+    }
+
+    Some(parts.into_iter().rev().collect::<Vec<_>>().join("::"))
+}
+
+/// Reads the base classes listed by a class hierarchy descriptor, skipping the
+/// first, which is the class itself.
+fn bases(pe: &PeImage, class_descriptor: u32) -> Option<Vec<BaseClass>> {
+    let count = read_u32(pe, class_descriptor.checked_add(8)?)?;
+    let array = read_u32(pe, class_descriptor.checked_add(12)?)?;
+    // a real hierarchy is nowhere near this deep, so anything more is garbage
+    if count > 1024 {
+        return None;
+    }
+
+    (1..count)
+        .map(|i| {
+            let descriptor = read_u32(pe, array.checked_add(i * 4)?)?;
+            Some(BaseClass {
+                mangled_name: type_name(pe, read_u32(pe, descriptor)?)?,
+                contained_bases: read_u32(pe, descriptor.checked_add(4)?)?,
+                offset: read_u32(pe, descriptor.checked_add(8)?)? as i32,
+            })
+        })
+        .collect()
+}
+
+/// Reads the decorated name from a type descriptor, if it looks like a class's.
+fn type_name(pe: &PeImage, type_descriptor: u32) -> Option<String> {
+    let rva = type_descriptor.checked_add(TYPE_NAME_OFFSET)?;
+    let section = pe.section_for_rva(rva)?;
+    let data = pe.section_data(section);
+    let start = (rva - section.virtual_address) as usize;
+    let bytes = data.get(start..(start + MAX_NAME_LEN).min(data.len()))?;
+
+    let name = &bytes[..bytes.iter().position(|&b| b == 0)?];
+    let name = std::str::from_utf8(name).ok()?;
+    TYPE_NAME_PREFIXES
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+        .then(|| name.to_string())
+}
+
+fn read_u32(pe: &PeImage, rva: u32) -> Option<u32> {
+    Some(u32::from_le_bytes(pe.read_rva(rva, 4)?.try_into().unwrap()))
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::{
+        map_image,
+        tests::{sample_pe, RDATA, TEXT},
+        Layout,
+    };
+
+    const IMAGE_BASE: u64 = 0x1_4000_0000;
+
+    fn put_u32s(buf: &mut [u8], rva: u32, values: &[u32]) {
+        for (i, value) in values.iter().enumerate() {
+            let at = (rva - 0x2000) as usize + i * 4;
+            buf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// `.rdata` for `Game::Derived`, which derives from `Base` and `Other`, so has
+    /// two vtables.
+    fn sample_rdata() -> Vec<u8> {
+        let mut rdata = vec![0u8; 0x200];
+        for (rva, name) in [
+            (0x2000, &b".?AVDerived@Game@@"[..]),
+            (0x2040, b".?AUBase@@"),
+            (0x2080, b".?AVOther@@"),
+        ] {
+            let at = rva - 0x2000 + TYPE_NAME_OFFSET as usize;
+            rdata[at..at + name.len()].copy_from_slice(name);
+        }
+
+        // the hierarchy descriptor, base class array, and base class descriptors
+        put_u32s(&mut rdata, 0x20C0, &[0, 1, 3, 0x20D0]);
+        put_u32s(&mut rdata, 0x20D0, &[0x20E0, 0x2100, 0x2120]);
+        put_u32s(&mut rdata, 0x20E0, &[0x2000, 2, 0, u32::MAX, 0, 0, 0x20C0]);
+        put_u32s(&mut rdata, 0x2100, &[0x2040, 0, 0, u32::MAX, 0, 0, 0]);
+        put_u32s(&mut rdata, 0x2120, &[0x2080, 0, 8, u32::MAX, 0, 0, 0]);
+
+        // locators for both vtables, and one that doesn't point to itself
+        put_u32s(&mut rdata, 0x2140, &[1, 0, 0, 0x2000, 0x20C0, 0x2140]);
+        put_u32s(&mut rdata, 0x2160, &[1, 8, 0, 0x2000, 0x20C0, 0x2160]);
+        put_u32s(&mut rdata, 0x21C0, &[1, 0, 0, 0x2040, 0x20C0, 0x2000]);
+
+        // the vtables, each preceded by a pointer to its locator
+        for (rva, locator) in [(0x2180, 0x2140), (0x21A0, 0x2160), (0x21E0, 0x21C0)] {
+            let at = rva - 0x2000;
+            rdata[at..at + 8].copy_from_slice(&(IMAGE_BASE + locator).to_le_bytes());
+            rdata[at + 8..at + 16].copy_from_slice(&(IMAGE_BASE + 0x1000).to_le_bytes());
+        }
+
+        rdata
+    }
+
+    #[test]
+    fn test_parse() {
+        let rdata = sample_rdata();
+        let file = sample_pe(
+            true,
+            &[
+                (".text", 0x1000, &[0xC3], TEXT),
+                (".rdata", 0x2000, &rdata, RDATA),
+            ],
+        );
+        let mapped = map_image(&file).unwrap();
+
+        for (data, layout) in [(&file, Layout::File), (&mapped, Layout::Mapped)] {
+            let pe = PeImage::parse(data, layout).unwrap();
+            let rtti = Rtti::parse(&pe, IMAGE_BASE);
+
+            assert_eq!(
+                rtti.classes(),
+                [Class {
+                    mangled_name: ".?AVDerived@Game@@".to_string(),
+                    name: Some("Game::Derived".to_string()),
+                    vtables: vec![
+                        VTable {
+                            rva: 0x2188,
+                            offset: 0
+                        },
+                        VTable {
+                            rva: 0x21A8,
+                            offset: 8
+                        }
+                    ],
+                    bases: vec![
+                        BaseClass {
+                            mangled_name: ".?AUBase@@".to_string(),
+                            offset: 0,
+                            contained_bases: 0
+                        },
+                        BaseClass {
+                            mangled_name: ".?AVOther@@".to_string(),
+                            offset: 8,
+                            contained_bases: 0
+                        }
+                    ],
+                }]
+            );
+
+            assert_eq!(rtti.vtable("Game::Derived"), Some(0x2188));
+            assert_eq!(rtti.vtable(".?AVDerived@Game@@"), Some(0x2188));
+            assert_eq!(rtti.vtable("Base"), None);
+        }
+
+        // relocated somewhere else, the vtables no longer point to the locators
+        let pe = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+        let rtti = Rtti::parse(&pe, 0x7FF6_0000_0000);
+        assert!(rtti.classes()[0].vtables.is_empty());
+
+        // descriptors at the end of the address space don't overflow
+        assert_eq!(bases(&pe, u32::MAX - 4), None);
+    }
+
+    #[test]
+    fn test_demangle() {
+        assert_eq!(
+            demangle(".?AVFramework@System@Client@@").as_deref(),
+            Some("Client::System::Framework")
+        );
+        assert_eq!(demangle(".?AUBase@@").as_deref(), Some("Base"));
+        assert_eq!(demangle(".?AV?$vector@HV?$allocator@H@std@@@std@@"), None);
+        assert_eq!(demangle(".?AVtype_info"), None);
+        assert_eq!(demangle(".?AVFoo@?A0x12345678@@"), None);
+        assert_eq!(demangle("Framework"), None);
+    }
+}
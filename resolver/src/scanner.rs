@@ -0,0 +1,517 @@
+use memchr::memmem;
+use std::{collections::HashMap, fmt};
+
+/// A byte pattern to search for, with a mask selecting which bits of each byte
+/// must match. A mask byte of `0xFF` matches the byte exactly, and `0x00` matches
+/// any byte.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+    mask: Vec<u8>,
+    /// The longest run of exact bytes in the pattern, used to quickly find
+    /// candidate matches: (offset into the pattern, length).
+    anchor: Option<(usize, usize)>,
+}
+
+impl Pattern {
+    /// Creates a new pattern from bytes and a bitmask of the same length.
+    pub fn new(bytes: Vec<u8>, mask: Vec<u8>) -> Self {
+        assert_eq!(
+            bytes.len(),
+            mask.len(),
+            "pattern bytes and mask must be the same length"
+        );
+
+        // normalize the bytes so that we can compare masked bytes directly
+        let bytes = bytes.iter().zip(&mask).map(|(b, m)| b & m).collect();
+        let anchor = Self::find_anchor(&mask);
+
+        Self {
+            bytes,
+            mask,
+            anchor,
+        }
+    }
+
+    /// Creates a new pattern from bytes and a mask where any non-zero byte means
+    /// an exact match, and zero means a wildcard.
+    pub fn from_byte_mask(bytes: &[u8], mask: &[u8]) -> Self {
+        Self::new(
+            bytes.to_vec(),
+            mask.iter()
+                .map(|&m| if m == 0 { 0x00 } else { 0xFF })
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+
+    /// Returns true if the pattern matches the haystack at the given position.
+    pub fn matches_at(&self, haystack: &[u8], pos: usize) -> bool {
+        match haystack.get(pos..pos + self.len()) {
+            Some(window) => window
+                .iter()
+                .zip(&self.bytes)
+                .zip(&self.mask)
+                .all(|((h, b), m)| h & m == *b),
+            None => false,
+        }
+    }
+
+    fn find_anchor(mask: &[u8]) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        let mut run_start = 0;
+
+        for i in 0..=mask.len() {
+            if i < mask.len() && mask[i] == 0xFF {
+                continue;
+            }
+
+            let run_len = i - run_start;
+            if run_len > 0 && best.map_or(true, |(_, len)| run_len > len) {
+                best = Some((run_start, run_len));
+            }
+
+            run_start = i + 1;
+        }
+
+        best
+    }
+}
+
+/// Formats the pattern as space-separated hex bytes, with `??` for wildcards and
+/// `?` for wildcard nibbles. Other masks are written as `byte&mask`.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (byte, mask)) in self.bytes.iter().zip(&self.mask).enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+
+            match mask {
+                0xFF => write!(f, "{:02X}", byte)?,
+                0x00 => f.write_str("??")?,
+                0xF0 => write!(f, "{:X}?", byte >> 4)?,
+                0x0F => write!(f, "?{:X}", byte & 0x0F)?,
+                _ => write!(f, "{:02X}&{:02X}", byte, mask)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A summary of where a pattern matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Matches {
+    /// The offset of the first match.
+    pub first: Option<usize>,
+    /// The number of matches found. When searching stops at the first match,
+    /// this is at most 1.
+    pub count: usize,
+}
+
+impl Matches {
+    /// Returns true if the pattern matched more than once, in which case the first
+    /// match may not be the one that was intended.
+    pub fn is_ambiguous(&self) -> bool {
+        self.count > 1
+    }
+}
+
+/// Summarizes the result of a search that stops at the first match.
+impl From<Option<usize>> for Matches {
+    fn from(first: Option<usize>) -> Self {
+        Self {
+            first,
+            count: first.is_some() as usize,
+        }
+    }
+}
+
+/// Finds the offset of the first match of `pattern` in `haystack`.
+pub fn find(haystack: &[u8], pattern: &Pattern) -> Option<usize> {
+    find_iter(haystack, pattern).next()
+}
+
+/// Returns an iterator over the offsets of all matches of `pattern` in `haystack`,
+/// in ascending order. Matches may overlap.
+pub fn find_iter<'h, 'p>(
+    haystack: &'h [u8],
+    pattern: &'p Pattern,
+) -> impl Iterator<Item = usize> + 'h
+where
+    'p: 'h,
+{
+    let last_start = haystack.len().checked_sub(pattern.len());
+    let mut candidates: Box<dyn Iterator<Item = usize> + 'h> = match (pattern.anchor, last_start) {
+        (_, None) => Box::new(std::iter::empty()),
+        // search for the anchor, which has no wildcards, then check the rest of the
+        // pattern around it; memmem uses SIMD to skip over non-matching regions
+        (Some((offset, len)), Some(last_start)) => {
+            // positions in the searched region line up with pattern start offsets
+            let finder = memmem::Finder::new(&pattern.bytes[offset..offset + len]);
+            let region = &haystack[offset..last_start + offset + len];
+
+            // resume just after each hit, as anchors may overlap
+            let mut start = 0;
+            Box::new(std::iter::from_fn(move || {
+                let pos = start + finder.find(&region[start..])?;
+                start = pos + 1;
+                Some(pos)
+            }))
+        }
+        // no fixed bytes at all, so every position is a candidate
+        (None, Some(last_start)) => Box::new(0..=last_start),
+    };
+
+    std::iter::from_fn(move || candidates.find(|&pos| pattern.matches_at(haystack, pos)))
+}
+
+/// Finds every match of `pattern` in `haystack`, returning the first and how many there were.
+pub fn count(haystack: &[u8], pattern: &Pattern) -> Matches {
+    let mut iter = find_iter(haystack, pattern);
+    let first = iter.next();
+
+    Matches {
+        first,
+        count: first.map_or(0, |_| 1 + iter.count()),
+    }
+}
+
+/// Number of bytes used to index the patterns in a batch search.
+const KEY_LEN: usize = 3;
+
+/// Bytes that are very common in x86-64 code (REX prefixes, mov/lea opcodes, padding,
+/// small displacements), and so make for poor batch search keys.
+const COMMON_BYTES: [u8; 16] = [
+    0x00, 0x01, 0x08, 0x0F, 0x10, 0x20, 0x24, 0x40, 0x44, 0x48, 0x4C, 0x83, 0x89, 0x8B, 0x8D, 0xFF,
+];
+
+/// A bitset of search keys, hashed down to 16 bits so that it fits in L1 cache.
+struct KeyFilter([u64; 1024]);
+
+impl KeyFilter {
+    fn slot(key: u32) -> (usize, u64) {
+        let hash = (key.wrapping_mul(0x9E37_79B1) >> 16) as usize;
+        (hash >> 6, 1 << (hash & 63))
+    }
+
+    fn insert(&mut self, key: u32) {
+        let (word, bit) = Self::slot(key);
+        self.0[word] |= bit;
+    }
+
+    fn contains(&self, key: u32) -> bool {
+        let (word, bit) = Self::slot(key);
+        self.0[word] & bit != 0
+    }
+}
+
+fn read_key(window: &[u8]) -> u32 {
+    u32::from_le_bytes([window[0], window[1], window[2], 0])
+}
+
+impl Pattern {
+    /// Picks the offset of the key used to find this pattern in a batch search: the
+    /// window within the anchor with the fewest common bytes, so that it hits rarely.
+    fn batch_key_offset(&self) -> Option<usize> {
+        let (offset, len) = self.anchor.filter(|&(_, len)| len >= KEY_LEN)?;
+
+        (offset..=offset + len - KEY_LEN).min_by_key(|&start| {
+            self.bytes[start..start + KEY_LEN]
+                .iter()
+                .filter(|b| COMMON_BYTES.contains(b))
+                .count()
+        })
+    }
+}
+
+/// Finds the first match of each pattern in a single pass over `haystack`,
+/// returning the offsets in the same order as `patterns`.
+///
+/// Each pattern is indexed by a few exact bytes from its anchor, and the full
+/// patterns are only checked where one of those keys appears. Patterns with
+/// shorter anchors are scanned for individually.
+pub fn find_batch(haystack: &[u8], patterns: &[Pattern]) -> Vec<Option<usize>> {
+    scan_batch(haystack, patterns, false)
+        .into_iter()
+        .map(|matches| matches.first)
+        .collect()
+}
+
+/// Like `find_batch`, but finds every match of each pattern, so that ambiguous
+/// patterns can be detected. This always scans the entire haystack.
+pub fn count_batch(haystack: &[u8], patterns: &[Pattern]) -> Vec<Matches> {
+    scan_batch(haystack, patterns, true)
+}
+
+fn scan_batch(haystack: &[u8], patterns: &[Pattern], count_all: bool) -> Vec<Matches> {
+    let mut results = vec![Matches::default(); patterns.len()];
+
+    let mut filter = KeyFilter([0; 1024]);
+    let mut owners: HashMap<u32, Vec<(usize, usize)>> = HashMap::new();
+    let mut remaining = 0;
+
+    for (idx, pattern) in patterns.iter().enumerate() {
+        match pattern.batch_key_offset() {
+            Some(offset) => {
+                let key = read_key(&pattern.bytes[offset..]);
+                filter.insert(key);
+                owners.entry(key).or_default().push((idx, offset));
+                remaining += 1;
+            }
+            None if count_all => results[idx] = count(haystack, pattern),
+            None => results[idx] = find(haystack, pattern).into(),
+        }
+    }
+
+    if remaining == 0 {
+        return results;
+    }
+
+    // positions are visited in order, and for any given pattern the key is a fixed
+    // distance from the start, so the first match we verify is also its earliest
+    for (pos, window) in haystack.windows(KEY_LEN).enumerate() {
+        let key = read_key(window);
+        if !filter.contains(key) {
+            continue;
+        }
+
+        let Some(candidates) = owners.get(&key) else {
+            continue;
+        };
+
+        for &(idx, offset) in candidates {
+            let result = &mut results[idx];
+            if !count_all && result.first.is_some() {
+                continue;
+            }
+
+            if let Some(start) = pos.checked_sub(offset) {
+                if patterns[idx].matches_at(haystack, start) {
+                    if result.first.is_none() {
+                        result.first = Some(start);
+                        remaining -= 1;
+                    }
+
+                    result.count += 1;
+                }
+            }
+        }
+
+        if remaining == 0 && !count_all {
+            break;
+        }
+    }
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(bytes: &[u8], mask: &[u8]) -> Pattern {
+        Pattern::from_byte_mask(bytes, mask)
+    }
+
+    #[test]
+    fn test_anchor() {
+        let p = pattern(&[1, 2, 0, 3, 4, 5], &[1, 1, 0, 1, 1, 1]);
+        assert_eq!(p.anchor, Some((3, 3)));
+
+        let p = pattern(&[0, 1, 0], &[0, 1, 0]);
+        assert_eq!(p.anchor, Some((1, 1)));
+
+        let p = pattern(&[0, 0], &[0, 0]);
+        assert_eq!(p.anchor, None);
+    }
+
+    #[test]
+    fn test_display() {
+        let p = pattern(&[0xE8, 0, 0, 0x7C], &[1, 0, 0, 1]);
+        assert_eq!(p.to_string(), "E8 ?? ?? 7C");
+
+        let p = Pattern::new(vec![0x48, 0x8B, 0x05, 0xFF], vec![0xF0, 0x0F, 0xFF, 0x81]);
+        assert_eq!(p.to_string(), "4? ?B 05 81&81");
+    }
+
+    #[test]
+    fn test_find() {
+        let haystack = [0x90, 0xE8, 0x11, 0x22, 0x33, 0x44, 0x80, 0x7C, 0x90];
+
+        // wildcards in the middle
+        let p = pattern(&[0xE8, 0, 0, 0, 0, 0x80, 0x7C], &[1, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(find(&haystack, &p), Some(1));
+
+        // wildcard at the start
+        let p = pattern(&[0, 0x22], &[0, 1]);
+        assert_eq!(find(&haystack, &p), Some(2));
+
+        // only wildcards
+        let p = pattern(&[0, 0, 0], &[0, 0, 0]);
+        assert_eq!(find(&haystack, &p), Some(0));
+
+        // no match
+        let p = pattern(&[0xE8, 0x12], &[1, 1]);
+        assert_eq!(find(&haystack, &p), None);
+    }
+
+    #[test]
+    fn test_find_bounds() {
+        let haystack = [0x01, 0x02, 0x03, 0x04];
+
+        // match ending exactly at the end of the haystack
+        let p = pattern(&[0x03, 0x04], &[1, 1]);
+        assert_eq!(find(&haystack, &p), Some(2));
+
+        // anchor found at the end, but the rest of the pattern would run off the end
+        let p = pattern(&[0x04, 0], &[1, 0]);
+        assert_eq!(find(&haystack, &p), None);
+        let p = pattern(&[0x03, 0x04, 0], &[1, 1, 0]);
+        assert_eq!(find(&haystack, &p), None);
+
+        // anchor at the end of a pattern with leading wildcards
+        let p = pattern(&[0, 0, 0, 0x02], &[0, 0, 0, 1]);
+        assert_eq!(find(&haystack, &p), None);
+        let p = pattern(&[0, 0, 0x02], &[0, 0, 1]);
+        assert_eq!(find(&haystack, &p), None);
+        let p = pattern(&[0, 0, 0x04], &[0, 0, 1]);
+        assert_eq!(find(&haystack, &p), Some(1));
+
+        // pattern longer than the haystack
+        let p = pattern(&[1, 2, 3, 4, 5], &[1, 1, 1, 1, 1]);
+        assert_eq!(find(&haystack, &p), None);
+        assert_eq!(find(&[], &p), None);
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let haystack = [0xAA, 0xAA, 0xAA, 0xBB, 0xAA];
+        let p = pattern(&[0xAA, 0xAA], &[1, 1]);
+        assert_eq!(find_iter(&haystack, &p).collect::<Vec<_>>(), [0, 1]);
+
+        let p = pattern(&[0xAA, 0], &[1, 0]);
+        assert_eq!(find_iter(&haystack, &p).collect::<Vec<_>>(), [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_find_batch() {
+        let haystack = [
+            0x90, 0xE8, 0x11, 0x22, 0x33, 0x44, 0x80, 0x7C, 0x90, 0x48, 0x8D, 0x05, 0x80, 0x7C,
+        ];
+
+        let patterns = [
+            pattern(&[0xE8, 0, 0, 0, 0, 0x80, 0x7C], &[1, 0, 0, 0, 0, 1, 1]),
+            pattern(&[0x8D, 0, 0x80, 0x7C], &[1, 0, 1, 1]),
+            pattern(&[0x48, 0x8D], &[1, 1]),
+            pattern(&[0, 0], &[0, 0]),
+            pattern(&[0xFF], &[1]),
+            pattern(&[0x7C, 0], &[1, 0]),
+            // long enough to be keyed, including two patterns sharing the same key
+            pattern(&[0x22, 0x33, 0x44, 0, 0x7C], &[1, 1, 1, 0, 1]),
+            pattern(&[0, 0x22, 0x33, 0x44, 0x80], &[0, 1, 1, 1, 1]),
+            pattern(&[0x22, 0x33, 0x44, 0x90], &[1, 1, 1, 1]),
+            pattern(
+                &[0x90, 0x48, 0x8D, 0x05, 0x80, 0x7C, 0],
+                &[1, 1, 1, 1, 1, 1, 0],
+            ),
+            pattern(&[0x48, 0x8D, 0x05, 0x80], &[1, 1, 1, 1]),
+        ];
+
+        let results = find_batch(&haystack, &patterns);
+        assert_eq!(
+            results,
+            [
+                Some(1),
+                Some(10),
+                Some(9),
+                Some(0),
+                None,
+                Some(7),
+                Some(3),
+                Some(2),
+                None,
+                None,
+                Some(9),
+            ]
+        );
+
+        // the batch search agrees with searching individually
+        for (pattern, result) in patterns.iter().zip(&results) {
+            assert_eq!(find(&haystack, pattern), *result);
+        }
+    }
+
+    #[test]
+    fn test_count() {
+        let haystack = [0xAA, 0xAA, 0xAA, 0xBB, 0xAA, 0xAA, 0xAA];
+
+        let p = pattern(&[0xAA, 0xAA, 0xAA], &[1, 1, 1]);
+        let matches = count(&haystack, &p);
+        assert_eq!(matches.first, Some(0));
+        assert_eq!(matches.count, 2);
+        assert!(matches.is_ambiguous());
+
+        let p = pattern(&[0xBB, 0, 0xAA], &[1, 0, 1]);
+        assert_eq!(
+            count(&haystack, &p),
+            Matches {
+                first: Some(3),
+                count: 1
+            }
+        );
+        assert_eq!(
+            count(&haystack, &pattern(&[0xCC], &[1])),
+            Matches::default()
+        );
+    }
+
+    #[test]
+    fn test_count_batch() {
+        let haystack = [
+            0x48, 0x8D, 0x05, 0x11, 0x90, 0x48, 0x8D, 0x05, 0x22, 0x90, 0x48, 0x8D, 0x0D,
+        ];
+
+        let patterns = [
+            pattern(&[0x48, 0x8D, 0x05, 0], &[1, 1, 1, 0]),
+            pattern(&[0x48, 0x8D, 0x05, 0x22], &[1, 1, 1, 1]),
+            pattern(&[0x48, 0x8D], &[1, 1]),
+            pattern(&[0x48, 0x8D, 0x0D, 0], &[1, 1, 1, 0]),
+        ];
+
+        let results = count_batch(&haystack, &patterns);
+        let summary: Vec<_> = results.iter().map(|m| (m.first, m.count)).collect();
+        assert_eq!(
+            summary,
+            [(Some(0), 2), (Some(5), 1), (Some(0), 3), (None, 0)]
+        );
+
+        // the batch search agrees with counting individually
+        for (pattern, result) in patterns.iter().zip(&results) {
+            assert_eq!(count(&haystack, pattern), *result);
+        }
+    }
+
+    #[test]
+    fn test_nibble_mask() {
+        let haystack = [0x00, 0x48, 0x8B, 0x4C, 0x8B];
+        let p = Pattern::new(vec![0x40, 0x8B], vec![0xF0, 0xFF]);
+        assert_eq!(find_iter(&haystack, &p).collect::<Vec<_>>(), [1, 3]);
+    }
+}
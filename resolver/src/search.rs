@@ -0,0 +1,248 @@
+//! Scans requested at runtime, by scripts and addons looking for data nothing
+//! resolves up front. Matches are capped, so a loose pattern can't return the whole
+//! section, and can be cached, since the same scans tend to be repeated.
+
+use std::{collections::HashMap, fmt};
+
+use crate::{
+    module::{ModuleImage, SectionKind},
+    scanner::{self, Pattern},
+    signature::{Signature, SignatureError},
+};
+
+/// The most matches a single search returns, however many are requested.
+pub const MAX_RESULTS: usize = 1024;
+
+/// How many searches a cache keeps results for, before it's cleared.
+pub const CACHE_CAPACITY: usize = 256;
+
+/// How a query's pattern is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryKind {
+    /// A signature, with matches reported at its offset marker.
+    Signature,
+    /// Text, matched as its UTF-8 bytes exactly.
+    Text,
+}
+
+/// Reasons a search can't be run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchError {
+    /// The signature doesn't parse.
+    InvalidSignature(SignatureError),
+    /// The text to search for is empty, so would match anywhere.
+    EmptyText,
+    /// The module doesn't have the section to search.
+    MissingSection(SectionKind),
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchError::InvalidSignature(e) => write!(f, "{}", e),
+            SearchError::EmptyText => write!(f, "text is empty"),
+            SearchError::MissingSection(kind) => {
+                write!(f, "the module has no {} section", kind.name())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+/// What to search for, and how many matches to return at most.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Query {
+    pub pattern: Pattern,
+    /// The offset into the pattern to report matches at.
+    pub offset: usize,
+    /// The most matches to return, no more than `MAX_RESULTS`.
+    pub limit: usize,
+}
+
+impl Query {
+    pub fn new(pattern: &str, kind: QueryKind, max_results: usize) -> Result<Self, SearchError> {
+        let (pattern, offset) = match kind {
+            QueryKind::Signature => {
+                let sig = Signature::parse(pattern).map_err(SearchError::InvalidSignature)?;
+                (sig.pattern, sig.offset)
+            }
+            QueryKind::Text if pattern.is_empty() => return Err(SearchError::EmptyText),
+            QueryKind::Text => {
+                let bytes = pattern.as_bytes();
+                (Pattern::new(bytes.to_vec(), vec![0xFF; bytes.len()]), 0)
+            }
+        };
+
+        Ok(Self {
+            pattern,
+            offset,
+            limit: max_results.min(MAX_RESULTS),
+        })
+    }
+}
+
+/// The matches of a search, as RVAs.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Found {
+    pub rvas: Vec<usize>,
+    /// Whether there were more matches than the query's limit.
+    pub truncated: bool,
+}
+
+/// Searches a section of `module` for the query's pattern.
+pub fn search(
+    module: &ModuleImage,
+    kind: SectionKind,
+    query: &Query,
+) -> Result<Found, SearchError> {
+    let (Some(range), Some(data)) = (module.section_range(kind), module.section(kind)) else {
+        return Err(SearchError::MissingSection(kind));
+    };
+
+    // find one more than needed, to tell whether there were more
+    let mut rvas: Vec<_> = scanner::find_iter(data, &query.pattern)
+        .take(query.limit + 1)
+        .map(|pos| range.start + pos + query.offset)
+        .collect();
+    let truncated = rvas.len() > query.limit;
+    rvas.truncate(query.limit);
+
+    Ok(Found { rvas, truncated })
+}
+
+/// The results of earlier searches, by the module and section they searched.
+/// Cleared once it holds `CACHE_CAPACITY` results, rather than evicting them one by one.
+#[derive(Debug, Default)]
+pub struct SearchCache {
+    results: HashMap<(usize, SectionKind, Query), Found>,
+}
+
+impl SearchCache {
+    pub fn get(&self, module: &ModuleImage, section: SectionKind, query: &Query) -> Option<Found> {
+        self.results
+            .get(&(module.base() as usize, section, query.clone()))
+            .cloned()
+    }
+
+    pub fn insert(
+        &mut self,
+        module: &ModuleImage,
+        section: SectionKind,
+        query: Query,
+        found: Found,
+    ) {
+        if self.results.len() >= CACHE_CAPACITY {
+            self.results.clear();
+        }
+        self.results
+            .insert((module.base() as usize, section, query), found);
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query() {
+        let query = Query::new("48 8B * 0D ??", QueryKind::Signature, 4).unwrap();
+        assert_eq!(query.pattern.len(), 4);
+        assert_eq!(query.offset, 2);
+        assert_eq!(query.limit, 4);
+
+        let query = Query::new("hello", QueryKind::Text, usize::MAX).unwrap();
+        assert_eq!(query.pattern.bytes(), b"hello");
+        assert_eq!(query.pattern.mask(), [0xFF; 5]);
+        assert_eq!(query.offset, 0);
+        assert_eq!(query.limit, MAX_RESULTS);
+
+        assert_eq!(
+            Query::new("", QueryKind::Text, 1),
+            Err(SearchError::EmptyText)
+        );
+        assert!(matches!(
+            Query::new("48 ZZ", QueryKind::Signature, 1),
+            Err(SearchError::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_search() {
+        let mut image = vec![0u8; 0x100];
+        for at in [0x40, 0x60, 0x80] {
+            image[at..at + 4].copy_from_slice(&[0x48, 0x8B, 0x0D, at as u8]);
+        }
+        image[0xC0..0xC5].copy_from_slice(b"hello");
+        let module = ModuleImage::with_sections(&image, 0..0x20, None, Some(0x20..0x100));
+
+        // limited to two of the three matches, reported at the offset marker
+        let query = Query::new("48 8B * 0D ??", QueryKind::Signature, 2).unwrap();
+        let found = search(&module, SectionKind::Rdata, &query).unwrap();
+        assert_eq!(found.rvas, [0x42, 0x62]);
+        assert!(found.truncated);
+
+        // exactly as many matches as the limit isn't truncated
+        let query = Query::new("48 8B * 0D ??", QueryKind::Signature, 3).unwrap();
+        let found = search(&module, SectionKind::Rdata, &query).unwrap();
+        assert_eq!(found.rvas, [0x42, 0x62, 0x82]);
+        assert!(!found.truncated);
+
+        // text is matched exactly, and only within the section
+        let module = ModuleImage::with_sections(&image, 0..0x50, None, Some(0x50..0x100));
+        let query = Query::new("hello", QueryKind::Text, 10).unwrap();
+        let found = search(&module, SectionKind::Rdata, &query).unwrap();
+        assert_eq!(found.rvas, [0xC0]);
+        let found = search(&module, SectionKind::Text, &query).unwrap();
+        assert!(found.rvas.is_empty() && !found.truncated);
+
+        assert_eq!(
+            search(&module, SectionKind::Data, &query),
+            Err(SearchError::MissingSection(SectionKind::Data))
+        );
+        assert_eq!(
+            SearchError::MissingSection(SectionKind::Data).to_string(),
+            "the module has no .data section"
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let image = vec![0u8; 0x100];
+        let other = vec![0u8; 0x100];
+        let module = ModuleImage::with_sections(&image, 0..0x80, None, Some(0x80..0x100));
+        let other = ModuleImage::with_sections(&other, 0..0x80, None, Some(0x80..0x100));
+        let query = Query::new("00", QueryKind::Signature, 1).unwrap();
+        let found = Found {
+            rvas: vec![0x80],
+            truncated: true,
+        };
+
+        let mut cache = SearchCache::default();
+        cache.insert(&module, SectionKind::Rdata, query.clone(), found.clone());
+        assert_eq!(cache.get(&module, SectionKind::Rdata, &query), Some(found));
+
+        // only for the same module, section and limit
+        assert_eq!(cache.get(&other, SectionKind::Rdata, &query), None);
+        assert_eq!(cache.get(&module, SectionKind::Text, &query), None);
+        let wider = Query::new("00", QueryKind::Signature, 2).unwrap();
+        assert_eq!(cache.get(&module, SectionKind::Rdata, &wider), None);
+
+        // cleared once full
+        for limit in 2..CACHE_CAPACITY + 1 {
+            let query = Query::new("00", QueryKind::Signature, limit).unwrap();
+            cache.insert(&module, SectionKind::Rdata, query, Found::default());
+        }
+        assert_eq!(cache.len(), CACHE_CAPACITY);
+        cache.insert(&module, SectionKind::Rdata, wider, Found::default());
+        assert_eq!(cache.len(), 1);
+    }
+}
@@ -0,0 +1,267 @@
+//! Signatures parsed from text at runtime, for signatures that aren't known
+//! when the LLRT is built, such as those from configuration files and addons.
+
+use std::{fmt, str::FromStr};
+
+use crate::scanner::Pattern;
+
+/// Marks the position in a signature that it refers to, such as the start of
+/// a RIP-relative displacement. Written as its own token, e.g. `48 8D 0D * ?? ?? ?? ??`.
+pub const OFFSET_MARKER: &str = "*";
+
+/// A signature parsed from text: a pattern to scan for, and how far into the
+/// pattern the position it refers to is.
+///
+/// Signatures are written as space-separated tokens, each of which is one of:
+/// - a hex byte, e.g. `E8`
+/// - `??` or `?`, matching any byte
+/// - a hex nibble and a `?`, e.g. `4?` or `?8`, matching one nibble exactly
+/// - a hex byte and mask, e.g. `40&F8`, matching the bits set in the mask
+/// - the offset marker `*`, which may appear once before any byte
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature {
+    pub pattern: Pattern,
+    /// The offset into the pattern of the offset marker, or 0 if there isn't one.
+    pub offset: usize,
+}
+
+/// Reasons a signature can fail to parse. Columns are 1-based character positions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The signature has no bytes.
+    Empty,
+    /// A token is not a byte, wildcard or offset marker.
+    InvalidToken { token: String, column: usize },
+    /// The offset marker appears more than once.
+    DuplicateOffset { column: usize },
+    /// The offset marker is after the last byte, so it refers to nothing.
+    OffsetPastEnd { column: usize },
+    /// Every bit of the signature is a wildcard, so it would match anywhere.
+    AllWildcards,
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Empty => write!(f, "signature is empty"),
+            SignatureError::InvalidToken { token, column } => write!(
+                f,
+                "invalid token {:?} at column {}: expected a hex byte, a wildcard \
+                (`??`, `4?`, `?8`), a masked byte (`40&F8`) or `{}`",
+                token, column, OFFSET_MARKER
+            ),
+            SignatureError::DuplicateOffset { column } => write!(
+                f,
+                "duplicate offset marker `{}` at column {}",
+                OFFSET_MARKER, column
+            ),
+            SignatureError::OffsetPastEnd { column } => write!(
+                f,
+                "offset marker `{}` at column {} must be followed by a byte",
+                OFFSET_MARKER, column
+            ),
+            SignatureError::AllWildcards => {
+                write!(f, "signature only has wildcards, so would match anywhere")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+impl Signature {
+    pub fn parse(text: &str) -> Result<Self, SignatureError> {
+        let mut bytes = Vec::new();
+        let mut mask = Vec::new();
+        let mut offset: Option<(usize, usize)> = None;
+
+        for (column, token) in tokens(text) {
+            if token == OFFSET_MARKER {
+                if offset.is_some() {
+                    return Err(SignatureError::DuplicateOffset { column });
+                }
+
+                offset = Some((bytes.len(), column));
+                continue;
+            }
+
+            let (byte, byte_mask) =
+                parse_byte(token).ok_or_else(|| SignatureError::InvalidToken {
+                    token: token.to_string(),
+                    column,
+                })?;
+            bytes.push(byte);
+            mask.push(byte_mask);
+        }
+
+        if bytes.is_empty() {
+            return Err(SignatureError::Empty);
+        }
+
+        if mask.iter().all(|&m| m == 0) {
+            return Err(SignatureError::AllWildcards);
+        }
+
+        let offset = match offset {
+            Some((offset, column)) if offset == bytes.len() => {
+                return Err(SignatureError::OffsetPastEnd { column })
+            }
+            Some((offset, _)) => offset,
+            None => 0,
+        };
+
+        Ok(Self {
+            pattern: Pattern::new(bytes, mask),
+            offset,
+        })
+    }
+}
+
+impl FromStr for Signature {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Formats the signature so that it parses back to the same signature.
+/// The offset marker is only written if the offset isn't 0.
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.offset == 0 {
+            return write!(f, "{}", self.pattern);
+        }
+
+        // the pattern is formatted with one token per byte
+        let pattern = self.pattern.to_string();
+        let mut tokens = pattern.split(' ');
+        let before = tokens.by_ref().take(self.offset).collect::<Vec<_>>();
+        let after = tokens.collect::<Vec<_>>();
+
+        write!(
+            f,
+            "{} {} {}",
+            before.join(" "),
+            OFFSET_MARKER,
+            after.join(" ")
+        )
+    }
+}
+
+/// Splits `text` on whitespace, returning each token with its 1-based column.
+fn tokens(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.split_whitespace().map(move |token| {
+        let start = token.as_ptr() as usize - text.as_ptr() as usize;
+        (text[..start].chars().count() + 1, token)
+    })
+}
+
+/// Parses a single byte token, returning the byte and its mask.
+fn parse_byte(token: &str) -> Option<(u8, u8)> {
+    fn nibble(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|n| n as u8)
+    }
+
+    if let Some((byte, mask)) = token.split_once('&') {
+        let parse = |hex: &str| (hex.len() == 2).then(|| u8::from_str_radix(hex, 16).ok())?;
+        return Some((parse(byte)?, parse(mask)?));
+    }
+
+    match token.as_bytes() {
+        b"?" | b"??" => Some((0x00, 0x00)),
+        [b'?', low] => Some((nibble(*low)?, 0x0F)),
+        [high, b'?'] => Some((nibble(*high)? << 4, 0xF0)),
+        [high, low] => Some((nibble(*high)? << 4 | nibble(*low)?, 0xFF)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let sig = Signature::parse("E8 ?? ?? ?? ?? 80 7C 24 ?? ?? 74 ?? B8").unwrap();
+        assert_eq!(sig.offset, 0);
+        assert_eq!(sig.pattern.len(), 13);
+        assert_eq!(
+            sig.pattern,
+            Pattern::from_byte_mask(
+                &[0xE8, 0, 0, 0, 0, 0x80, 0x7C, 0x24, 0, 0, 0x74, 0, 0xB8],
+                &[1, 0, 0, 0, 0, 1, 1, 1, 0, 0, 1, 0, 1]
+            )
+        );
+
+        // nibbles, masks, single-character wildcards, and extra whitespace
+        let sig: Signature = "  4? 8b\t?5  ?  48&F8 * ?? ?? ?? ?? ".parse().unwrap();
+        assert_eq!(sig.offset, 5);
+        assert_eq!(
+            sig.pattern,
+            Pattern::new(
+                vec![0x40, 0x8B, 0x05, 0x00, 0x48, 0, 0, 0, 0],
+                vec![0xF0, 0xFF, 0x0F, 0x00, 0xF8, 0, 0, 0, 0]
+            )
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for text in [
+            "E8 ?? ?? ?? ?? C3",
+            "48 8D 0D * ?? ?? ?? ?? 4? ?B",
+            "40&F8 * 8B",
+        ] {
+            let sig = Signature::parse(text).unwrap();
+            assert_eq!(sig.to_string(), text);
+            assert_eq!(Signature::parse(&sig.to_string()), Ok(sig));
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("", SignatureError::Empty),
+            ("  * ", SignatureError::Empty),
+            ("?? ??", SignatureError::AllWildcards),
+            (
+                "48 8G",
+                SignatureError::InvalidToken {
+                    token: "8G".to_string(),
+                    column: 4,
+                },
+            ),
+            (
+                "48 *8B",
+                SignatureError::InvalidToken {
+                    token: "*8B".to_string(),
+                    column: 4,
+                },
+            ),
+            (
+                "488B",
+                SignatureError::InvalidToken {
+                    token: "488B".to_string(),
+                    column: 1,
+                },
+            ),
+            (
+                "48 8B&F",
+                SignatureError::InvalidToken {
+                    token: "8B&F".to_string(),
+                    column: 4,
+                },
+            ),
+            (
+                "48 * 8B * 05",
+                SignatureError::DuplicateOffset { column: 9 },
+            ),
+            ("48 8B *", SignatureError::OffsetPastEnd { column: 7 }),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(Signature::parse(text), Err(expected), "parsing {:?}", text);
+        }
+    }
+}
@@ -0,0 +1,191 @@
+//! Symbols in other modules, for resolving against export and import tables
+//! rather than by signature. Written as `module!name` or `module!#ordinal`,
+//! e.g. `dxgi.dll!CreateDXGIFactory1`.
+
+use std::{fmt, str::FromStr};
+
+use crate::pe::{Export, Import};
+
+/// A function or variable exported by a module, by name or ordinal.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    Name(String),
+    Ordinal(u32),
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Name(name) => write!(f, "{}", name),
+            Symbol::Ordinal(ordinal) => write!(f, "#{}", ordinal),
+        }
+    }
+}
+
+/// A symbol and the module it belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ModuleSymbol {
+    pub module: String,
+    pub symbol: Symbol,
+}
+
+/// Reasons a module symbol can fail to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolParseError {
+    /// The text isn't of the form `module!symbol`.
+    Malformed(String),
+    /// The symbol starts with `#`, but isn't followed by a decimal ordinal.
+    InvalidOrdinal(String),
+}
+
+impl fmt::Display for SymbolParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolParseError::Malformed(text) => {
+                write!(f, "expected module!symbol, got {:?}", text)
+            }
+            SymbolParseError::InvalidOrdinal(text) => write!(f, "invalid ordinal {:?}", text),
+        }
+    }
+}
+
+impl std::error::Error for SymbolParseError {}
+
+impl FromStr for Symbol {
+    type Err = SymbolParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('#') {
+            Some(ordinal) => ordinal
+                .parse()
+                .map(Symbol::Ordinal)
+                .map_err(|_| SymbolParseError::InvalidOrdinal(s.to_string())),
+            None if s.is_empty() => Err(SymbolParseError::Malformed(s.to_string())),
+            None => Ok(Symbol::Name(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for ModuleSymbol {
+    type Err = SymbolParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (module, symbol) = s
+            .trim()
+            .split_once('!')
+            .filter(|(module, symbol)| !module.is_empty() && !symbol.is_empty())
+            .ok_or_else(|| SymbolParseError::Malformed(s.to_string()))?;
+
+        Ok(ModuleSymbol {
+            module: module.to_string(),
+            symbol: symbol.parse()?,
+        })
+    }
+}
+
+impl fmt::Display for ModuleSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}!{}", self.module, self.symbol)
+    }
+}
+
+impl ModuleSymbol {
+    /// Parses where a forwarded export leads, e.g. `NTDLL.RtlAllocateHeap` or
+    /// `NTDLL.#12`, where the module is named without its `.dll` extension.
+    pub fn from_forwarder(forwarder: &str) -> Option<Self> {
+        // module names can contain dots (api-ms-win-core-synch-l1-2-0), symbols can't
+        let (module, symbol) = forwarder.rsplit_once('.')?;
+        if module.is_empty() {
+            return None;
+        }
+
+        Some(ModuleSymbol {
+            module: format!("{}.dll", module),
+            symbol: symbol.parse().ok()?,
+        })
+    }
+}
+
+/// Returns true if two module names refer to the same module, ignoring case and
+/// a missing `.dll` extension, as the loader does.
+pub fn same_module(a: &str, b: &str) -> bool {
+    let normalize = |name: &str| {
+        let name = name.to_ascii_lowercase();
+        if name.contains('.') {
+            name
+        } else {
+            name + ".dll"
+        }
+    };
+
+    normalize(a) == normalize(b)
+}
+
+/// Finds an export by name or ordinal.
+pub fn find_export<'a>(exports: &'a [Export], symbol: &Symbol) -> Option<&'a Export> {
+    exports.iter().find(|export| match symbol {
+        Symbol::Name(name) => export.name.as_deref() == Some(name.as_str()),
+        Symbol::Ordinal(ordinal) => export.ordinal == *ordinal,
+    })
+}
+
+/// Finds where a symbol imported from a module is bound.
+pub fn find_import<'a>(imports: &'a [Import], module: &str, symbol: &Symbol) -> Option<&'a Import> {
+    imports
+        .iter()
+        .find(|import| import.symbol == *symbol && same_module(&import.module, module))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let symbol: ModuleSymbol = "dxgi.dll!CreateDXGIFactory1".parse().unwrap();
+        assert_eq!(symbol.module, "dxgi.dll");
+        assert_eq!(
+            symbol.symbol,
+            Symbol::Name("CreateDXGIFactory1".to_string())
+        );
+        assert_eq!(symbol.to_string(), "dxgi.dll!CreateDXGIFactory1");
+
+        let symbol: ModuleSymbol = " d3d11.dll!#12 ".parse().unwrap();
+        assert_eq!(symbol.symbol, Symbol::Ordinal(12));
+        assert_eq!(symbol.to_string(), "d3d11.dll!#12");
+
+        for text in ["CreateDXGIFactory1", "!Sleep", "kernel32.dll!"] {
+            assert_eq!(
+                text.parse::<ModuleSymbol>(),
+                Err(SymbolParseError::Malformed(text.to_string()))
+            );
+        }
+        assert_eq!(
+            "d3d11.dll!#x".parse::<ModuleSymbol>(),
+            Err(SymbolParseError::InvalidOrdinal("#x".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_forwarders() {
+        assert_eq!(
+            ModuleSymbol::from_forwarder("NTDLL.RtlAllocateHeap"),
+            Some(ModuleSymbol {
+                module: "NTDLL.dll".to_string(),
+                symbol: Symbol::Name("RtlAllocateHeap".to_string()),
+            })
+        );
+        assert_eq!(
+            ModuleSymbol::from_forwarder("api-ms-win-core-synch-l1-2-0.#7"),
+            Some(ModuleSymbol {
+                module: "api-ms-win-core-synch-l1-2-0.dll".to_string(),
+                symbol: Symbol::Ordinal(7),
+            })
+        );
+        assert_eq!(ModuleSymbol::from_forwarder("RtlAllocateHeap"), None);
+
+        assert!(same_module("KERNEL32.dll", "kernel32.DLL"));
+        assert!(same_module("kernel32", "Kernel32.dll"));
+        assert!(!same_module("kernel32.dll", "kernelbase.dll"));
+    }
+}
@@ -0,0 +1,422 @@
+//! Finds functions by the string literals they reference, for functions that are
+//! easier to identify by a log message or format string than by their code.
+
+use std::{fmt, ops::Range};
+
+use iced_x86::{Decoder, DecoderOptions};
+use memchr::memmem;
+
+use crate::pe::{PeImage, RuntimeFunction};
+
+/// The longest an x86-64 instruction can be.
+const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// Set in an unwind info's flags when it continues another function's unwind info.
+const UNW_FLAG_CHAININFO: u8 = 0x4;
+/// How many chained unwind infos to follow before giving up.
+const MAX_CHAIN_LEN: usize = 32;
+
+/// How far back to look for padding when a function has no unwind information.
+const MAX_LEAF_FUNCTION_LEN: usize = 0x1000;
+
+/// Reasons a string reference can't be resolved.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XrefError {
+    /// The image has no `.text` or `.rdata` section.
+    MissingSection(&'static str),
+    /// The string isn't in `.rdata`.
+    StringNotFound,
+    /// Nothing in `.text` references the string.
+    NoReferences,
+    /// The string is referenced, but not from anywhere that looks like a function.
+    NoFunction,
+    /// The string is referenced from more than one function.
+    Ambiguous { functions: Vec<usize> },
+}
+
+impl fmt::Display for XrefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XrefError::MissingSection(name) => write!(f, "image has no {} section", name),
+            XrefError::StringNotFound => write!(f, "string not found in .rdata"),
+            XrefError::NoReferences => write!(f, "string isn't referenced from .text"),
+            XrefError::NoFunction => {
+                write!(f, "couldn't find the functions referencing the string")
+            }
+            XrefError::Ambiguous { functions } => {
+                let functions: Vec<_> = functions.iter().map(|f| format!("0x{:X}", f)).collect();
+                write!(
+                    f,
+                    "string is referenced from {} functions: {}",
+                    functions.len(),
+                    functions.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for XrefError {}
+
+/// Resolves a string to the start of the one function that references it.
+/// `pe` must have been parsed from a mapped image.
+pub fn resolve_string_xref(pe: &PeImage, string: &str) -> Result<usize, XrefError> {
+    let mut functions = functions_referencing(pe, string)?;
+    match functions.len() {
+        0 => Err(XrefError::NoFunction),
+        1 => Ok(functions.remove(0)),
+        _ => Err(XrefError::Ambiguous { functions }),
+    }
+}
+
+/// Returns the start of every function referencing a string, sorted and deduplicated.
+/// `pe` must have been parsed from a mapped image.
+pub fn functions_referencing(pe: &PeImage, string: &str) -> Result<Vec<usize>, XrefError> {
+    let image = pe.data();
+    let text = section_range(pe, ".text")?;
+    let rdata = section_range(pe, ".rdata")?;
+
+    let strings = find_strings(image, rdata, string);
+    if strings.is_empty() {
+        return Err(XrefError::StringNotFound);
+    }
+
+    let references = find_references(image, text.clone(), &strings);
+    if references.is_empty() {
+        return Err(XrefError::NoReferences);
+    }
+
+    let runtime_functions = pe.runtime_functions().unwrap_or_default();
+    let mut functions: Vec<usize> = references
+        .into_iter()
+        .filter_map(|rva| function_start(pe, &runtime_functions, text.clone(), rva))
+        .collect();
+    functions.sort_unstable();
+    functions.dedup();
+
+    Ok(functions)
+}
+
+pub(crate) fn section_range(pe: &PeImage, name: &'static str) -> Result<Range<usize>, XrefError> {
+    let section = pe.section(name).ok_or(XrefError::MissingSection(name))?;
+    let start = section.virtual_address as usize;
+    Ok(start..start + pe.section_data(section).len())
+}
+
+/// Finds every null-terminated occurrence of `string` in `range`, returning their RVAs.
+pub fn find_strings(image: &[u8], range: Range<usize>, string: &str) -> Vec<usize> {
+    let Some(haystack) = image.get(range.clone()) else {
+        return Vec::new();
+    };
+
+    let mut needle = string.as_bytes().to_vec();
+    needle.push(0);
+    memmem::find_iter(haystack, &needle)
+        .map(|pos| range.start + pos)
+        .collect()
+}
+
+/// Finds every instruction in `text` with a RIP-relative operand referencing one of
+/// `targets`, returning the RVAs of the instructions.
+pub fn find_references(image: &[u8], text: Range<usize>, targets: &[usize]) -> Vec<usize> {
+    let Some(code) = image.get(text.clone()) else {
+        return Vec::new();
+    };
+
+    let mut references = Vec::new();
+    for at in 0..code.len().saturating_sub(3) {
+        let displacement = i32::from_le_bytes(code[at..at + 4].try_into().unwrap()) as isize;
+        let after = (text.start + at + 4) as isize + displacement;
+
+        // the displacement may be followed by an immediate of up to 4 bytes, so the
+        // target could be that far past the end of the displacement
+        let is_candidate = targets
+            .iter()
+            .any(|&target| matches!(target as isize - after, 0 | 1 | 2 | 4));
+        if !is_candidate {
+            continue;
+        }
+
+        if let Some(rva) =
+            instruction_with_displacement(image, text.start, text.start + at, targets)
+        {
+            references.push(rva);
+        }
+    }
+
+    references
+}
+
+/// Looks for the instruction whose RIP-relative displacement is at `at`, and references
+/// one of `targets`, returning its RVA.
+fn instruction_with_displacement(
+    image: &[u8],
+    text_start: usize,
+    at: usize,
+    targets: &[usize],
+) -> Option<usize> {
+    let references_target = |start: usize| {
+        let code = &image[start..(start + MAX_INSTRUCTION_LEN).min(image.len())];
+        let mut decoder = Decoder::with_ip(64, code, start as u64, DecoderOptions::NONE);
+        let instruction = decoder.decode();
+        if instruction.is_invalid() || !instruction.is_ip_rel_memory_operand() {
+            return false;
+        }
+
+        let offsets = decoder.get_constant_offsets(&instruction);
+        offsets.has_displacement()
+            && start + offsets.displacement_offset() == at
+            && targets.contains(&(instruction.ip_rel_memory_address() as usize))
+    };
+
+    // the displacement comes after at least an opcode and ModRM byte
+    let earliest = at.saturating_sub(MAX_INSTRUCTION_LEN - 4).max(text_start);
+    let mut start = (earliest..at.saturating_sub(1))
+        .rev()
+        .find(|&start| references_target(start))?;
+
+    // prefixes are optional, so the instruction decodes without them too; take them
+    // in, but not a second REX prefix, which would be the end of another instruction
+    let is_rex = |byte: u8| byte & 0xF0 == 0x40;
+    while start > earliest
+        && !(is_rex(image[start - 1]) && is_rex(image[start]))
+        && references_target(start - 1)
+    {
+        start -= 1;
+    }
+
+    Some(start)
+}
+
+/// Finds the start of the function containing `rva`, using its unwind information.
+/// Leaf functions may not have any, in which case the start is taken to be just after
+/// the nearest `int3` padding before `rva`, at 16-byte alignment.
+pub fn function_start(
+    pe: &PeImage,
+    functions: &[RuntimeFunction],
+    text: Range<usize>,
+    rva: usize,
+) -> Option<usize> {
+    match containing_function(functions, rva) {
+        Some(function) => primary_function(pe, function).map(|f| f.begin as usize),
+        None => {
+            let image = pe.data();
+            let earliest = rva
+                .saturating_sub(MAX_LEAF_FUNCTION_LEN)
+                .max(text.start + 1);
+            (earliest..=rva)
+                .rev()
+                .find(|&start| start % 16 == 0 && image.get(start - 1) == Some(&0xCC))
+        }
+    }
+}
+
+/// Finds the runtime function, or fragment of one, containing `rva`.
+/// `functions` must be sorted by start RVA.
+pub fn containing_function(functions: &[RuntimeFunction], rva: usize) -> Option<RuntimeFunction> {
+    let index = functions.partition_point(|f| f.begin as usize <= rva);
+    index
+        .checked_sub(1)
+        .map(|i| functions[i])
+        .filter(|f| rva < f.end as usize)
+}
+
+/// Follows chained unwind information back to the function a fragment belongs to,
+/// as the compiler may split cold paths out from the rest of the function.
+fn primary_function(pe: &PeImage, mut function: RuntimeFunction) -> Option<RuntimeFunction> {
+    for _ in 0..MAX_CHAIN_LEN {
+        // UNWIND_INFO: version and flags, prolog size, code count, frame register
+        let header = pe.read_rva(function.unwind_info, 4)?;
+        if header[0] >> 3 & UNW_FLAG_CHAININFO == 0 {
+            return Some(function);
+        }
+
+        // the chained entry follows the unwind codes, which are padded to an even count
+        let codes = (header[2] as u32 + 1) & !1;
+        let chained = pe.read_rva(function.unwind_info.checked_add(4 + codes * 2)?, 12)?;
+        let field = |i: usize| u32::from_le_bytes(chained[i * 4..i * 4 + 4].try_into().unwrap());
+        function = RuntimeFunction {
+            begin: field(0),
+            end: field(1),
+            unwind_info: field(2),
+        };
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pe::{
+        map_image,
+        tests::{sample_pe, RDATA, TEXT},
+        Layout,
+    };
+
+    const HELLO: usize = 0x2010;
+    const LEAF: usize = 0x2020;
+    const SHARED: usize = 0x2000;
+
+    /// Writes an instruction with a RIP-relative displacement at `disp` bytes into it,
+    /// followed by `imm` bytes of immediate.
+    fn put_rip_relative(
+        text: &mut [u8],
+        rva: usize,
+        bytes: &[u8],
+        disp: usize,
+        imm: usize,
+        target: usize,
+    ) {
+        let at = rva - 0x1000;
+        text[at..at + bytes.len()].copy_from_slice(bytes);
+
+        let end = rva + bytes.len();
+        let displacement = (target as i32) - (end as i32);
+        text[at + disp..at + disp + 4].copy_from_slice(&displacement.to_le_bytes());
+        assert_eq!(bytes.len(), disp + 4 + imm);
+    }
+
+    fn sample_image() -> Vec<u8> {
+        let mut text = vec![0xCC; 0x100];
+
+        // a function with unwind info, referencing "hello world" twice
+        text[0x10..0x14].copy_from_slice(&[0x48, 0x83, 0xEC, 0x28]);
+        put_rip_relative(
+            &mut text,
+            0x1014,
+            &[0x48, 0x8D, 0x0D, 0, 0, 0, 0],
+            3,
+            0,
+            HELLO,
+        );
+        put_rip_relative(
+            &mut text,
+            0x101B,
+            &[0x80, 0x3D, 0, 0, 0, 0, 0x00],
+            2,
+            1,
+            HELLO,
+        );
+        text[0x22..0x27].copy_from_slice(&[0x48, 0x83, 0xC4, 0x28, 0xC3]);
+
+        // a leaf function without unwind info
+        put_rip_relative(
+            &mut text,
+            0x1040,
+            &[0x48, 0x8D, 0x05, 0, 0, 0, 0],
+            3,
+            0,
+            LEAF,
+        );
+        put_rip_relative(
+            &mut text,
+            0x1047,
+            &[0x48, 0x8D, 0x0D, 0, 0, 0, 0],
+            3,
+            0,
+            SHARED,
+        );
+        text[0x4E] = 0xC3;
+
+        // a cold path split from the first function
+        put_rip_relative(
+            &mut text,
+            0x1060,
+            &[0x48, 0x8D, 0x15, 0, 0, 0, 0],
+            3,
+            0,
+            HELLO,
+        );
+        put_rip_relative(
+            &mut text,
+            0x1067,
+            &[0x48, 0x8D, 0x0D, 0, 0, 0, 0],
+            3,
+            0,
+            SHARED,
+        );
+        text[0x6E] = 0xC3;
+
+        let mut rdata = vec![0u8; 0x40];
+        for (rva, string) in [
+            (SHARED, &b"shared"[..]),
+            (HELLO, b"hello world"),
+            (LEAF, b"leaf"),
+            (0x2030, b"nobody"),
+        ] {
+            rdata[rva - 0x2000..rva - 0x2000 + string.len()].copy_from_slice(string);
+        }
+
+        // runtime functions for the first function and its cold path, whose unwind
+        // info is chained to the first function's
+        let mut pdata = Vec::new();
+        for value in [0x1010u32, 0x1027, 0x3100, 0x1060, 0x106F, 0x3110] {
+            pdata.extend_from_slice(&value.to_le_bytes());
+        }
+        pdata.resize(0x100, 0);
+        pdata.extend_from_slice(&[0x01, 0x04, 0x01, 0x00, 0x04, 0x42, 0x00, 0x00]);
+        pdata.resize(0x110, 0);
+        pdata.extend_from_slice(&[0x21, 0x00, 0x00, 0x00]);
+        for value in [0x1010u32, 0x1027, 0x3100] {
+            pdata.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut file = sample_pe(
+            true,
+            &[
+                (".text", 0x1000, &text, TEXT),
+                (".rdata", 0x2000, &rdata, RDATA),
+                (".pdata", 0x3000, &pdata, RDATA),
+            ],
+        );
+        // the exception directory
+        file[0x120..0x124].copy_from_slice(&0x3000u32.to_le_bytes());
+        file[0x124..0x128].copy_from_slice(&24u32.to_le_bytes());
+
+        map_image(&file).unwrap()
+    }
+
+    #[test]
+    fn test_references() {
+        let image = sample_image();
+
+        assert_eq!(find_strings(&image, 0x2000..0x2040, "hello world"), [HELLO]);
+        // suffixes of other strings count, as the linker may merge them
+        assert_eq!(find_strings(&image, 0x2000..0x2040, "world"), [HELLO + 6]);
+        assert_eq!(find_strings(&image, 0x2000..0x2040, "hello"), []);
+
+        assert_eq!(
+            find_references(&image, 0x1000..0x1100, &[HELLO]),
+            [0x1014, 0x101B, 0x1060]
+        );
+        assert_eq!(
+            find_references(&image, 0x1000..0x1100, &[LEAF, SHARED]),
+            [0x1040, 0x1047, 0x1067]
+        );
+    }
+
+    #[test]
+    fn test_resolve_string_xref() {
+        let image = sample_image();
+        let pe = PeImage::parse(&image, Layout::Mapped).unwrap();
+
+        // referenced from the cold path too, which belongs to the same function
+        assert_eq!(resolve_string_xref(&pe, "hello world"), Ok(0x1010));
+        assert_eq!(resolve_string_xref(&pe, "leaf"), Ok(0x1040));
+        assert_eq!(
+            resolve_string_xref(&pe, "shared"),
+            Err(XrefError::Ambiguous {
+                functions: vec![0x1010, 0x1040]
+            })
+        );
+        assert_eq!(
+            resolve_string_xref(&pe, "nobody"),
+            Err(XrefError::NoReferences)
+        );
+        assert_eq!(
+            resolve_string_xref(&pe, "missing"),
+            Err(XrefError::StringNotFound)
+        );
+    }
+}
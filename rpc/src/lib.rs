@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 
+pub mod resolver;
 pub mod ui;
 
 #[derive(Debug, PartialEq, Deserialize, Serialize)]
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+
+/// The outcome of resolving every signature the LLRT needed, written to the
+/// runtime directory and sent to the HLRT on request.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ResolutionReport {
+    pub signatures: Vec<SignatureResolution>,
+    /// Addresses that Grebuloff can't run without, and that failed to resolve.
+    pub critical_failures: Vec<String>,
+    /// How long resolution took during init, in milliseconds.
+    pub elapsed_ms: u64,
+    /// Pointer paths declared in the resolver options, evaluated when the report was made.
+    pub pointer_paths: Vec<PointerPathResolution>,
+}
+
+impl ResolutionReport {
+    /// Returns the signatures that weren't found.
+    pub fn failures(&self) -> impl Iterator<Item = &SignatureResolution> {
+        self.signatures
+            .iter()
+            .filter(|sig| sig.status == ResolutionStatus::NotFound)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SignatureResolution {
+    pub kind: SignatureKind,
+    /// The signature, as space-separated hex bytes with `??` for wildcards.
+    pub signature: String,
+    pub status: ResolutionStatus,
+    /// Where the address came from, if the signature was resolved.
+    pub source: Option<ResolutionSource>,
+    /// The address the signature resolved to, if it wasn't null.
+    pub address: Option<u64>,
+    /// How many times the signature matched, if it was scanned for. Matches past the
+    /// first are only counted when ambiguity detection or strict mode is enabled.
+    #[serde(default)]
+    pub matches: Option<u32>,
+    /// How long resolution took, in microseconds.
+    pub elapsed_us: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct PointerPathResolution {
+    pub name: String,
+    pub path: String,
+    /// The address the path led to, if every step succeeded.
+    pub address: Option<u64>,
+    /// Why the path couldn't be parsed, resolved or evaluated.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum SignatureKind {
+    VTable,
+    StaticAddress,
+    MemberFunction,
+    /// The function referencing a string literal, which stands in for the signature.
+    StringReference,
+    /// A symbol exported by a module, as `module!name` or `module!#ordinal`.
+    Export,
+    /// The import address table slot through which the game calls a symbol in another module.
+    Import,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ResolutionStatus {
+    Resolved,
+    /// The signature was found, but the pointer it refers to hasn't been set yet.
+    /// Not a failure, as some pointers are only set once the game has started.
+    NullPointer,
+    NotFound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ResolutionSource {
+    /// Found by scanning the game executable.
+    Scan,
+    /// Taken from the address database.
+    Database,
+    /// Resolved by the Dalamud support plugin.
+    Dalamud,
+    /// Looked up in a module's export or import table.
+    SymbolTable,
+}
+
+/// A request to scan the game executable, for data that scripts and addons need
+/// but that the LLRT doesn't resolve itself.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ScanRequest {
+    /// Chosen by the requester, and included in the result to match it up.
+    pub id: u32,
+    pub pattern: String,
+    pub pattern_kind: ScanPatternKind,
+    pub section: ScanSection,
+    /// The most matches to return. The LLRT may return fewer, up to a limit of its own.
+    pub max_results: u32,
+    /// Reuse the result of an identical earlier scan. Results for `.data` may be out
+    /// of date, as it changes while the game runs.
+    pub use_cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ScanPatternKind {
+    /// A signature, as space-separated hex bytes with `??` for wildcards. Matches are
+    /// reported at the `*` marker, if there is one.
+    Signature,
+    /// The exact UTF-8 bytes of the pattern, without a null terminator.
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ScanSection {
+    Text,
+    Data,
+    Rdata,
+}
+
+/// Sent in response to a `ScanRequest`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ScanResult {
+    pub id: u32,
+    pub matches: Vec<ScanMatch>,
+    /// Whether there were more matches than were returned.
+    pub truncated: bool,
+    /// Whether the result came from an earlier scan.
+    pub cached: bool,
+    /// Why the scan couldn't be run, in which case there are no matches.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScanMatch {
+    pub rva: u64,
+    pub address: u64,
+}
+
+/// A request for everything referring to a function in the game executable,
+/// for working out how it's used.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CallersRequest {
+    /// Chosen by the requester, and included in the result to match it up.
+    pub id: u32,
+    /// The RVA of the function.
+    pub rva: u64,
+    /// Also look for pointers to the function in `.rdata`, such as vtable slots.
+    pub include_vtables: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum CallerKind {
+    /// A direct `call`.
+    Call,
+    /// A direct `jmp`, such as a tail call.
+    Jump,
+    /// A pointer in `.rdata`, such as a vtable slot.
+    VTableSlot,
+}
+
+/// Sent in response to a `CallersRequest`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct CallersResult {
+    pub id: u32,
+    pub callers: Vec<Caller>,
+    /// Why the callers couldn't be found, in which case there are none.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Caller {
+    pub kind: CallerKind,
+    /// The RVA of the instruction or pointer.
+    pub rva: u64,
+    pub address: u64,
+    /// The RVA of the function containing the instruction, if it could be found.
+    pub function: Option<u64>,
+}
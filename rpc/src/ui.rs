@@ -1,11 +1,46 @@
-use super::{RpcClientboundMessage, RpcServerboundMessage};
-use anyhow::{bail, Result};
+use super::{
+    resolver::{CallersRequest, CallersResult, ResolutionReport, ScanRequest, ScanResult},
+    RpcClientboundMessage, RpcServerboundMessage,
+};
 use bytes::{Buf, Bytes, BytesMut};
 use serde::Deserialize;
 use serde::Serialize;
+use std::fmt;
+
+/// Size of the header preceding the image data in a raw paint message.
+pub const PAINT_HEADER_SIZE: usize = 5;
+
+/// Maximum width or height accepted in a paint message.
+pub const MAX_PAINT_DIMENSION: u16 = 8192;
 
 #[derive(Debug, PartialEq, Deserialize)]
-pub enum UiRpcServerboundMessage {}
+pub enum UiRpcServerboundMessage {
+    /// Requests that the LLRT write the current UI buffer to a PNG file
+    /// in the runtime directory. Used for debugging rendering issues.
+    ExportSnapshot(UiRpcServerboundExportSnapshot),
+
+    /// Changes how paints that don't match the game window size are adapted.
+    SetScalePolicy(UiRpcServerboundSetScalePolicy),
+
+    /// Changes the user's UI scale setting. Triggers a resize of the UI.
+    SetUiScale(UiRpcServerboundSetUiScale),
+
+    /// Requests how many paints the LLRT has rejected, by reason.
+    /// Answered with `UiRpcClientboundMessage::RejectedPaints`.
+    GetRejectedPaints(UiRpcServerboundGetRejectedPaints),
+
+    /// Requests the LLRT's signature resolution report.
+    /// Answered with `UiRpcClientboundMessage::ResolutionReport`.
+    GetResolutionReport(UiRpcServerboundGetResolutionReport),
+
+    /// Requests a scan of the game executable on behalf of a script or addon.
+    /// Answered with `UiRpcClientboundMessage::ScanResult`.
+    Scan(ScanRequest),
+
+    /// Requests everything that calls a function in the game executable.
+    /// Answered with `UiRpcClientboundMessage::Callers`.
+    FindCallers(CallersRequest),
+}
 
 impl TryFrom<RpcServerboundMessage> for UiRpcServerboundMessage {
     type Error = ();
@@ -32,8 +67,50 @@ pub enum UiRpcClientboundMessage {
     /// Sent when the game window is resized.
     /// Triggers a resize of the UI.
     Resize(UiRpcClientboundResize),
+
+    /// Sent when the game window gains or loses focus.
+    Focus(UiRpcClientboundFocus),
+
+    /// Sent when the game window is minimized or restored.
+    /// The UI should not paint while minimized.
+    Minimize(UiRpcClientboundMinimize),
+
+    /// Sent when the overlay is shown or hidden.
+    /// The UI should not paint while hidden.
+    Visibility(UiRpcClientboundVisibility),
+
+    /// Sent in response to `UiRpcServerboundMessage::GetRejectedPaints`.
+    RejectedPaints(UiRpcClientboundRejectedPaints),
+
+    /// Sent in response to `UiRpcServerboundMessage::GetResolutionReport`.
+    ResolutionReport(ResolutionReport),
+
+    /// Sent in response to `UiRpcServerboundMessage::Scan`.
+    ScanResult(ScanResult),
+
+    /// Sent in response to `UiRpcServerboundMessage::FindCallers`.
+    Callers(CallersResult),
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct UiRpcServerboundExportSnapshot {}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct UiRpcServerboundSetScalePolicy {
+    pub policy: ScalePolicy,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct UiRpcServerboundSetUiScale {
+    pub scale: f32,
 }
 
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct UiRpcServerboundGetRejectedPaints {}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct UiRpcServerboundGetResolutionReport {}
+
 #[derive(Debug, PartialEq)]
 pub struct UiRpcServerboundPaint {
     pub width: u16,
@@ -43,30 +120,188 @@ pub struct UiRpcServerboundPaint {
 }
 
 impl UiRpcServerboundPaint {
-    pub fn from_raw(mut buf: BytesMut) -> Result<Self> {
-        let data = buf.split_off(5).freeze();
+    pub fn from_raw(mut buf: BytesMut) -> Result<Self, PaintError> {
+        if buf.len() < PAINT_HEADER_SIZE {
+            return Err(PaintError::BadHeader { len: buf.len() });
+        }
+
+        let data = buf.split_off(PAINT_HEADER_SIZE).freeze();
 
         // image format is first, so we don't overlap 0x80..=0x8F | 0xDE..=0xDF (msgpack map)
         let format = match buf.get_u8() {
             0 => ImageFormat::BGRA8,
-            _ => bail!("invalid image format"),
+            other => return Err(PaintError::UnknownFormat(other)),
         };
         let width = buf.get_u16_le();
         let height = buf.get_u16_le();
 
-        Ok(Self {
+        let paint = Self {
             width,
             height,
             format,
             data,
-        })
+        };
+        paint.validate()?;
+
+        Ok(paint)
+    }
+
+    /// Checks that the dimensions are sane and that the image data
+    /// is exactly the size described by the dimensions and format.
+    pub fn validate(&self) -> Result<(), PaintError> {
+        if self.width == 0 || self.height == 0 {
+            return Err(PaintError::ZeroDimensions {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        if self.width > MAX_PAINT_DIMENSION || self.height > MAX_PAINT_DIMENSION {
+            return Err(PaintError::OversizedDimensions {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        let expected = self
+            .format
+            .byte_size_of(self.width as usize, self.height as usize);
+        if self.data.len() != expected {
+            return Err(PaintError::SizeMismatch {
+                expected,
+                actual: self.data.len(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Reasons a paint message can be rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaintError {
+    /// The message is too short to contain a paint header.
+    BadHeader { len: usize },
+    /// The image format byte is not a known `ImageFormat`.
+    UnknownFormat(u8),
+    /// The width or height is zero.
+    ZeroDimensions { width: u16, height: u16 },
+    /// The width or height exceeds `MAX_PAINT_DIMENSION`.
+    OversizedDimensions { width: u16, height: u16 },
+    /// The image data does not match the size implied by the header.
+    SizeMismatch { expected: usize, actual: usize },
+}
+
+impl fmt::Display for PaintError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaintError::BadHeader { len } => write!(
+                f,
+                "paint message too short for header ({} < {} bytes)",
+                len, PAINT_HEADER_SIZE
+            ),
+            PaintError::UnknownFormat(format) => write!(f, "invalid image format {}", format),
+            PaintError::ZeroDimensions { width, height } => {
+                write!(f, "paint has zero dimensions ({}x{})", width, height)
+            }
+            PaintError::OversizedDimensions { width, height } => write!(
+                f,
+                "paint dimensions {}x{} exceed the maximum of {}",
+                width, height, MAX_PAINT_DIMENSION
+            ),
+            PaintError::SizeMismatch { expected, actual } => write!(
+                f,
+                "paint data size mismatch (expected {} bytes, got {})",
+                expected, actual
+            ),
+        }
     }
 }
 
+impl std::error::Error for PaintError {}
+
+/// The UI is always painted at the physical size given by `width` and `height`.
+/// Its content is laid out at a logical size of the physical size divided by
+/// `dpi_scale * ui_scale`, so one logical pixel covers that many physical pixels.
 #[derive(Debug, PartialEq, Serialize)]
 pub struct UiRpcClientboundResize {
+    /// Physical width of the game window, in pixels.
     pub width: u32,
+    /// Physical height of the game window, in pixels.
     pub height: u32,
+    /// Scale factor of the display the game window is on (1.0 = 96 DPI).
+    pub dpi_scale: f32,
+    /// The user's UI scale setting, applied on top of `dpi_scale`.
+    pub ui_scale: f32,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UiRpcClientboundFocus {
+    pub focused: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UiRpcClientboundMinimize {
+    pub minimized: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UiRpcClientboundVisibility {
+    pub visible: bool,
+}
+
+/// How many paints the LLRT has rejected since it started, by reason.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct UiRpcClientboundRejectedPaints {
+    pub bad_header: u64,
+    pub unknown_format: u64,
+    /// Zero or oversized dimensions.
+    pub bad_dimensions: u64,
+    pub size_mismatch: u64,
+}
+
+/// Determines how a UI paint is adapted when its size doesn't match the
+/// size of the overlay, such as while the HLRT catches up with a resize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ScalePolicy {
+    pub mode: ScaleMode,
+    /// Where the paint is placed when using `ScaleMode::Letterbox`.
+    pub anchor: Anchor,
+}
+
+impl Default for ScalePolicy {
+    fn default() -> Self {
+        Self {
+            mode: ScaleMode::Letterbox,
+            anchor: Anchor::TopLeft,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ScaleMode {
+    /// Drop mismatched paints entirely.
+    Skip,
+    /// Stretch the paint to fit using nearest-neighbour sampling.
+    Nearest,
+    /// Stretch the paint to fit using bilinear filtering.
+    Bilinear,
+    /// Place the paint unscaled at the anchor, cropping or leaving
+    /// transparent borders as necessary.
+    Letterbox,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
 }
 
 /// Represents supported image formats.
@@ -87,3 +322,147 @@ impl ImageFormat {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_paint(format: u8, width: u16, height: u16, data_len: usize) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(&[format]);
+        buf.extend_from_slice(&width.to_le_bytes());
+        buf.extend_from_slice(&height.to_le_bytes());
+        buf.extend_from_slice(&vec![0xAB; data_len]);
+        buf
+    }
+
+    /// Simple xorshift PRNG, so the fuzz corpus is deterministic.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_paint_valid() {
+        let paint = UiRpcServerboundPaint::from_raw(raw_paint(0, 3, 2, 24)).unwrap();
+        assert_eq!(paint.width, 3);
+        assert_eq!(paint.height, 2);
+        assert_eq!(paint.format, ImageFormat::BGRA8);
+        assert_eq!(paint.data.len(), 24);
+    }
+
+    #[test]
+    fn test_paint_corpus() {
+        let corpus: &[(BytesMut, PaintError)] = &[
+            (BytesMut::new(), PaintError::BadHeader { len: 0 }),
+            (BytesMut::from(&[0u8][..]), PaintError::BadHeader { len: 1 }),
+            (
+                BytesMut::from(&[0u8, 1, 0, 1][..]),
+                PaintError::BadHeader { len: 4 },
+            ),
+            (raw_paint(1, 1, 1, 4), PaintError::UnknownFormat(1)),
+            (raw_paint(0xFF, 1, 1, 4), PaintError::UnknownFormat(0xFF)),
+            (
+                raw_paint(0, 0, 1, 0),
+                PaintError::ZeroDimensions {
+                    width: 0,
+                    height: 1,
+                },
+            ),
+            (
+                raw_paint(0, 1, 0, 0),
+                PaintError::ZeroDimensions {
+                    width: 1,
+                    height: 0,
+                },
+            ),
+            (
+                raw_paint(0, u16::MAX, 1, 0),
+                PaintError::OversizedDimensions {
+                    width: u16::MAX,
+                    height: 1,
+                },
+            ),
+            (
+                raw_paint(0, 1, MAX_PAINT_DIMENSION + 1, 0),
+                PaintError::OversizedDimensions {
+                    width: 1,
+                    height: MAX_PAINT_DIMENSION + 1,
+                },
+            ),
+            (
+                raw_paint(0, 2, 2, 15),
+                PaintError::SizeMismatch {
+                    expected: 16,
+                    actual: 15,
+                },
+            ),
+            (
+                raw_paint(0, 2, 2, 17),
+                PaintError::SizeMismatch {
+                    expected: 16,
+                    actual: 17,
+                },
+            ),
+            (
+                raw_paint(0, 2, 2, 0),
+                PaintError::SizeMismatch {
+                    expected: 16,
+                    actual: 0,
+                },
+            ),
+        ];
+
+        for (raw, expected) in corpus {
+            assert_eq!(
+                UiRpcServerboundPaint::from_raw(raw.clone()),
+                Err(*expected),
+                "unexpected result for {:?}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn test_paint_fuzz() {
+        let mut rng = XorShift(0x9E3779B97F4A7C15);
+
+        for _ in 0..10_000 {
+            // mostly small dimensions so that valid frames are reasonably likely
+            let len = (rng.next() % 64) as usize;
+            let mut raw = BytesMut::with_capacity(len);
+            for _ in 0..len {
+                raw.extend_from_slice(&[(rng.next() % 4) as u8]);
+            }
+
+            if let Ok(paint) = UiRpcServerboundPaint::from_raw(raw) {
+                assert!(paint.validate().is_ok());
+                assert_eq!(
+                    paint.data.len(),
+                    paint
+                        .format
+                        .byte_size_of(paint.width as usize, paint.height as usize)
+                );
+            }
+        }
+
+        // mutate single bytes of a valid frame
+        let valid = raw_paint(0, 4, 4, 64);
+        for _ in 0..10_000 {
+            let mut raw = valid.clone();
+            let idx = (rng.next() % raw.len() as u64) as usize;
+            raw[idx] = rng.next() as u8;
+
+            let truncate = (rng.next() % (raw.len() as u64 + 1)) as usize;
+            raw.truncate(truncate);
+
+            let _ = UiRpcServerboundPaint::from_raw(raw);
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use grebuloff_resolver::{
+    generate, resolve,
+    scanner::{self, Pattern},
+    signature::Signature,
+};
+
+use crate::{image::GameImage, resolve_signature, signatures::KnownSignature, Resolution};
+
+/// What happened to a signature between two builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    /// Resolves to the same RVA in both.
+    Unchanged,
+    /// Resolves in both, but to a different RVA.
+    Moved,
+    /// Matched once in the old build, but more than once in the new one.
+    NewlyAmbiguous,
+    /// Resolved in the old build, but not in the new one.
+    Broken,
+    /// Resolved in neither build.
+    StillBroken,
+    /// Didn't resolve in the old build, but does in the new one.
+    Fixed,
+}
+
+impl Change {
+    fn of(old: &Resolution, new: &Resolution) -> Self {
+        match (old.rva, new.rva) {
+            (Some(_), None) => Change::Broken,
+            (None, None) => Change::StillBroken,
+            (_, Some(_)) if new.matches > 1 && old.matches <= 1 => Change::NewlyAmbiguous,
+            (None, Some(_)) => Change::Fixed,
+            (Some(old), Some(new)) if old == new => Change::Unchanged,
+            (Some(_), Some(_)) => Change::Moved,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Change::Unchanged => "unchanged",
+            Change::Moved => "moved",
+            Change::NewlyAmbiguous => "AMBIGUOUS",
+            Change::Broken => "BROKEN",
+            Change::StillBroken => "broken",
+            Change::Fixed => "fixed",
+        }
+    }
+}
+
+/// A replacement for a broken signature, found near where it matched in the old build.
+struct Rederived {
+    /// The RVA of the replacement's match in the new build.
+    match_rva: usize,
+    signature: Signature,
+    /// The RVA the replacement resolves to, if it resolves.
+    rva: Option<usize>,
+}
+
+/// Looks for the code a signature matched in the old build near the same place in
+/// the new one, by matching ever shorter prefixes of the signature within `window`
+/// bytes of `old_match`. Once a prefix matches exactly once, a new unique signature
+/// is generated there.
+fn rederive(
+    new: &GameImage,
+    sig: &KnownSignature,
+    old_match: usize,
+    window: usize,
+) -> Option<Rederived> {
+    let start = old_match.saturating_sub(window).max(new.text.start);
+    let end = old_match.saturating_add(window).min(new.text.end);
+    let region = new.mapped.get(start..end)?;
+
+    // the replacement has to reach the operand at the offset marker, if any
+    let offset = sig.offset.max(0) as usize;
+    let bytes = sig.pattern.bytes();
+    let mask = sig.pattern.mask();
+
+    let match_rva = (offset + 1..sig.pattern.len())
+        .rev()
+        .filter(|&len| mask[len - 1] != 0)
+        .find_map(|len| {
+            let prefix = Pattern::new(bytes[..len].to_vec(), mask[..len].to_vec());
+            let matches: Vec<_> = scanner::find_iter(region, &prefix).take(2).collect();
+            match matches[..] {
+                [] => None,
+                [pos] => Some(Some(start + pos)),
+                // shorter prefixes will only match more, so give up
+                _ => Some(None),
+            }
+        })??;
+
+    let pattern = generate::generate(
+        &new.mapped,
+        new.text.clone(),
+        &new.relocations,
+        match_rva,
+        offset + 1,
+        generate::DEFAULT_MAX_LEN,
+    )
+    .ok()?;
+
+    Some(Rederived {
+        match_rva,
+        signature: Signature { pattern, offset },
+        rva: resolve::resolve_match(&new.mapped, match_rva, sig.kind, sig.offset),
+    })
+}
+
+fn format_rva(rva: Option<usize>) -> String {
+    match rva {
+        Some(rva) => format!("0x{:X}", rva),
+        None => "-".to_string(),
+    }
+}
+
+/// Prints how every signature's resolution changed between two builds, returning
+/// false if any broke or became ambiguous. With a search window, broken signatures
+/// are looked for near where they used to match.
+pub fn print_diff(
+    old: &GameImage,
+    new: &GameImage,
+    signatures: &[KnownSignature],
+    search_window: Option<usize>,
+) -> bool {
+    let mut counts = [0usize; 6];
+
+    for sig in signatures {
+        let old_resolution = resolve_signature(old, sig);
+        let new_resolution = resolve_signature(new, sig);
+        let change = Change::of(&old_resolution, &new_resolution);
+        counts[change as usize] += 1;
+
+        println!(
+            "{:<9} {:<15} {:>10} -> {:<10} {:>4} -> {:<4} {}{}",
+            change.label(),
+            sig.kind,
+            format_rva(old_resolution.rva),
+            format_rva(new_resolution.rva),
+            old_resolution.matches,
+            new_resolution.matches,
+            sig.text,
+            sig.location
+                .as_ref()
+                .map(|location| format!(" ({})", location))
+                .unwrap_or_default(),
+        );
+
+        let (Change::Broken, Some(window), Some(old_match)) =
+            (change, search_window, old_resolution.first)
+        else {
+            continue;
+        };
+
+        match rederive(new, sig, old_match, window) {
+            Some(rederived) => println!(
+                "          re-derived at 0x{:X}, resolving to {}: {}",
+                rederived.match_rva,
+                format_rva(rederived.rva),
+                rederived.signature
+            ),
+            None => println!("          couldn't re-derive near 0x{:X}", old_match),
+        }
+    }
+
+    let [unchanged, moved, ambiguous, broken, still_broken, fixed] = counts;
+    println!(
+        "\n{} signatures: {} unchanged, {} moved, {} newly ambiguous, {} broken, {} still broken, {} fixed \
+        (image timestamps {:08X} -> {:08X})",
+        signatures.len(),
+        unchanged,
+        moved,
+        ambiguous,
+        broken,
+        still_broken,
+        fixed,
+        old.timestamp,
+        new.timestamp
+    );
+
+    broken == 0 && ambiguous == 0
+}
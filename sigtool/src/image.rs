@@ -0,0 +1,47 @@
+use std::{ops::Range, path::Path};
+
+use anyhow::{Context, Result};
+use grebuloff_resolver::pe::{self, Layout, PeImage, Relocation};
+
+/// A game executable read from disk and laid out as it would be in memory,
+/// so that everything can be addressed by RVA.
+pub struct GameImage {
+    pub mapped: Vec<u8>,
+    pub text: Range<usize>,
+    pub timestamp: u32,
+    pub image_base: u64,
+    /// Base relocations, sorted by RVA.
+    pub relocations: Vec<Relocation>,
+}
+
+impl GameImage {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let mapped =
+            pe::map_image(&file).with_context(|| format!("failed to map {}", path.display()))?;
+
+        let pe = PeImage::parse(&mapped, Layout::Mapped)?;
+        let text = pe.section(".text").context("image has no .text section")?;
+        let text_start = text.virtual_address as usize;
+        let text = text_start..text_start + pe.section_data(text).len();
+        let relocations = pe.relocations()?;
+
+        Ok(Self {
+            text,
+            timestamp: pe.timestamp,
+            image_base: pe.image_base,
+            relocations,
+            mapped,
+        })
+    }
+
+    /// Parses the headers of the mapped image.
+    pub fn pe(&self) -> Result<PeImage> {
+        Ok(PeImage::parse(&self.mapped, Layout::Mapped)?)
+    }
+
+    pub fn text(&self) -> &[u8] {
+        &self.mapped[self.text.clone()]
+    }
+}
@@ -0,0 +1,441 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use grebuloff_resolver::{
+    calls::{self, ReferenceKind},
+    generate,
+    pe::{ExportTarget, Layout, PeImage},
+    resolve,
+    rtti::{self, Rtti},
+    scanner,
+    symbol::ModuleSymbol,
+    xref,
+};
+
+use crate::{image::GameImage, signatures::KnownSignature};
+
+mod diff;
+mod image;
+mod signatures;
+
+/// The LLRT sources, searched for inline signatures by default.
+const DEFAULT_SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../src");
+
+#[derive(Parser)]
+struct Args {
+    #[clap(subcommand)]
+    command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Resolves every known signature against a game executable on disk.
+    Resolve {
+        #[clap(short, long)]
+        game_path: PathBuf,
+
+        /// Directory to search for `resolve_signature!` uses.
+        #[clap(short, long, default_value = DEFAULT_SOURCE_DIR)]
+        source_dir: PathBuf,
+
+        /// Treat signatures that match more than once as failures.
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Generates the shortest unique signature for an address in a game executable on disk.
+    Generate {
+        #[clap(short, long)]
+        game_path: PathBuf,
+
+        /// The RVA to generate a signature for, in hex.
+        #[clap(short, long, value_parser = parse_hex)]
+        rva: usize,
+
+        /// The longest signature to try, in bytes.
+        #[clap(long, default_value_t = generate::DEFAULT_MAX_LEN)]
+        max_len: usize,
+    },
+    /// Finds the functions referencing a string in a game executable on disk.
+    Xref {
+        #[clap(short, long)]
+        game_path: PathBuf,
+
+        /// The string, without its null terminator.
+        #[clap(short, long)]
+        string: String,
+    },
+    /// Lists every direct call and jump to a function in a game executable on disk.
+    Callers {
+        #[clap(short, long)]
+        game_path: PathBuf,
+
+        /// The RVA of the function, in hex.
+        #[clap(short, long, value_parser = parse_hex)]
+        rva: usize,
+
+        /// Also list pointers to the function in .rdata, such as vtable slots.
+        #[clap(long)]
+        vtables: bool,
+    },
+    /// Lists the classes found through RTTI in a game executable on disk, with their vtables.
+    Classes {
+        #[clap(short, long)]
+        game_path: PathBuf,
+
+        /// Only list classes whose mangled or demangled name contains this.
+        #[clap(short, long)]
+        filter: Option<String>,
+    },
+    /// Lists the symbols exported by a module on disk, such as a system DLL.
+    Exports {
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// Only list symbols whose name contains this.
+        #[clap(short, long)]
+        filter: Option<String>,
+    },
+    /// Lists the symbols imported by a module on disk, with their import address table slots.
+    Imports {
+        #[clap(short, long)]
+        path: PathBuf,
+
+        /// Only list symbols whose module or name contains this.
+        #[clap(short, long)]
+        filter: Option<String>,
+    },
+    /// Compares how every known signature resolves in two game executables on disk.
+    Diff {
+        #[clap(long)]
+        old_game_path: PathBuf,
+
+        #[clap(long)]
+        new_game_path: PathBuf,
+
+        /// Directory to search for `resolve_signature!` uses.
+        #[clap(short, long, default_value = DEFAULT_SOURCE_DIR)]
+        source_dir: PathBuf,
+
+        /// Look for broken signatures near where they matched in the old executable.
+        #[clap(long)]
+        rederive: bool,
+
+        /// How far either side of the old match to look when re-deriving, in hex.
+        #[clap(long, value_parser = parse_hex, default_value = "100000")]
+        search_window: usize,
+    },
+}
+
+fn parse_hex(text: &str) -> Result<usize, String> {
+    let hex = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    usize::from_str_radix(hex, 16).map_err(|e| e.to_string())
+}
+
+async fn known_signatures(source_dir: &Path) -> Result<Vec<KnownSignature>> {
+    let mut signatures = unsafe { signatures::client_structs().await };
+    signatures.extend(signatures::inline(source_dir)?);
+    Ok(signatures)
+}
+
+/// The outcome of resolving a single signature.
+struct Resolution {
+    /// The number of places the signature matched in .text.
+    matches: usize,
+    /// The RVA of the first match, if there was one.
+    first: Option<usize>,
+    /// The resolved RVA, if the signature matched and its target was valid.
+    rva: Option<usize>,
+}
+
+fn resolve_signature(image: &GameImage, sig: &KnownSignature) -> Resolution {
+    let matches = scanner::count(image.text(), &sig.pattern);
+
+    let first = matches.first.map(|pos| image.text.start + pos);
+
+    Resolution {
+        matches: matches.count,
+        first,
+        rva: first.and_then(|rva| resolve::resolve_match(&image.mapped, rva, sig.kind, sig.offset)),
+    }
+}
+
+/// Prints the resolution of every signature, returning false if any failed to resolve,
+/// or were ambiguous in strict mode.
+fn print_report(image: &GameImage, signatures: &[KnownSignature], strict: bool) -> bool {
+    let (mut resolved, mut failed, mut ambiguous) = (0, 0, 0);
+
+    for sig in signatures {
+        let resolution = resolve_signature(image, sig);
+
+        let status = match resolution.rva {
+            None => {
+                failed += 1;
+                "FAILED"
+            }
+            Some(_) if resolution.matches > 1 => {
+                ambiguous += 1;
+                "AMBIGUOUS"
+            }
+            Some(_) => {
+                resolved += 1;
+                "ok"
+            }
+        };
+
+        let rva = match resolution.rva {
+            Some(rva) => format!("0x{:X}", rva),
+            None => "-".to_string(),
+        };
+
+        // pointers are only set at runtime, so we can only report where they're stored
+        let notes = [
+            sig.is_pointer.then(|| "(pointer)".to_string()),
+            sig.location
+                .as_ref()
+                .map(|location| format!("({})", location)),
+        ];
+
+        println!(
+            "{:<9} {:<15} {:>10} {:>4}  {} {}",
+            status,
+            sig.kind,
+            rva,
+            resolution.matches,
+            sig.text,
+            notes.into_iter().flatten().collect::<Vec<_>>().join(" ")
+        );
+    }
+
+    println!(
+        "\n{} signatures: {} resolved, {} ambiguous, {} failed (image timestamp {:08X}, base 0x{:X})",
+        signatures.len(),
+        resolved,
+        ambiguous,
+        failed,
+        image.timestamp,
+        image.image_base
+    );
+
+    failed == 0 && !(strict && ambiguous > 0)
+}
+
+/// Prints the resolution of every string reference, returning false if any failed.
+fn print_string_references(image: &GameImage, strings: &[(String, String)]) -> Result<bool> {
+    let pe = image.pe()?;
+    let mut failed = 0;
+
+    for (string, location) in strings {
+        match xref::resolve_string_xref(&pe, string) {
+            Ok(rva) => println!(
+                "{:<9} {:<15} {:>10}  {:?} ({})",
+                "ok",
+                "string xref",
+                format!("0x{:X}", rva),
+                string,
+                location
+            ),
+            Err(e) => {
+                failed += 1;
+                println!(
+                    "{:<9} {:<15} {:>10}  {:?} ({}): {}",
+                    "FAILED", "string xref", "-", string, location, e
+                );
+            }
+        }
+    }
+
+    if !strings.is_empty() {
+        println!(
+            "{} string references: {} resolved, {} failed",
+            strings.len(),
+            strings.len() - failed,
+            failed
+        );
+    }
+
+    Ok(failed == 0)
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Commands::Resolve {
+            game_path,
+            source_dir,
+            strict,
+        } => {
+            let image = GameImage::load(&game_path)?;
+            let signatures = known_signatures(&source_dir).await?;
+            let strings = signatures::inline_string_references(&source_dir)?;
+
+            let resolved = print_report(&image, &signatures, strict);
+            if !print_string_references(&image, &strings)? || !resolved {
+                std::process::exit(1);
+            }
+        }
+        Commands::Generate {
+            game_path,
+            rva,
+            max_len,
+        } => {
+            let image = GameImage::load(&game_path)?;
+            let pattern = generate::generate(
+                &image.mapped,
+                image.text.clone(),
+                &image.relocations,
+                rva,
+                1,
+                max_len,
+            )?;
+
+            // check it the same way `resolve` would see it
+            let matches = scanner::count(image.text(), &pattern);
+            let wildcards = pattern.mask().iter().filter(|&&m| m == 0).count();
+            println!("{}", pattern);
+            println!(
+                "\n{} bytes, {} wildcards, {} match in .text at 0x{:X}",
+                pattern.len(),
+                wildcards,
+                matches.count,
+                image.text.start + matches.first.unwrap_or_default()
+            );
+        }
+        Commands::Diff {
+            old_game_path,
+            new_game_path,
+            source_dir,
+            rederive,
+            search_window,
+        } => {
+            let old = GameImage::load(&old_game_path)?;
+            let new = GameImage::load(&new_game_path)?;
+            let signatures = known_signatures(&source_dir).await?;
+
+            let search_window = rederive.then_some(search_window);
+            if !diff::print_diff(&old, &new, &signatures, search_window) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Xref { game_path, string } => {
+            let image = GameImage::load(&game_path)?;
+            let pe = image.pe()?;
+
+            let functions = xref::functions_referencing(&pe, &string)?;
+            for function in &functions {
+                println!("0x{:X}", function);
+            }
+            if functions.len() != 1 {
+                println!("\n{} functions reference {:?}", functions.len(), string);
+            }
+        }
+        Commands::Exports { path, filter } => {
+            let file = read_file(&path)?;
+            let exports = PeImage::parse(&file, Layout::File)?.exports()?;
+
+            for export in exports.iter().filter(|export| match &filter {
+                Some(filter) => export
+                    .name
+                    .as_ref()
+                    .is_some_and(|name| name.contains(filter.as_str())),
+                None => true,
+            }) {
+                let target = match &export.target {
+                    ExportTarget::Rva(rva) => format!("0x{:X}", rva),
+                    ExportTarget::Forwarder(forwarder) => format!("-> {}", forwarder),
+                };
+                println!(
+                    "#{:<5} {:<40} {}",
+                    export.ordinal,
+                    export.name.as_deref().unwrap_or("-"),
+                    target
+                );
+            }
+        }
+        Commands::Imports { path, filter } => {
+            let file = read_file(&path)?;
+            let imports = PeImage::parse(&file, Layout::File)?.imports()?;
+
+            for import in imports {
+                let symbol = ModuleSymbol {
+                    module: import.module,
+                    symbol: import.symbol,
+                }
+                .to_string();
+                if filter
+                    .as_ref()
+                    .is_some_and(|filter| !symbol.contains(filter.as_str()))
+                {
+                    continue;
+                }
+
+                println!("0x{:X}  {}", import.iat_rva, symbol);
+            }
+        }
+        Commands::Callers {
+            game_path,
+            rva,
+            vtables,
+        } => {
+            let image = GameImage::load(&game_path)?;
+            let pe = image.pe()?;
+
+            let callers = calls::find_callers(&pe, rva, vtables.then_some(image.image_base))?;
+            for caller in &callers {
+                let kind = match caller.kind {
+                    ReferenceKind::Call => "call",
+                    ReferenceKind::Jump => "jmp",
+                    ReferenceKind::VTableSlot => "vtable",
+                };
+                match caller.function {
+                    Some(function) => {
+                        println!("0x{:<10X} {:<6} in 0x{:X}", caller.rva, kind, function)
+                    }
+                    None => println!("0x{:<10X} {}", caller.rva, kind),
+                }
+            }
+            println!("\n{} references to 0x{:X}", callers.len(), rva);
+        }
+        Commands::Classes { game_path, filter } => {
+            let image = GameImage::load(&game_path)?;
+            let rtti = Rtti::parse(&image.pe()?, image.image_base);
+
+            let classes = rtti.classes().iter().filter(|class| match &filter {
+                Some(filter) => {
+                    class.mangled_name.contains(filter.as_str())
+                        || class
+                            .name
+                            .as_ref()
+                            .is_some_and(|name| name.contains(filter.as_str()))
+                }
+                None => true,
+            });
+
+            for class in classes {
+                println!("{}", class.name.as_deref().unwrap_or(&class.mangled_name));
+                for vtable in &class.vtables {
+                    println!("    vtable 0x{:X} (+0x{:X})", vtable.rva, vtable.offset);
+                }
+                for base in &class.bases {
+                    let name = rtti::demangle(&base.mangled_name);
+                    println!(
+                        "    base   {} (+0x{:X})",
+                        name.as_deref().unwrap_or(&base.mangled_name),
+                        base.offset
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
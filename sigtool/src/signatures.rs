@@ -0,0 +1,210 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use ffxiv_client_structs::{
+    MemberFunctionSignature, Signature, StaticAddressSignature, VTableSignature,
+};
+use grebuloff_resolver::{resolve::SignatureKind, scanner::Pattern, signature};
+
+/// The macro used by the LLRT to resolve signatures inline.
+const INLINE_MACRO: &str = "resolve_signature!(";
+
+/// The function used by the LLRT to resolve functions by the strings they reference.
+const INLINE_STRING_XREF: &str = "resolve_string_xref(";
+
+/// A signature the LLRT will try to resolve.
+pub struct KnownSignature {
+    pub kind: SignatureKind,
+    pub text: String,
+    pub pattern: Pattern,
+    pub offset: isize,
+    pub is_pointer: bool,
+    /// Where the signature is declared, if it is not from FFXIVClientStructs.
+    pub location: Option<String>,
+}
+
+static COLLECTED: Mutex<Vec<KnownSignature>> = Mutex::new(Vec::new());
+
+fn collect(kind: SignatureKind, sig: &Signature, offset: isize, is_pointer: bool) -> *const u8 {
+    COLLECTED.lock().unwrap().push(KnownSignature {
+        kind,
+        text: sig.to_string(),
+        pattern: Pattern::from_byte_mask(&sig.bytes, &sig.mask),
+        offset,
+        is_pointer,
+        location: None,
+    });
+
+    std::ptr::null()
+}
+
+unsafe fn collect_vtable(input: &VTableSignature) -> *const u8 {
+    collect(
+        SignatureKind::VTable,
+        &input.signature,
+        input.offset,
+        input.is_pointer,
+    )
+}
+
+unsafe fn collect_static_address(input: &StaticAddressSignature) -> *const u8 {
+    collect(
+        SignatureKind::StaticAddress,
+        &input.signature,
+        input.offset,
+        input.is_pointer,
+    )
+}
+
+unsafe fn collect_member_function(input: &MemberFunctionSignature) -> *const u8 {
+    collect(SignatureKind::MemberFunction, &input.signature, 0, false)
+}
+
+/// Collects every signature declared by FFXIVClientStructs, by running its resolver
+/// with callbacks that record each signature instead of resolving it.
+pub async unsafe fn client_structs() -> Vec<KnownSignature> {
+    ffxiv_client_structs::resolve_all_async(
+        collect_vtable,
+        collect_static_address,
+        collect_member_function,
+    )
+    .await;
+
+    std::mem::take(&mut *COLLECTED.lock().unwrap())
+}
+
+/// Finds every `resolve_signature!` use in the Rust sources under `dir`.
+pub fn inline(dir: &Path) -> Result<Vec<KnownSignature>> {
+    string_arguments(dir, INLINE_MACRO)?
+        .into_iter()
+        .map(|(mut literals, location)| {
+            // the macro takes the signature last, optionally after a name
+            let text = literals
+                .pop()
+                .with_context(|| format!("{}: expected a string literal", location))?;
+            let location = match literals.pop() {
+                Some(name) => format!("{} at {}", name, location),
+                None => location,
+            };
+            let sig = signature::Signature::parse(&text)
+                .with_context(|| format!("{}: invalid signature {:?}", location, text))?;
+
+            Ok(KnownSignature {
+                kind: SignatureKind::MemberFunction,
+                text,
+                pattern: sig.pattern,
+                offset: sig.offset as isize,
+                is_pointer: false,
+                location: Some(location),
+            })
+        })
+        .collect()
+}
+
+/// Finds every `resolve_string_xref` call with a string literal in the Rust sources
+/// under `dir`, returning each string with where it's used.
+pub fn inline_string_references(dir: &Path) -> Result<Vec<(String, String)>> {
+    Ok(string_arguments(dir, INLINE_STRING_XREF)?
+        .into_iter()
+        .filter_map(|(literals, location)| Some((literals.into_iter().next()?, location)))
+        .collect())
+}
+
+/// Finds every use of `call` in the Rust sources under `dir`, returning the contents
+/// of the string literals it's called with on that line, and where it's used.
+fn string_arguments(dir: &Path, call: &str) -> Result<Vec<(Vec<String>, String)>> {
+    let mut arguments = Vec::new();
+
+    for path in rust_sources(dir)? {
+        let source = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        for (line_idx, line) in source.lines().enumerate() {
+            let Some(start) = line.find(call) else {
+                continue;
+            };
+
+            let location = format!("{}:{}", path.display(), line_idx + 1);
+            let mut literals = Vec::new();
+            let mut rest = line[start + call.len()..].trim_start();
+            while let Some((text, after)) =
+                rest.strip_prefix('"').and_then(|rest| rest.split_once('"'))
+            {
+                literals.push(text.to_string());
+
+                // only literals separated by commas, up to the end of the call
+                let Some(next) = after.trim_start().strip_prefix(',') else {
+                    break;
+                };
+                rest = next.trim_start();
+            }
+
+            arguments.push((literals, location));
+        }
+    }
+
+    Ok(arguments)
+}
+
+fn rust_sources(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+
+    for entry in
+        std::fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.is_dir() {
+            sources.extend(rust_sources(&path)?);
+        } else if path.extension().map_or(false, |ext| ext == "rs") {
+            sources.push(path);
+        }
+    }
+
+    sources.sort();
+    Ok(sources)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline() {
+        let dir = std::env::temp_dir().join(format!("sigtool-inline-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("hooking")).unwrap();
+        std::fs::write(
+            dir.join("lib.rs"),
+            "let f = resolve_signature!(\"E8 * ?? ?? ?? ??\");\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("hooking").join("wndproc.rs"),
+            "let f = resolve_signature!(\"WndProc\", \"48 8B C4 ??\");\n",
+        )
+        .unwrap();
+
+        let signatures = inline(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        let signatures = signatures.unwrap();
+
+        assert_eq!(signatures.len(), 2);
+        assert_eq!(signatures[0].text, "48 8B C4 ??");
+        assert_eq!(signatures[0].offset, 0);
+        assert_eq!(
+            signatures[0].location,
+            Some(format!(
+                "WndProc at {}:1",
+                dir.join("hooking").join("wndproc.rs").display()
+            ))
+        );
+        assert_eq!(signatures[1].text, "E8 * ?? ?? ?? ??");
+        assert_eq!(signatures[1].offset, 1);
+        assert_eq!(
+            signatures[1].location,
+            Some(format!("{}:1", dir.join("lib.rs").display()))
+        );
+    }
+}
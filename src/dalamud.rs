@@ -1,14 +1,64 @@
-use log::info;
-use std::sync::Mutex;
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::time::Duration;
-use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::windows::named_pipe::{ClientOptions, NamedPipeClient, PipeMode};
+use tokio::sync::Mutex;
 use tokio::time;
 use windows::Win32::Foundation::ERROR_PIPE_BUSY;
 
+/// How long to wait for the pipe to connect, or for a response to a request.
+const PIPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The largest message we expect to receive from the Dalamud plugin.
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+/// Requests sent to the Dalamud support plugin, each answered by a `DalamudResponse`
+/// with the same ID.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum DalamudRequest {
+    ResolveSignature(DalamudResolveSignatureRequest),
+}
+
+#[derive(Debug, Serialize)]
+pub struct DalamudResolveSignatureRequest {
+    pub id: u32,
+    /// The signature, as space-separated hex bytes with `??` for wildcards.
+    pub signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum DalamudResponse {
+    ResolveSignature(DalamudResolveSignatureResponse),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DalamudResolveSignatureResponse {
+    pub id: u32,
+    /// The absolute address the signature resolved to, if it was found.
+    pub address: Option<u64>,
+}
+
+impl DalamudResponse {
+    fn id(&self) -> u32 {
+        match self {
+            DalamudResponse::ResolveSignature(response) => response.id,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct DalamudPipe {
     pipe_name: String,
     pipe_client: Mutex<Option<NamedPipeClient>>,
+    /// Cleared if we fail to connect, or a request fails or times out, so that later
+    /// requests fail immediately instead of each waiting for the pipe to time out.
+    available: AtomicBool,
+    next_request_id: AtomicU32,
 }
 
 impl DalamudPipe {
@@ -16,24 +66,124 @@ impl DalamudPipe {
         Self {
             pipe_name: pipe_name.to_owned(),
             pipe_client: Mutex::new(None),
+            available: AtomicBool::new(true),
+            next_request_id: AtomicU32::new(0),
         }
     }
 
-    pub async fn connect(&self) {
+    /// Connects to the pipe, if we aren't connected already.
+    pub async fn connect(&self) -> Result<()> {
+        let mut pipe_client = self.pipe_client.lock().await;
+        if pipe_client.is_some() {
+            return Ok(());
+        }
+
         let pipe_name = self.pipe_name.to_owned();
+        let connect = async {
+            loop {
+                match ClientOptions::new()
+                    .pipe_mode(PipeMode::Message)
+                    .open(&pipe_name)
+                {
+                    Ok(client) => break Ok(client),
+                    Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => (),
+                    Err(e) => break Err(e),
+                }
 
-        let client = loop {
-            match ClientOptions::new().open(&pipe_name) {
-                Ok(client) => break client,
-                Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) => (),
-                Err(e) => panic!("failed to connect to Dalamud pipe: {}", e),
+                time::sleep(Duration::from_millis(50)).await;
             }
-
-            time::sleep(Duration::from_millis(50)).await;
         };
 
-        self.pipe_client.lock().unwrap().replace(client);
+        let client = match time::timeout(PIPE_TIMEOUT, connect).await {
+            Ok(Ok(client)) => client,
+            Ok(Err(e)) => {
+                self.available.store(false, Ordering::SeqCst);
+                return Err(e).context("failed to connect to Dalamud pipe");
+            }
+            Err(_) => {
+                self.available.store(false, Ordering::SeqCst);
+                bail!("timed out connecting to Dalamud pipe");
+            }
+        };
 
+        pipe_client.replace(client);
         info!("connected to Dalamud pipe at {}", pipe_name);
+
+        Ok(())
+    }
+
+    /// Asks Dalamud to resolve a signature, returning the absolute address it found.
+    pub async fn resolve_signature(&self, signature: &str) -> Result<Option<usize>> {
+        let request = DalamudResolveSignatureRequest {
+            id: self.next_request_id.fetch_add(1, Ordering::SeqCst),
+            signature: signature.to_owned(),
+        };
+
+        match self
+            .request(DalamudRequest::ResolveSignature(request))
+            .await?
+        {
+            DalamudResponse::ResolveSignature(response) => {
+                Ok(response.address.map(|address| address as usize))
+            }
+        }
+    }
+
+    async fn request(&self, request: DalamudRequest) -> Result<DalamudResponse> {
+        if !self.available.load(Ordering::SeqCst) {
+            bail!("Dalamud pipe is unavailable");
+        }
+
+        // we may be resolving before the connection is made during late init
+        self.connect().await?;
+
+        let id = match &request {
+            DalamudRequest::ResolveSignature(request) => request.id,
+        };
+
+        let mut message = Vec::new();
+        let mut serializer = rmp_serde::Serializer::new(&mut message).with_struct_map();
+        request.serialize(&mut serializer)?;
+
+        let mut pipe_client = self.pipe_client.lock().await;
+        let client = pipe_client
+            .as_mut()
+            .context("not connected to Dalamud pipe")?;
+
+        let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+        let exchange = async {
+            // the pipe is in message mode, so each write and read is a whole message
+            client.write_all(&message).await?;
+
+            loop {
+                let len = client.read(&mut buf).await?;
+                if len == 0 {
+                    bail!("Dalamud pipe closed");
+                }
+
+                let response: DalamudResponse = rmp_serde::from_slice(&buf[..len])?;
+                if response.id() == id {
+                    break Ok(response);
+                }
+
+                // a response to an earlier request that we gave up waiting for
+                debug!("discarding stale Dalamud response: {:?}", response);
+            }
+        };
+
+        let result = match time::timeout(PIPE_TIMEOUT, exchange).await {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("timed out waiting for Dalamud response")),
+        };
+
+        // a plugin that's hung or misbehaving won't answer later requests either,
+        // so don't make each of them wait for it
+        if let Err(e) = &result {
+            warn!("giving up on the Dalamud pipe: {:#}", e);
+            self.available.store(false, Ordering::SeqCst);
+            pipe_client.take();
+        }
+
+        result
     }
 }
@@ -5,9 +5,12 @@ use ffxiv_client_structs::generated::ffxiv::client::system::framework::{
     Framework, Framework_Fn_Instance,
 };
 use grebuloff_macros::{function_hook, vtable_functions, VTable};
-use log::debug;
+use log::{debug, warn};
 
-use crate::{get_tokio_rt, hooking::create_function_hook};
+use crate::{get_tokio_rt, hooking::create_function_hook, resolvers};
+
+/// The name of `Framework` in the game's RTTI.
+const FRAMEWORK_CLASS: &str = "Client::System::Framework";
 
 #[derive(VTable)]
 struct FrameworkVTable {
@@ -38,6 +41,20 @@ pub unsafe fn hook_framework() -> Result<()> {
     let vtable = FrameworkVTable { base: *framework };
     debug!("framework vtable: {:p}", vtable.base);
 
+    // the instance should be using the vtable the game's RTTI knows about, and if it
+    // isn't, something's off with one or the other. parsing RTTI takes a while, so
+    // only check this in debug builds
+    if cfg!(debug_assertions) {
+        match resolvers::find_vtable(FRAMEWORK_CLASS) {
+            Some(rtti_vtable) if rtti_vtable != vtable.base as *const u8 => warn!(
+                "framework vtable {:p} doesn't match {:p} from RTTI",
+                vtable.base, rtti_vtable
+            ),
+            Some(_) => debug!("framework vtable matches RTTI"),
+            None => warn!("couldn't find the framework vtable through RTTI"),
+        }
+    }
+
     create_function_hook!(tick, *vtable.address_table().tick()).enable()?;
 
     Ok(())
@@ -1,7 +1,7 @@
 use std::{fmt, sync::OnceLock};
 
 use anyhow::Result;
-use log::{debug, info};
+use log::{debug, info, warn};
 use retour::StaticDetour;
 
 mod framework;
@@ -110,7 +110,16 @@ pub unsafe fn init_hooks() -> Result<()> {
     info!("initializing hooks");
 
     swapchain::hook_swap_chain()?;
-    // wndproc::hook_wndproc()?;
+
+    // the WndProc hook tracks focus, minimizing and DPI changes, so the UI can be
+    // throttled and scaled, as well as handling our debug hotkeys. the overlay still
+    // draws without it, so a broken signature for it shouldn't stop loading
+    if let Err(e) = wndproc::hook_wndproc() {
+        warn!(
+            "failed to hook WndProc, the UI won't follow window focus or DPI: {:#}",
+            e
+        );
+    }
 
     Ok(())
 }
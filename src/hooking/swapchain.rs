@@ -1,10 +1,10 @@
-use crate::{hooking::create_function_hook, rpc::ui::UiRpcServer, ui};
+use crate::{hooking::create_function_hook, ui};
 use anyhow::Result;
 use ffxiv_client_structs::generated::ffxiv::client::graphics::kernel::{
     Device, Device_Fn_Instance,
 };
 use grebuloff_macros::{function_hook, vtable_functions, VTable};
-use log::{debug, trace, warn};
+use log::{debug, trace};
 use std::{
     cell::{RefCell, RefMut},
     mem::MaybeUninit,
@@ -23,6 +23,7 @@ use windows::Win32::{
             IDXGISwapChain, DXGI_SWAP_CHAIN_DESC,
         },
     },
+    UI::HiDpi::GetDpiForWindow,
 };
 
 thread_local! {
@@ -131,7 +132,7 @@ unsafe extern "stdcall" fn resize_buffers(
     }
 
     // inform the UI host of the new size
-    UiRpcServer::resize(width, height);
+    ui::resize(width, height, get_dpi_scale(&this));
 
     original.call(
         this,
@@ -143,12 +144,27 @@ unsafe extern "stdcall" fn resize_buffers(
     )
 }
 
+/// Returns the scale factor of the display that the swap chain's window is on.
+unsafe fn get_dpi_scale(this: &IDXGISwapChain) -> f32 {
+    let mut sc_desc = MaybeUninit::<DXGI_SWAP_CHAIN_DESC>::zeroed();
+    if this.GetDesc(sc_desc.as_mut_ptr()).is_err() {
+        return 1.0;
+    }
+
+    ui::dpi_to_scale(GetDpiForWindow(sc_desc.assume_init().OutputWindow))
+}
+
 #[function_hook]
 unsafe extern "stdcall" fn present(
     this: IDXGISwapChain,
     sync_interval: u32,
     present_flags: u32,
 ) -> i32 {
+    // don't touch anything while the overlay is hidden or the game is minimized
+    if !ui::should_render() {
+        return original.call(this, sync_interval, present_flags);
+    }
+
     let device: ID3D11Device2 = this.GetDevice().unwrap();
 
     RENDER_DATA.with(move |cell| {
@@ -157,6 +173,13 @@ unsafe extern "stdcall" fn present(
             // initialize the render data
             trace!("calling initialize_render_data from IDXGISwapChain::Present");
             cell = initialize_render_data(&this, cell, None);
+
+            // the game may not resize before our first frame, so the UI doesn't know
+            // what size to paint at yet; tell it the size of the texture we composite
+            let data = cell.as_ref().unwrap();
+            if !ui::metrics().has_size() {
+                ui::resize(data.buffer_width, data.buffer_height, get_dpi_scale(&this));
+            }
         }
 
         // borrow it as mutable now
@@ -170,7 +193,11 @@ unsafe extern "stdcall" fn present(
             let _ = RenderStateBackup::new(device.GetImmediateContext().unwrap());
 
             // poll to see if we have new data, and if so, update the texture
-            if let Some(snapshot) = ui::poll_dirty() {
+            // paints are adapted to our size as they arrive, but one painted before
+            // a resize can still be the old size, and won't fit the texture
+            if let Some(snapshot) = ui::poll_dirty().filter(|snapshot| {
+                snapshot.width == data.buffer_width && snapshot.height == data.buffer_height
+            }) {
                 let mut mapped = MaybeUninit::<D3D11_MAPPED_SUBRESOURCE>::zeroed();
                 context
                     .Map(
@@ -181,25 +208,22 @@ unsafe extern "stdcall" fn present(
                         Some(mapped.as_mut_ptr()),
                     )
                     .expect("Map failed");
-                let mut mapped = mapped.assume_init();
-
-                if snapshot.width * 4 != mapped.RowPitch
-                    || mapped.RowPitch * snapshot.height != mapped.DepthPitch
-                {
-                    warn!("latest UI snapshot does not match our current UI size, skipping update");
-                } else {
-                    let src = snapshot.data.as_ptr();
-                    let dst = mapped.pData as *mut u8;
-
-                    mapped.RowPitch = snapshot.width * 4;
-                    mapped.DepthPitch = snapshot.width * snapshot.height * 4;
-
-                    let size = (data.buffer_width as usize * data.buffer_height as usize * 4)
-                        .min(snapshot.data.len());
-                    std::ptr::copy_nonoverlapping(src, dst, size);
-
-                    context.Unmap(&data.texture, 0);
+                let mapped = mapped.assume_init();
+
+                // the texture's rows may be padded, so copy row by row
+                let src = snapshot.data.as_ptr();
+                let dst = mapped.pData as *mut u8;
+                let row_size = snapshot.width as usize * 4;
+
+                for row in 0..snapshot.height as usize {
+                    std::ptr::copy_nonoverlapping(
+                        src.add(row * row_size),
+                        dst.add(row * mapped.RowPitch as usize),
+                        row_size,
+                    );
                 }
+
+                context.Unmap(&data.texture, 0);
             }
 
             // render the overlay
@@ -1,12 +1,21 @@
 use super::create_function_hook;
-use crate::resolvers::resolve_signature;
-use anyhow::Result;
+use crate::{get_tokio_rt, resolvers::resolve_signature, ui};
+use anyhow::{bail, Result};
 use grebuloff_macros::function_hook;
-use log::debug;
-use windows::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use log::{debug, error, trace};
+use windows::Win32::{
+    Foundation::{HWND, LPARAM, LRESULT, WPARAM},
+    UI::{
+        Input::KeyboardAndMouse::{GetKeyState, VIRTUAL_KEY, VK_CONTROL, VK_F11, VK_F12, VK_SHIFT},
+        WindowsAndMessaging::{SIZE_MINIMIZED, WM_ACTIVATEAPP, WM_DPICHANGED, WM_KEYDOWN, WM_SIZE},
+    },
+};
 
 pub unsafe fn hook_wndproc() -> Result<()> {
-    let wndproc_ptr = resolve_signature!("E8 ?? ?? ?? ?? 80 7C 24 ?? ?? 74 ?? B8");
+    let wndproc_ptr = resolve_signature!("WndProc", "E8 ?? ?? ?? ?? 80 7C 24 ?? ?? 74 ?? B8");
+    if wndproc_ptr.is_null() {
+        bail!("failed to resolve WndProc");
+    }
     debug!("WndProc: {:p}", wndproc_ptr);
     create_function_hook!(wndproc, wndproc_ptr).enable()?;
 
@@ -15,9 +24,60 @@ pub unsafe fn hook_wndproc() -> Result<()> {
 
 #[function_hook]
 unsafe fn wndproc(hwnd: HWND, msg: u32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
-    debug!(
+    trace!(
         "WndProc invoked with: hwnd = {:?}, msg = {}, wparam = {:?}, lparam = {:?}",
-        hwnd, msg, wparam, lparam
+        hwnd,
+        msg,
+        wparam,
+        lparam
     );
+
+    match msg {
+        // our hotkeys shouldn't also trigger whatever the game has bound to them
+        WM_KEYDOWN => {
+            if handle_hotkeys(VIRTUAL_KEY(wparam.0 as u16)) {
+                return LRESULT(0);
+            }
+        }
+        WM_ACTIVATEAPP => ui::set_window_focused(wparam.0 != 0),
+        WM_SIZE => ui::set_window_minimized(wparam.0 as u32 == SIZE_MINIMIZED),
+        // the new DPI is in the low word, and is the same for both axes
+        WM_DPICHANGED => ui::set_dpi_scale(ui::dpi_to_scale(wparam.0 as u32 & 0xFFFF)),
+        _ => {}
+    }
+
     original.call(hwnd, msg, wparam, lparam)
 }
+
+/// Returns true if the given modifier key is currently held down.
+unsafe fn is_key_down(key: VIRTUAL_KEY) -> bool {
+    GetKeyState(key.0 as i32) < 0
+}
+
+/// Handles our hotkeys, returning true if the key was one of them.
+unsafe fn handle_hotkeys(key: VIRTUAL_KEY) -> bool {
+    if !is_key_down(VK_CONTROL) || !is_key_down(VK_SHIFT) {
+        return false;
+    }
+
+    // debug builds only, until hotkeys are configurable
+    if !cfg!(debug_assertions) {
+        return false;
+    }
+
+    match key {
+        // Ctrl+Shift+F11: show/hide the overlay
+        VK_F11 => ui::toggle_overlay_visible(),
+        // Ctrl+Shift+F12: export the current UI buffer to a PNG
+        VK_F12 => {
+            get_tokio_rt().spawn_blocking(|| {
+                if let Err(e) = ui::export_snapshot_png() {
+                    error!("failed to export UI snapshot: {}", e);
+                }
+            });
+        }
+        _ => return false,
+    }
+
+    true
+}
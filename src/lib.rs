@@ -69,6 +69,15 @@ pub fn get_execution_id() -> String {
     EXEC_ID.get().unwrap().clone()
 }
 
+pub fn get_runtime_dir() -> &'static PathBuf {
+    RUNTIME_DIR.get().unwrap()
+}
+
+/// Returns the pipe to the Dalamud support plugin, if we were loaded by Dalamud.
+pub fn get_dalamud_pipe() -> Option<&'static DalamudPipe> {
+    DALAMUD_PIPE.get()
+}
+
 fn setup_logging(dir: &PathBuf) {
     // log to grebuloff.log in the specified directory
     // log format should have timestamps, level, module, and message
@@ -123,6 +132,13 @@ fn setup_logging(dir: &PathBuf) {
     }));
 }
 
+/// Shows an error to the user, blocking until they dismiss it.
+fn show_error(message: &str) {
+    if let Err(e) = msgbox::create("Grebuloff", message, IconType::Error) {
+        error!("failed to show error message: {:?}", e);
+    }
+}
+
 fn init_sync_rt(runtime_dir: &CString, dalamud_pipe_name: Option<&CString>) {
     if LOAD_METHOD.get().is_none() {
         LOAD_METHOD
@@ -185,9 +201,15 @@ async fn init_sync_early(dalamud_pipe_name: Option<&str>) {
     info!("Execution ID: {}", get_execution_id());
 
     // resolve clientstructs
-    unsafe { resolvers::init_resolvers(get_load_method()) }
-        .await
-        .expect("failed to init resolvers");
+    // if anything we need is missing, stop here rather than crash the game later on
+    if let Err(e) = unsafe { resolvers::init_resolvers(get_load_method()) }.await {
+        error!("failed to init resolvers: {:?}", e);
+        show_error(&format!(
+            "{:#}\n\nGrebuloff will not be loaded for this session.",
+            e
+        ));
+        return;
+    }
 
     // initialize early hooks (framework)
     unsafe { hooking::init_early_hooks() }.expect("failed to init early hooks");
@@ -214,7 +236,11 @@ pub async fn init_sync_late() {
 
     // start attempting connection to the Dalamud pipe, if applicable
     if let Some(pipe) = DALAMUD_PIPE.get() {
-        task::spawn(pipe.connect());
+        task::spawn(async {
+            if let Err(e) = pipe.connect().await {
+                error!("failed to connect to Dalamud pipe: {:?}", e);
+            }
+        });
     }
 
     // handle anything that needs to be loaded sync first
@@ -0,0 +1,197 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use grebuloff_resolver::{pe::PeImage, scanner::Pattern};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+/// The name of the file in the runtime directory that resolved signatures are cached in.
+pub const CACHE_FILE: &str = "resolver-cache.json";
+
+/// Identifies a build of the game, so that cached matches are only reused
+/// for the exact image they were found in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildId {
+    pub timestamp: u32,
+    pub checksum: u32,
+    pub size_of_image: u32,
+}
+
+impl BuildId {
+    pub fn of(pe: &PeImage) -> Self {
+        Self {
+            timestamp: pe.timestamp,
+            checksum: pe.checksum,
+            size_of_image: pe.size_of_image,
+        }
+    }
+}
+
+/// Where a signature matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedMatch {
+    /// The RVA of the first match.
+    rva: usize,
+    /// How many times the signature matched, if every match was counted.
+    count: Option<usize>,
+}
+
+/// Where each signature matched in a specific build of the game.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResolutionCache {
+    build: BuildId,
+    /// Each signature's matches, keyed by the signature's text.
+    matches: HashMap<String, CachedMatch>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl ResolutionCache {
+    pub fn new(build: BuildId) -> Self {
+        Self {
+            build,
+            matches: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Loads the cache for the given build from `dir`. If there is no cache,
+    /// or it was written for a different build, an empty cache is returned.
+    pub fn load(dir: &Path, build: BuildId) -> Self {
+        match Self::read(&dir.join(CACHE_FILE)) {
+            Ok(Some(cache)) if cache.build == build => cache,
+            Ok(Some(cache)) => {
+                info!(
+                    "resolution cache is for a different build ({:?}, current {:?}), ignoring it",
+                    cache.build, build
+                );
+                Self::new(build)
+            }
+            Ok(None) => Self::new(build),
+            Err(e) => {
+                warn!("failed to load resolution cache, ignoring it: {:?}", e);
+                Self::new(build)
+            }
+        }
+    }
+
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = std::fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+        let cache =
+            serde_json::from_slice(&file).with_context(|| format!("failed to parse {:?}", path))?;
+
+        Ok(Some(cache))
+    }
+
+    /// Writes the cache to `dir`, if anything has changed since it was loaded.
+    pub fn save(&mut self, dir: &Path) -> Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let path = dir.join(CACHE_FILE);
+        let file = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&path, file).with_context(|| format!("failed to write {:?}", path))?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    /// Returns the cached RVA of the first match of `pattern` and how many times it
+    /// matched, as long as the pattern still matches there in `image`. If `counted`,
+    /// entries are only returned if every match was counted when they were cached;
+    /// otherwise, those that weren't count as a single match.
+    pub fn get(&self, pattern: &Pattern, image: &[u8], counted: bool) -> Option<(usize, usize)> {
+        let entry = self.matches.get(&pattern.to_string())?;
+        let count = match entry.count {
+            Some(count) => count,
+            None if counted => return None,
+            None => 1,
+        };
+
+        pattern
+            .matches_at(image, entry.rva)
+            .then_some((entry.rva, count))
+    }
+
+    /// Records where a pattern first matched and, if every match was counted, how many
+    /// times it matched. Forgets the pattern if it no longer matches.
+    pub fn update(&mut self, pattern: &Pattern, rva: Option<usize>, count: Option<usize>) {
+        let key = pattern.to_string();
+        let entry = rva.map(|rva| CachedMatch { rva, count });
+        let previous = match entry {
+            Some(entry) => self.matches.insert(key, entry),
+            None => self.matches.remove(&key),
+        };
+
+        self.dirty |= previous != entry;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILD: BuildId = BuildId {
+        timestamp: 0x6480_1234,
+        checksum: 0,
+        size_of_image: 0x1000,
+    };
+
+    #[test]
+    fn test_get_validates() {
+        let mut image = vec![0xCC; 0x40];
+        image[0x10..0x13].copy_from_slice(&[0x48, 0x8D, 0x05]);
+
+        let pattern = Pattern::from_byte_mask(&[0x48, 0x8D, 0x05], &[1, 1, 1]);
+        let mut cache = ResolutionCache::new(BUILD);
+        cache.update(&pattern, Some(0x10), None);
+        assert_eq!(cache.get(&pattern, &image, false), Some((0x10, 1)));
+
+        // the image no longer matches the cached entry
+        image[0x10] = 0x4C;
+        assert_eq!(cache.get(&pattern, &image, false), None);
+
+        cache.update(&pattern, None, None);
+        assert!(cache.matches.is_empty());
+    }
+
+    #[test]
+    fn test_get_counted() {
+        let mut image = vec![0xCC; 0x40];
+        image[0x10..0x13].copy_from_slice(&[0x48, 0x8D, 0x05]);
+        image[0x20..0x23].copy_from_slice(&[0x48, 0x8D, 0x05]);
+        let pattern = Pattern::from_byte_mask(&[0x48, 0x8D, 0x05], &[1, 1, 1]);
+
+        // entries cached without counting can't be used when counting
+        let mut cache = ResolutionCache::new(BUILD);
+        cache.update(&pattern, Some(0x10), None);
+        assert_eq!(cache.get(&pattern, &image, true), None);
+
+        // an ambiguous pattern stays ambiguous
+        cache.update(&pattern, Some(0x10), Some(2));
+        assert!(cache.dirty);
+        assert_eq!(cache.get(&pattern, &image, true), Some((0x10, 2)));
+        assert_eq!(cache.get(&pattern, &image, false), Some((0x10, 2)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let pattern = Pattern::from_byte_mask(&[0xE8, 0, 0, 0, 0, 0xC3], &[1, 0, 0, 0, 0, 1]);
+        let mut cache = ResolutionCache::new(BUILD);
+        cache.update(&pattern, Some(0x1234), Some(1));
+        assert!(cache.dirty);
+
+        let json = serde_json::to_string(&cache).unwrap();
+        assert!(json.contains("\"E8 ?? ?? ?? ?? C3\":{\"rva\":4660,\"count\":1}"));
+
+        let loaded: ResolutionCache = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.build, BUILD);
+        assert_eq!(loaded.matches, cache.matches);
+        assert!(!loaded.dirty);
+    }
+}
@@ -1,6 +1,54 @@
 use ffxiv_client_structs::MemberFunctionSignature;
+use grebuloff_resolver::scanner::Pattern;
+use grebuloff_rpc::resolver::ResolutionSource;
+use log::{debug, warn};
+use tokio::task;
 
-pub unsafe fn resolve_member_function(_input: &MemberFunctionSignature) -> *const u8 {
-    // TODO
-    panic!("not yet implemented");
+use super::native::{self, Resolved};
+use crate::{get_dalamud_pipe, get_tokio_rt};
+
+/// Resolves a member function through the Dalamud support plugin, so that we find
+/// the same function that Dalamud (and its plugins) do. Falls back to scanning
+/// natively if the plugin can't resolve the signature, or the pipe is unavailable.
+pub unsafe fn resolve_member_function(input: &MemberFunctionSignature) -> Option<Resolved> {
+    let Some(pipe) = get_dalamud_pipe() else {
+        warn!("resolve_member_function: no Dalamud pipe, resolving natively");
+        return resolve_natively(input);
+    };
+
+    // clientstructs signatures only ever wildcard whole bytes, which Dalamud understands
+    let signature = Pattern::from_byte_mask(&input.signature.bytes, &input.signature.mask);
+    let signature = signature.to_string();
+
+    // we may be called from within the runtime (during init) or from a game thread
+    let result =
+        task::block_in_place(|| get_tokio_rt().block_on(pipe.resolve_signature(&signature)));
+
+    match result {
+        Ok(Some(address)) => {
+            debug!(
+                "resolve_member_function: Dalamud resolved {} - {:X}",
+                signature, address
+            );
+            Some((address as *const u8, ResolutionSource::Dalamud))
+        }
+        Ok(None) => {
+            warn!(
+                "resolve_member_function: Dalamud couldn't resolve {}, resolving natively",
+                signature
+            );
+            resolve_natively(input)
+        }
+        Err(e) => {
+            warn!(
+                "resolve_member_function: failed to resolve {} through Dalamud, resolving natively: {:?}",
+                signature, e
+            );
+            resolve_natively(input)
+        }
+    }
+}
+
+fn resolve_natively(input: &MemberFunctionSignature) -> Option<Resolved> {
+    native::resolve_member_function(native::module()?, input)
 }
@@ -0,0 +1,225 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::{Context, Result};
+use grebuloff_resolver::signature::Signature;
+use grebuloff_rpc::resolver::SignatureKind;
+use log::{info, warn};
+use serde::Deserialize;
+
+use super::cache::BuildId;
+
+/// The name of the file in the runtime directory that the address database is read from.
+pub const DATABASE_FILE: &str = "addresses.json";
+
+/// Known addresses for specific builds of the game, used when signatures break
+/// after a patch, until a new release fixes them.
+#[derive(Debug, Default, Deserialize)]
+pub struct AddressDatabase {
+    pub builds: Vec<BuildAddresses>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BuildAddresses {
+    pub build: BuildId,
+    pub addresses: Vec<AddressEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AddressEntry {
+    /// A name for the address, which resolvers that know the name of what they're
+    /// resolving look it up by before the signature. Otherwise for reference only.
+    pub name: String,
+    pub kind: SignatureKind,
+    /// The signature this address stands in for.
+    pub signature: String,
+    /// The RVA the signature resolves to: the function, vtable or static variable
+    /// itself, before any dereference.
+    pub rva: usize,
+}
+
+/// The addresses in the database for the current build, keyed by what they stand in for.
+#[derive(Debug, Default)]
+pub struct AddressTable {
+    entries: HashMap<(SignatureKind, String), AddressEntry>,
+    /// The signature each name is for, to look entries up by name.
+    names: HashMap<String, (SignatureKind, String)>,
+}
+
+impl AddressDatabase {
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let file = std::fs::read(path).with_context(|| format!("failed to read {:?}", path))?;
+        let database =
+            serde_json::from_slice(&file).with_context(|| format!("failed to parse {:?}", path))?;
+
+        Ok(Some(database))
+    }
+
+    /// Returns the addresses for `build` that fit in an image of `image_size` bytes.
+    pub fn table(self, build: BuildId, image_size: usize) -> AddressTable {
+        let mut table = AddressTable::default();
+        let Some(addresses) = self.builds.into_iter().find(|b| b.build == build) else {
+            return table;
+        };
+
+        for entry in addresses.addresses {
+            // normalize the signature, so that it's written the same way as ours
+            let signature = match Signature::parse(&entry.signature) {
+                Ok(sig) => sig.pattern.to_string(),
+                Err(e) => {
+                    warn!("address database: ignoring {}: {}", entry.name, e);
+                    continue;
+                }
+            };
+
+            if entry.rva >= image_size {
+                warn!(
+                    "address database: ignoring {}: RVA {:X} is outside the image",
+                    entry.name, entry.rva
+                );
+                continue;
+            }
+
+            let key = (entry.kind, signature);
+            let previous = table.names.insert(entry.name.clone(), key.clone());
+            if previous.is_some() {
+                warn!(
+                    "address database: {} is named more than once, using the last",
+                    entry.name
+                );
+            }
+            table.entries.insert(key, entry);
+        }
+
+        table
+    }
+}
+
+impl AddressTable {
+    /// Loads the addresses for the given build from the database in `dir`, if there is one.
+    pub fn load(dir: &Path, build: BuildId, image_size: usize) -> Self {
+        match AddressDatabase::read(&dir.join(DATABASE_FILE)) {
+            Ok(Some(database)) => {
+                let table = database.table(build, image_size);
+                info!(
+                    "address database has {} addresses for this build",
+                    table.entries.len()
+                );
+                table
+            }
+            Ok(None) => Self::default(),
+            Err(e) => {
+                warn!("failed to load address database, ignoring it: {:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Returns the entry for a signature, given as formatted by `Pattern`.
+    pub fn get(&self, kind: SignatureKind, signature: &str) -> Option<&AddressEntry> {
+        self.entries.get(&(kind, signature.to_string()))
+    }
+
+    /// Returns the entry with the given name, if it's for the given kind of signature.
+    /// Unlike signatures, names stay the same when a release changes a signature,
+    /// so entries for older signatures are still found.
+    pub fn get_by_name(&self, kind: SignatureKind, name: &str) -> Option<&AddressEntry> {
+        let key = self.names.get(name).filter(|(k, _)| *k == kind)?;
+        // another entry for the same signature may have replaced it
+        self.entries.get(key).filter(|entry| entry.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table() {
+        let database: AddressDatabase = serde_json::from_str(
+            r#"{
+                "builds": [
+                    {
+                        "build": { "timestamp": 1, "checksum": 0, "size_of_image": 4096 },
+                        "addresses": [
+                            { "name": "stale", "kind": "MemberFunction", "signature": "E8", "rva": 16 }
+                        ]
+                    },
+                    {
+                        "build": { "timestamp": 2, "checksum": 0, "size_of_image": 4096 },
+                        "addresses": [
+                            { "name": "Framework", "kind": "StaticAddress", "signature": "48 8b 0d ?? ?? ?? ??", "rva": 256 },
+                            { "name": "outside", "kind": "VTable", "signature": "48 8D 05", "rva": 4096 },
+                            { "name": "invalid", "kind": "VTable", "signature": "48 8G", "rva": 0 }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let build = BuildId {
+            timestamp: 2,
+            checksum: 0,
+            size_of_image: 4096,
+        };
+        let table = database.table(build, 4096);
+        assert_eq!(table.entries.len(), 1);
+
+        let entry = table
+            .get(SignatureKind::StaticAddress, "48 8B 0D ?? ?? ?? ??")
+            .unwrap();
+        assert_eq!(entry.name, "Framework");
+        assert_eq!(entry.rva, 256);
+        assert!(table
+            .get(SignatureKind::VTable, "48 8B 0D ?? ?? ?? ??")
+            .is_none());
+
+        // names find entries whatever their signature, but only for their kind
+        let entry = table
+            .get_by_name(SignatureKind::StaticAddress, "Framework")
+            .unwrap();
+        assert_eq!(entry.rva, 256);
+        assert!(table
+            .get_by_name(SignatureKind::MemberFunction, "Framework")
+            .is_none());
+        assert!(table
+            .get_by_name(SignatureKind::StaticAddress, "stale")
+            .is_none());
+    }
+
+    #[test]
+    fn test_table_duplicate_names() {
+        let database: AddressDatabase = serde_json::from_str(
+            r#"{
+                "builds": [
+                    {
+                        "build": { "timestamp": 1, "checksum": 0, "size_of_image": 4096 },
+                        "addresses": [
+                            { "name": "tick", "kind": "MemberFunction", "signature": "E8 01", "rva": 16 },
+                            { "name": "tick", "kind": "MemberFunction", "signature": "E8 02", "rva": 32 },
+                            { "name": "other", "kind": "MemberFunction", "signature": "E8 02", "rva": 48 }
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let build = BuildId {
+            timestamp: 1,
+            checksum: 0,
+            size_of_image: 4096,
+        };
+        let table = database.table(build, 4096);
+
+        // the last entry by a name wins, and entries replaced by signature aren't found by name
+        let kind = SignatureKind::MemberFunction;
+        assert_eq!(table.get(kind, "E8 01").unwrap().rva, 16);
+        assert!(table.get_by_name(kind, "tick").is_none());
+        assert_eq!(table.get_by_name(kind, "other").unwrap().rva, 48);
+    }
+}
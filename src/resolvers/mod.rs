@@ -1,39 +1,229 @@
+mod cache;
 mod dalamud;
+mod database;
+mod modules;
 mod native;
+mod options;
+mod paths;
+mod report;
+mod rtti;
+mod scan;
 
-use crate::{get_load_method, GrebuloffLoadMethod};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use crate::{get_load_method, get_runtime_dir, GrebuloffLoadMethod};
 use anyhow::Result;
-use ffxiv_client_structs::MemberFunctionSignature;
-use log::info;
+use ffxiv_client_structs::{
+    generated::ffxiv::client::system::framework::{Framework, Framework_Fn_Instance},
+    MemberFunctionSignature, StaticAddressSignature, VTableSignature,
+};
+use grebuloff_resolver::resolve::SignatureKind;
+use grebuloff_rpc::resolver::{
+    CallersRequest, CallersResult, ResolutionReport, ScanRequest, ScanResult,
+};
+use log::{error, info, warn};
+use options::ResolverOptions;
+
+static OPTIONS: OnceLock<ResolverOptions> = OnceLock::new();
+
+/// Returns the resolver options, which are the defaults until `init_resolvers` loads them.
+pub fn options() -> &'static ResolverOptions {
+    OPTIONS.get_or_init(ResolverOptions::default)
+}
 
 pub async unsafe fn init_resolvers(load_method: GrebuloffLoadMethod) -> Result<()> {
     info!("init resolvers: {:?}", load_method);
-    native::prepare()?;
 
+    let options = ResolverOptions::load(get_runtime_dir()).unwrap_or_else(|e| {
+        warn!("failed to load resolver options, using defaults: {:?}", e);
+        ResolverOptions::default()
+    });
+    info!("resolver options: {:?}", options);
+    if OPTIONS.set(options).is_err() {
+        warn!("resolver options were already in use, ignoring loaded options");
+    }
+
+    let module = native::prepare()?;
+    let start = Instant::now();
+
+    // collect every signature first, so that they can all be found in one pass
+    // over .text, rather than one full scan per signature
     ffxiv_client_structs::resolve_all_async(
-        native::resolve_vtable,
-        native::resolve_static_address,
-        if load_method.controls_its_own_destiny() {
-            native::resolve_member_function
-        } else {
-            dalamud::resolve_member_function
-        },
+        native::queue_vtable,
+        native::queue_static_address,
+        native::queue_member_function,
     )
     .await;
+    native::scan_queued(module);
+
+    ffxiv_client_structs::resolve_all_async(
+        resolve_vtable,
+        resolve_static_address,
+        resolve_member_function,
+    )
+    .await;
+
+    let critical_failures = critical_failures();
+    for name in &critical_failures {
+        error!("critical address failed to resolve: {}", name);
+    }
+    report::finish_init(critical_failures, start.elapsed().as_millis() as u64);
+
+    paths::resolve_configured(module, &options().pointer_paths);
+    for (name, string) in &options().string_references {
+        if resolve_string_xref(string).is_null() {
+            warn!("string reference {} failed to resolve", name);
+        }
+    }
+    for (name, symbol) in &options().exports {
+        if resolve_export(symbol).is_null() {
+            warn!("export {} failed to resolve", name);
+        }
+    }
+    for (name, symbol) in &options().imports {
+        if resolve_import(symbol).is_null() {
+            warn!("import {} failed to resolve", name);
+        }
+    }
+
+    let report = resolution_report();
+    if let Err(e) = report::save(get_runtime_dir(), &report) {
+        warn!("failed to save resolution report: {:?}", e);
+    }
 
-    Ok(())
+    info!(
+        "resolved {}/{} signatures in {}ms",
+        report.signatures.len() - report.failures().count(),
+        report.signatures.len(),
+        report.elapsed_ms
+    );
+
+    options().failure_policy.check(&report)
+}
+
+/// Returns the names of addresses that Grebuloff can't run without, and that
+/// failed to resolve.
+fn critical_failures() -> Vec<String> {
+    // the Device instance isn't here, as it's only set once the game has created it
+    let critical = [(
+        "Framework instance",
+        ffxiv_client_structs::address::get::<Framework_Fn_Instance>() as *const *const Framework,
+    )];
+
+    critical
+        .into_iter()
+        .filter(|(_, address)| address.is_null())
+        .map(|(name, _)| name.to_string())
+        .collect()
+}
+
+/// Returns the outcome of every signature resolved so far, and the current
+/// value of every configured pointer path.
+pub fn resolution_report() -> ResolutionReport {
+    let mut report = report::snapshot();
+    report.pointer_paths = unsafe { paths::evaluate_configured() };
+    report
+}
+
+/// Scans a section of the game executable on behalf of a script or addon, for data
+/// that isn't resolved during init. Must only be called once the resolvers have
+/// been initialized.
+pub fn scan(request: &ScanRequest) -> ScanResult {
+    match native::module() {
+        Some(module) => scan::scan(module, request),
+        None => scan::failed(request, "the game module hasn't been prepared"),
+    }
+}
+
+/// Finds every direct call and jump to a function in the game executable, and
+/// optionally the pointers to it in `.rdata`, such as vtable slots. Must only be
+/// called once the resolvers have been initialized.
+pub fn find_callers(request: &CallersRequest) -> CallersResult {
+    match native::module() {
+        Some(module) => scan::find_callers(module, request),
+        None => CallersResult {
+            id: request.id,
+            error: Some("the game module hasn't been prepared".to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+/// Looks up the primary vtable of a class in the game module through its RTTI,
+/// by mangled (`.?AVFramework@System@Client@@`) or demangled (`Client::System::Framework`)
+/// name. Only classes that can be demangled without templates can be found by the latter.
+/// Must only be called once the resolvers have been initialized.
+pub fn find_vtable(class: &str) -> Option<*const u8> {
+    rtti::vtable(class)
+}
+
+fn resolve_vtable(input: &VTableSignature) -> *const u8 {
+    report::record(SignatureKind::VTable, &input.signature, || {
+        native::resolve_vtable(native::module()?, input)
+    })
+}
+
+fn resolve_static_address(input: &StaticAddressSignature) -> *const u8 {
+    report::record(SignatureKind::StaticAddress, &input.signature, || {
+        native::resolve_static_address(native::module()?, input)
+    })
+}
+
+/// Resolves a string literal to the start of the one function that references it,
+/// for functions that are easier to find by a log message or format string than
+/// by a signature. Returns a null pointer if it could not be resolved.
+pub fn resolve_string_xref(string: &str) -> *const u8 {
+    let kind = grebuloff_rpc::resolver::SignatureKind::StringReference;
+    report::record_as(kind, string.to_string(), || {
+        native::resolve_string_xref(native::module()?, string)
+    })
+}
+
+/// Resolves a symbol exported by a loaded module, written as `module!name` or
+/// `module!#ordinal`, e.g. `dxgi.dll!CreateDXGIFactory1`. Forwarded exports are
+/// followed into the module they're forwarded to. Returns a null pointer if it
+/// could not be resolved.
+pub fn resolve_export(symbol: &str) -> *const u8 {
+    let kind = grebuloff_rpc::resolver::SignatureKind::Export;
+    report::record_as(kind, symbol.to_string(), || unsafe {
+        modules::resolve_export(symbol)
+    })
+}
+
+/// Resolves a symbol the game imports from another module, written as for
+/// `resolve_export`, to the import address table slot the game calls it through,
+/// for hooking the game's calls without touching the module itself.
+/// Returns a null pointer if it could not be resolved.
+pub fn resolve_import(symbol: &str) -> *const u8 {
+    let kind = grebuloff_rpc::resolver::SignatureKind::Import;
+    report::record_as(kind, symbol.to_string(), || unsafe {
+        modules::resolve_import(symbol)
+    })
 }
 
 /// Internal helper function used by the `resolve_signature` macro.
 pub unsafe fn resolve_member_function(input: &MemberFunctionSignature) -> *const u8 {
-    if get_load_method().controls_its_own_destiny() {
-        native::resolve_member_function(input)
-    } else {
-        dalamud::resolve_member_function(input)
-    }
+    resolve_named_member_function(None, input)
 }
 
-/// Resolves a signature to a pointer.
+/// Internal helper function used by the `resolve_signature` macro, for functions
+/// that can also be looked up by name in the address database.
+pub unsafe fn resolve_named_member_function(
+    name: Option<&str>,
+    input: &MemberFunctionSignature,
+) -> *const u8 {
+    report::record(SignatureKind::MemberFunction, &input.signature, || {
+        if get_load_method().controls_its_own_destiny() {
+            native::resolve_member_function(native::module()?, name, input)
+        } else {
+            dalamud::resolve_member_function(input)
+        }
+    })
+}
+
+/// Resolves a signature to a pointer, optionally naming it, so that the address
+/// database can supply it by name when the signature breaks.
 /// Returns a null pointer if the signature could not be resolved.
 macro_rules! resolve_signature {
     ($signature: tt) => {{
@@ -43,5 +233,12 @@ macro_rules! resolve_signature {
 
         crate::resolvers::resolve_member_function(&member_func)
     }};
+    ($name: literal, $signature: tt) => {{
+        let member_func = ::ffxiv_client_structs::MemberFunctionSignature::new(
+            ::ffxiv_client_structs_macros::signature!($signature),
+        );
+
+        crate::resolvers::resolve_named_member_function(Some($name), &member_func)
+    }};
 }
 pub(crate) use resolve_signature;
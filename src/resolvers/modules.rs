@@ -0,0 +1,121 @@
+//! Resolves symbols in any loaded module through its export and import tables,
+//! e.g. for reaching into system DLLs like dxgi.dll rather than the game itself.
+
+use std::ffi::CString;
+
+use anyhow::{bail, Context, Result};
+use grebuloff_resolver::{
+    pe::{ExportTarget, Layout, PeImage},
+    symbol::{self, ModuleSymbol},
+};
+use grebuloff_rpc::resolver::ResolutionSource;
+use log::{debug, warn};
+use windows::core::PCSTR;
+use windows::Win32::System::LibraryLoader::GetModuleHandleA;
+use windows::Win32::System::ProcessStatus::{GetModuleInformation, MODULEINFO};
+use windows::Win32::System::Threading::GetCurrentProcess;
+
+use super::native::Resolved;
+
+/// How many forwarded exports to follow before giving up, in case of a cycle.
+const MAX_FORWARDS: usize = 8;
+
+/// Returns the mapped image of a loaded module, or of the game executable if `name`
+/// is `None`. Modules aren't loaded on our behalf, and must stay loaded for as long
+/// as the image is used.
+pub(super) unsafe fn loaded_module(name: Option<&str>) -> Result<&'static [u8]> {
+    let handle = match name {
+        Some(name) => {
+            let name = CString::new(name)?;
+            GetModuleHandleA(PCSTR::from_raw(name.as_ptr() as *const u8))
+        }
+        None => GetModuleHandleA(None),
+    }
+    .with_context(|| format!("{} isn't loaded", name.unwrap_or("the game module")))?;
+
+    let mut info = std::mem::zeroed::<MODULEINFO>();
+    let result = GetModuleInformation(
+        GetCurrentProcess(),
+        handle,
+        &mut info,
+        std::mem::size_of::<MODULEINFO>() as u32,
+    );
+
+    if !result.as_bool() {
+        bail!("GetModuleInformation failed");
+    }
+
+    Ok(std::slice::from_raw_parts(
+        info.lpBaseOfDll as *const u8,
+        info.SizeOfImage as usize,
+    ))
+}
+
+/// Resolves a symbol exported by a loaded module, written as `module!name` or
+/// `module!#ordinal`, following forwarders into other modules as the loader would.
+pub unsafe fn resolve_export(text: &str) -> Option<Resolved> {
+    let result = text
+        .parse::<ModuleSymbol>()
+        .map_err(anyhow::Error::from)
+        .and_then(find_export);
+
+    match result {
+        Ok(result) => {
+            debug!("resolve_export: resolved {} - {:p}", text, result);
+            Some((result, ResolutionSource::SymbolTable))
+        }
+        Err(e) => {
+            warn!("resolve_export: couldn't resolve {}: {:#}", text, e);
+            None
+        }
+    }
+}
+
+/// Resolves a symbol the game imports from another module, written as for
+/// `resolve_export`, to the import address table slot the game calls it through.
+pub unsafe fn resolve_import(text: &str) -> Option<Resolved> {
+    let result = text
+        .parse::<ModuleSymbol>()
+        .map_err(anyhow::Error::from)
+        .and_then(|symbol| find_import_slot(&symbol));
+
+    match result {
+        Ok(result) => {
+            debug!("resolve_import: resolved {} - {:p}", text, result);
+            Some((result as *const u8, ResolutionSource::SymbolTable))
+        }
+        Err(e) => {
+            warn!("resolve_import: couldn't resolve {}: {:#}", text, e);
+            None
+        }
+    }
+}
+
+unsafe fn find_export(mut symbol: ModuleSymbol) -> Result<*const u8> {
+    for _ in 0..=MAX_FORWARDS {
+        let image = loaded_module(Some(&symbol.module))?;
+        let exports = PeImage::parse(image, Layout::Mapped)?.exports()?;
+        let export = symbol::find_export(&exports, &symbol.symbol)
+            .with_context(|| format!("{} has no export {}", symbol.module, symbol.symbol))?;
+
+        match &export.target {
+            ExportTarget::Rva(rva) => return Ok(image.as_ptr().add(*rva as usize)),
+            ExportTarget::Forwarder(forwarder) => {
+                debug!("find_export: {} is forwarded to {}", symbol, forwarder);
+                symbol = ModuleSymbol::from_forwarder(forwarder)
+                    .with_context(|| format!("invalid forwarder {:?}", forwarder))?;
+            }
+        }
+    }
+
+    bail!("more than {} forwarders", MAX_FORWARDS)
+}
+
+unsafe fn find_import_slot(symbol: &ModuleSymbol) -> Result<*mut *const u8> {
+    let image = loaded_module(None)?;
+    let imports = PeImage::parse(image, Layout::Mapped)?.imports()?;
+    let import = symbol::find_import(&imports, &symbol.module, &symbol.symbol)
+        .with_context(|| format!("{} isn't imported", symbol))?;
+
+    Ok(image.as_ptr().add(import.iat_rva as usize) as *mut *const u8)
+}
@@ -1,215 +1,425 @@
-use anyhow::bail;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
 use ffxiv_client_structs::{
     MemberFunctionSignature, Signature, StaticAddressSignature, VTableSignature,
 };
+use grebuloff_resolver::{
+    module::{ModuleImage, SectionKind},
+    path::PointerPath,
+    resolve::{self, SignatureKind},
+    scanner::{self, Matches, Pattern},
+    xref,
+};
+use grebuloff_rpc::resolver::ResolutionSource;
 use log::{debug, info, warn};
-use windows::Win32::System::LibraryLoader::GetModuleHandleA;
-use windows::Win32::System::ProcessStatus::{GetModuleInformation, MODULEINFO};
-use windows::Win32::System::Threading::GetCurrentProcess;
-
-static mut MODULE_START: *const u8 = std::ptr::null();
-static mut MODULE_SIZE: usize = 0;
-
-static mut TEXT_START: *const u8 = std::ptr::null();
-static mut TEXT_SIZE: usize = 0;
-
-static mut RDATA_START: *const u8 = std::ptr::null();
-static mut RDATA_SIZE: usize = 0;
-
-static mut DATA_START: *const u8 = std::ptr::null();
-static mut DATA_SIZE: usize = 0;
-
-pub unsafe fn prepare() -> anyhow::Result<()> {
-    let handle = GetModuleHandleA(None)?;
-    let mut info = std::mem::zeroed::<MODULEINFO>();
-    let result = GetModuleInformation(
-        GetCurrentProcess(),
-        handle,
-        &mut info,
-        std::mem::size_of::<MODULEINFO>() as u32,
-    );
+use rustc_hash::FxHashMap;
+
+use super::{
+    cache::{BuildId, ResolutionCache},
+    database::AddressTable,
+    modules,
+    options::DatabaseMode,
+    report,
+};
+use crate::get_runtime_dir;
 
-    if !result.as_bool() {
-        bail!("GetModuleInformation failed");
-    }
+/// The game module, once `prepare` has found it.
+static MODULE: OnceLock<ModuleImage<'static>> = OnceLock::new();
+
+/// Identifies the game build, for caching resolutions.
+static BUILD_ID: OnceLock<BuildId> = OnceLock::new();
 
+/// Patterns collected by the `queue_*` resolvers, waiting to be scanned for together.
+static SCAN_QUEUE: Mutex<Vec<Pattern>> = Mutex::new(Vec::new());
+
+/// Matches in the game's .text of every pattern scanned for by `scan_queued`.
+static SCAN_RESULTS: OnceLock<FxHashMap<Pattern, Matches>> = OnceLock::new();
+
+/// Known addresses for this game build, used in place of scanning as configured.
+static ADDRESSES: OnceLock<AddressTable> = OnceLock::new();
+
+/// An address we resolved, and where it came from.
+pub type Resolved = (*const u8, ResolutionSource);
+
+/// Finds the game module and its sections, and loads what's known about this build.
+pub unsafe fn prepare() -> anyhow::Result<&'static ModuleImage<'static>> {
+    let image = modules::loaded_module(None)?;
     info!(
         "found module base: {:X}, size: {:X}",
-        info.lpBaseOfDll as usize, info.SizeOfImage
+        image.as_ptr() as usize,
+        image.len()
     );
 
-    MODULE_START = info.lpBaseOfDll as *const u8;
-    MODULE_SIZE = info.SizeOfImage as usize;
-
-    // adapted from FFXIVClientStructs.Resolver, which was adapted from Dalamud SigScanner
-    let base_address = info.lpBaseOfDll as *const u8;
-
-    // We don't want to read all of IMAGE_DOS_HEADER or IMAGE_NT_HEADER stuff so we cheat here.
-    let nt_new_offset = std::ptr::read_unaligned(base_address.offset(0x3C) as *const i32);
-    let nt_header = base_address.offset(nt_new_offset as isize);
-
-    // IMAGE_NT_HEADER
-    let file_header = nt_header.offset(4);
-    let num_sections = std::ptr::read_unaligned(file_header.offset(6) as *const i16);
-
-    // IMAGE_OPTIONAL_HEADER
-    let optional_header = file_header.offset(20);
-
-    let section_header = optional_header.offset(240); // IMAGE_OPTIONAL_HEADER64
-
-    // IMAGE_SECTION_HEADER
-    let mut section_cursor = section_header;
-    for _ in 0..num_sections {
-        let section_name = std::ptr::read_unaligned(section_cursor as *const i64);
-
-        // .text
-        match section_name {
-            0x747865742E => {
-                // .text
-                TEXT_START = base_address.offset(std::ptr::read_unaligned(
-                    section_cursor.offset(12) as *const i32
-                ) as isize);
-                TEXT_SIZE =
-                    std::ptr::read_unaligned(section_cursor.offset(8) as *const i32) as usize;
-            }
-            0x617461642E => {
-                // .data
-                DATA_START = base_address.offset(std::ptr::read_unaligned(
-                    section_cursor.offset(12) as *const i32
-                ) as isize);
-                DATA_SIZE =
-                    std::ptr::read_unaligned(section_cursor.offset(8) as *const i32) as usize;
-            }
-            0x61746164722E => {
-                // .rdata
-                RDATA_START = base_address.offset(std::ptr::read_unaligned(
-                    section_cursor.offset(12) as *const i32,
-                ) as isize);
-                RDATA_SIZE =
-                    std::ptr::read_unaligned(section_cursor.offset(8) as *const i32) as usize;
-            }
-            _ => {}
-        }
+    let module = ModuleImage::parse(image)?;
+    let pe = module.pe()?;
+
+    let build = BuildId::of(&pe);
+    info!("game build: {:?}", build);
+    BUILD_ID.set(build).ok();
 
-        section_cursor = section_cursor.offset(40); // advance by 40
+    if super::options().address_database != DatabaseMode::Disabled {
+        ADDRESSES
+            .set(AddressTable::load(get_runtime_dir(), build, image.len()))
+            .ok();
+    }
+
+    for section in &pe.sections {
+        debug!(
+            "section {:<8} rva {:X}(+{:X}), characteristics {:08X}",
+            section.name, section.virtual_address, section.virtual_size, section.characteristics
+        );
     }
 
     info!(
-        "image sections: .text {:X}(+{:X}), .data {:X}(+{:X}), .rdata {:X}(+{:X})",
-        TEXT_START as usize,
-        TEXT_SIZE,
-        DATA_START as usize,
-        DATA_SIZE,
-        RDATA_START as usize,
-        RDATA_SIZE
+        "image sections: .text {:X?}, .data {:X?}, .rdata {:X?}",
+        module.text_range(),
+        module.section_range(SectionKind::Data),
+        module.section_range(SectionKind::Rdata)
     );
 
-    Ok(())
+    Ok(MODULE.get_or_init(|| module))
 }
 
-pub unsafe fn resolve_vtable(input: &VTableSignature) -> *const u8 {
-    let sig_result = find_sig(TEXT_START, TEXT_SIZE, &input.signature);
-
-    if sig_result == std::ptr::null() {
-        warn!("resolve_vtable: couldn't resolve {}", input.signature);
+/// Returns the game module, if `prepare` has found it.
+pub fn module() -> Option<&'static ModuleImage<'static>> {
+    MODULE.get()
+}
 
-        sig_result
-    } else {
-        // get the 4 bytes at input.offset bytes past the result
-        let access_offset = std::ptr::read_unaligned(sig_result.offset(input.offset) as *const i32);
-        let mut result =
-            sig_result.offset(input.offset + 4 + access_offset as isize) as *const usize;
-
-        if input.is_pointer {
-            // dereference the pointer
-            result = std::ptr::read_unaligned(result as *const *const usize);
-        }
+/// Returns true if `module` is the game module, which the batch scan results and
+/// the address database are for.
+fn is_game(module: &ModuleImage) -> bool {
+    MODULE
+        .get()
+        .is_some_and(|game| game.base() == module.base())
+}
 
-        debug!(
-            "resolve_vtable: resolved {} (offset {}, is_pointer {}) - {:p}",
-            input.signature, input.offset, input.is_pointer, sig_result
-        );
+pub fn resolve_vtable(module: &ModuleImage, input: &VTableSignature) -> Option<Resolved> {
+    let result = resolve_relative_sig(
+        module,
+        SignatureKind::VTable,
+        &input.signature,
+        input.offset,
+        input.is_pointer,
+    );
 
-        result as *const u8
+    match result {
+        None => warn!("resolve_vtable: couldn't resolve {}", input.signature),
+        Some((result, source)) => debug!(
+            "resolve_vtable: resolved {} (offset {}, is_pointer {}) - {:p} ({:?})",
+            input.signature, input.offset, input.is_pointer, result, source
+        ),
     }
+
+    result
 }
 
-pub unsafe fn resolve_static_address(input: &StaticAddressSignature) -> *const u8 {
-    let sig_result = find_sig(TEXT_START, TEXT_SIZE, &input.signature);
+pub fn resolve_static_address(
+    module: &ModuleImage,
+    input: &StaticAddressSignature,
+) -> Option<Resolved> {
+    let result = resolve_relative_sig(
+        module,
+        SignatureKind::StaticAddress,
+        &input.signature,
+        input.offset,
+        input.is_pointer,
+    );
 
-    if sig_result == std::ptr::null() {
-        warn!(
+    match result {
+        None => warn!(
             "resolve_static_address: couldn't resolve {}",
             input.signature
-        );
+        ),
+        Some((result, source)) => debug!(
+            "resolve_static_address: resolved {} (offset {}, is_pointer {}) - {:p} ({:?})",
+            input.signature, input.offset, input.is_pointer, result, source
+        ),
+    }
 
-        sig_result
-    } else {
-        // get the 4 bytes at input.offset bytes past the result
-        let access_offset = std::ptr::read_unaligned(sig_result.offset(input.offset) as *const i32);
-        let mut result =
-            sig_result.offset(input.offset + 4 + access_offset as isize) as *const usize;
-
-        if input.is_pointer {
-            // dereference the pointer
-            result = std::ptr::read_unaligned(result as *const *const usize);
+    result
+}
+
+/// Resolves a function by its signature, or by `name` in the address database.
+pub fn resolve_member_function(
+    module: &ModuleImage,
+    name: Option<&str>,
+    input: &MemberFunctionSignature,
+) -> Option<Resolved> {
+    let kind = SignatureKind::MemberFunction;
+    let result = locate(module, kind, name, &input.signature, 0)
+        .map(|(rva, source)| (module.address(rva), source));
+
+    match result {
+        None => warn!(
+            "resolve_member_function: couldn't resolve {}",
+            input.signature
+        ),
+        Some((result, source)) => debug!(
+            "resolve_member_function: resolved {} - {:p} ({:?})",
+            input.signature, result, source
+        ),
+    }
+
+    result
+}
+
+/// Resolves a string literal to the start of the one function that references it.
+pub fn resolve_string_xref(module: &ModuleImage, string: &str) -> Option<Resolved> {
+    let result = module
+        .pe()
+        .map_err(|e| e.to_string())
+        .and_then(|pe| xref::resolve_string_xref(&pe, string).map_err(|e| e.to_string()));
+
+    match result {
+        Ok(rva) => {
+            let result = module.address(rva);
+            debug!("resolve_string_xref: resolved {:?} - {:p}", string, result);
+            Some((result, ResolutionSource::Scan))
         }
+        Err(e) => {
+            warn!("resolve_string_xref: couldn't resolve {:?}: {}", string, e);
+            None
+        }
+    }
+}
 
-        debug!(
-            "resolve_static_address: resolved {} (offset {}, is_pointer {}, access_offset {:X}, sig_result {:p}) - {:p} p, {:X} x",
-            input.signature, input.offset, input.is_pointer, access_offset, sig_result, result, result as usize
-        );
+/// Resolves a signature for an instruction referencing an address relative to itself,
+/// optionally dereferencing the result. Returns `None` if the signature wasn't found,
+/// and a null pointer if it was, but the pointer it refers to is null.
+fn resolve_relative_sig(
+    module: &ModuleImage,
+    kind: SignatureKind,
+    sig: &Signature,
+    offset: isize,
+    is_pointer: bool,
+) -> Option<Resolved> {
+    let (rva, source) = locate(module, kind, None, sig, offset)?;
+
+    if is_pointer {
+        // dereference the pointer
+        Some((module.read_pointer(rva)?, source))
+    } else {
+        Some((module.address(rva), source))
+    }
+}
 
-        result as *const u8
+/// Finds the RVA that a signature resolves to, by scanning for it or from the
+/// address database, as configured.
+fn locate(
+    module: &ModuleImage,
+    kind: SignatureKind,
+    name: Option<&str>,
+    sig: &Signature,
+    offset: isize,
+) -> Option<(usize, ResolutionSource)> {
+    let scan = || {
+        find_sig(module, sig)
+            .and_then(|rva| resolve::resolve_match(module.data(), rva, kind, offset))
+            .map(|rva| (rva, ResolutionSource::Scan))
+    };
+    let database =
+        || lookup_address(module, kind, name, sig).map(|rva| (rva, ResolutionSource::Database));
+
+    match super::options().address_database {
+        DatabaseMode::Fallback => scan().or_else(database),
+        DatabaseMode::Prefer => database().or_else(scan),
+        DatabaseMode::Disabled => scan(),
     }
 }
 
-pub unsafe fn resolve_member_function(input: &MemberFunctionSignature) -> *const u8 {
-    let result = find_sig(TEXT_START, TEXT_SIZE, &input.signature);
+/// Looks up a signature in the address database for this build, if `module` is the game,
+/// by name if there is one and then by the signature itself.
+fn lookup_address(
+    module: &ModuleImage,
+    kind: SignatureKind,
+    name: Option<&str>,
+    sig: &Signature,
+) -> Option<usize> {
+    if !is_game(module) {
+        return None;
+    }
 
-    if result == std::ptr::null() {
+    let table = ADDRESSES.get()?;
+    let pattern = Pattern::from_byte_mask(&sig.bytes, &sig.mask);
+    let entry = name
+        .and_then(|name| table.get_by_name(report::kind(kind), name))
+        .or_else(|| table.get(report::kind(kind), &pattern.to_string()))?;
+
+    // a function outside .text, say, is an entry for another build or a typo, and
+    // jumping to it would crash rather than fail to resolve
+    let section = expected_section(kind);
+    if !module
+        .section_range(section)
+        .is_some_and(|range| range.contains(&entry.rva))
+    {
         warn!(
-            "resolve_member_function: couldn't resolve {}",
-            input.signature
-        );
-    } else {
-        debug!(
-            "resolve_member_function: resolved {} - {:p}",
-            input.signature, result
+            "lookup_address: ignoring {} at {:X} from the address database, as it isn't in {}",
+            entry.name,
+            entry.rva,
+            section.name()
         );
+        return None;
     }
 
-    result
+    info!(
+        "lookup_address: using {} at {:X} from the address database for {}",
+        entry.name, entry.rva, pattern
+    );
+    Some(entry.rva)
 }
 
-unsafe fn find_sig(start_addr: *const u8, size: usize, sig: &Signature) -> *const u8 {
-    let sig_len = sig.bytes.len();
+/// The section that what a signature resolves to is in: code for functions,
+/// read-only data for vtables, and writable data for static variables.
+fn expected_section(kind: SignatureKind) -> SectionKind {
+    match kind {
+        SignatureKind::MemberFunction => SectionKind::Text,
+        SignatureKind::VTable => SectionKind::Rdata,
+        SignatureKind::StaticAddress => SectionKind::Data,
+    }
+}
+
+/// Resolves the base of a pointer path to an address in the module.
+pub fn resolve_path_base(module: &ModuleImage, path: &PointerPath) -> Option<*const u8> {
+    let rva = path.resolve_base(module.data(), module.text_range())?;
 
-    // we use two cursors here to handle edge cases
-    // first, we iterate over the entire memory region
-    'prog: for pi in 0..size {
-        let prog_cursor = start_addr.add(pi);
+    Some(module.address(rva))
+}
 
-        // next, we attempt to match the entire signature from the program cursor
-        for si in 0..sig_len {
-            let sig_cursor = prog_cursor.add(si);
+pub unsafe fn queue_vtable(input: &VTableSignature) -> *const u8 {
+    queue_sig(&input.signature)
+}
 
-            let valid = sig.mask[si] == 0x00 || sig.bytes[si] == *sig_cursor;
-            if !valid {
-                continue 'prog;
-            }
+pub unsafe fn queue_static_address(input: &StaticAddressSignature) -> *const u8 {
+    queue_sig(&input.signature)
+}
+
+pub unsafe fn queue_member_function(input: &MemberFunctionSignature) -> *const u8 {
+    queue_sig(&input.signature)
+}
+
+/// Queues a signature to be found by `scan_queued`.
+/// Always returns a null pointer, so that it can stand in for a resolver.
+fn queue_sig(sig: &Signature) -> *const u8 {
+    let pattern = Pattern::from_byte_mask(&sig.bytes, &sig.mask);
+    SCAN_QUEUE.lock().unwrap().push(pattern);
+
+    std::ptr::null()
+}
+
+/// Finds every queued signature in a single pass over the game's .text.
+/// Signatures resolved in the game afterwards use these results instead of scanning again.
+///
+/// Where each signature matched is cached between launches, so that as long as
+/// the game hasn't been updated, only signatures missing from the cache are scanned for.
+pub fn scan_queued(module: &ModuleImage) {
+    let mut patterns = std::mem::take(&mut *SCAN_QUEUE.lock().unwrap());
+    patterns.sort_unstable_by(|a, b| (a.bytes(), a.mask()).cmp(&(b.bytes(), b.mask())));
+    patterns.dedup();
+
+    let options = super::options();
+    let text_range = module.text_range();
+    let text_rva = text_range.start;
+    let mut results = FxHashMap::default();
+
+    let mut cache = match BUILD_ID.get() {
+        Some(&build) if options.cache => Some(ResolutionCache::load(get_runtime_dir(), build)),
+        _ => None,
+    };
+
+    // anything that still matches where it did last time doesn't need scanning for,
+    // and is as ambiguous as it was then
+    let counting = options.count_matches();
+    if let Some(cache) = &cache {
+        patterns.retain(|pattern| {
+            let cached = cache
+                .get(pattern, module.data(), counting)
+                .filter(|(rva, _)| text_range.contains(rva));
+            let Some((rva, count)) = cached else {
+                return true;
+            };
+
+            let first = Some(rva - text_rva);
+            results.insert(pattern.clone(), Matches { first, count });
+            false
+        });
+
+        info!("scan_queued: {} signatures found in cache", results.len());
+    }
+
+    let start = Instant::now();
+    let scanned = if counting {
+        scanner::count_batch(module.text(), &patterns)
+    } else {
+        scanner::find_batch(module.text(), &patterns)
+            .into_iter()
+            .map(Matches::from)
+            .collect()
+    };
+
+    let found = scanned.iter().filter(|m| m.first.is_some()).count();
+    info!(
+        "scan_queued: found {}/{} signatures in {:?}",
+        found,
+        patterns.len(),
+        start.elapsed()
+    );
+
+    if let Some(cache) = &mut cache {
+        for (pattern, matches) in patterns.iter().zip(&scanned) {
+            let rva = matches.first.map(|offset| text_rva + offset);
+            cache.update(pattern, rva, counting.then_some(matches.count));
         }
 
-        // if we get here, we found the signature
-        let b = *prog_cursor;
-        return if b == 0xE8 || b == 0xE9 {
-            // relative call
-            let offset = std::ptr::read_unaligned(prog_cursor.add(1) as *const i32);
-            prog_cursor.add(5).offset(offset as isize)
-        } else {
-            prog_cursor
-        };
+        if let Err(e) = cache.save(get_runtime_dir()) {
+            warn!("scan_queued: failed to save resolution cache: {:?}", e);
+        }
     }
 
-    std::ptr::null()
+    results.extend(patterns.into_iter().zip(scanned));
+    if counting {
+        let ambiguous = results.values().filter(|m| m.is_ambiguous()).count();
+        info!("scan_queued: {} signatures are ambiguous", ambiguous);
+    }
+
+    if SCAN_RESULTS.set(results).is_err() {
+        warn!("scan_queued: signatures were already scanned for, ignoring new results");
+    }
+}
+
+/// Finds a signature in .text, returning the RVA of the match.
+/// Signatures that match more than once are rejected in strict mode.
+fn find_sig(module: &ModuleImage, sig: &Signature) -> Option<usize> {
+    let pattern = Pattern::from_byte_mask(&sig.bytes, &sig.mask);
+    let options = super::options();
+
+    // use the batch scan if we have it, otherwise fall back to scanning on our own
+    let batched = SCAN_RESULTS
+        .get()
+        .filter(|_| is_game(module))
+        .and_then(|results| results.get(&pattern));
+    let matches = match batched {
+        Some(&matches) => matches,
+        None if options.count_matches() => scanner::count(module.text(), &pattern),
+        None => scanner::find(module.text(), &pattern).into(),
+    };
+    report::record_matches(matches.count);
+
+    let offset = matches.first?;
+    if matches.is_ambiguous() {
+        if options.strict {
+            warn!(
+                "find_sig: {} matches {} times, refusing to resolve it in strict mode",
+                sig, matches.count
+            );
+            return None;
+        }
+
+        warn!(
+            "find_sig: {} matches {} times, using the first match",
+            sig, matches.count
+        );
+    }
+
+    Some(module.text_range().start + offset)
 }
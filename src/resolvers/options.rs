@@ -0,0 +1,171 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{bail, Context, Result};
+use grebuloff_rpc::resolver::ResolutionReport;
+use serde::Deserialize;
+
+/// The name of the file in the runtime directory that resolver options are read from.
+pub const OPTIONS_FILE: &str = "resolvers.json";
+
+/// User-configurable resolver behaviour. Missing fields take their default value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ResolverOptions {
+    /// Count every match of each signature, rather than stopping at the first,
+    /// and warn about signatures that match more than once.
+    pub detect_ambiguity: bool,
+    /// Refuse to resolve signatures that match more than once, instead of
+    /// warning and using the first match. Implies `detect_ambiguity`.
+    pub strict: bool,
+    /// Reuse where signatures matched on the previous launch, as long as the game
+    /// hasn't been updated since. When counting matches, only matches that were
+    /// counted when they were cached are reused.
+    pub cache: bool,
+    /// Whether to keep loading when signatures fail to resolve.
+    pub failure_policy: FailurePolicy,
+    /// When to use the address database in place of scanning.
+    pub address_database: DatabaseMode,
+    /// Named pointer paths to evaluate for the resolution report, in the format
+    /// accepted by `PointerPath`.
+    pub pointer_paths: BTreeMap<String, String>,
+    /// Named functions to find by a string literal they reference, resolved during
+    /// init and included in the resolution report.
+    pub string_references: BTreeMap<String, String>,
+    /// Named symbols exported by loaded modules, as `module!name` or `module!#ordinal`,
+    /// resolved during init and included in the resolution report.
+    pub exports: BTreeMap<String, String>,
+    /// Named symbols the game imports from other modules, written as for `exports`,
+    /// resolved to their import address table slots during init and included in the
+    /// resolution report.
+    pub imports: BTreeMap<String, String>,
+}
+
+impl Default for ResolverOptions {
+    fn default() -> Self {
+        Self {
+            detect_ambiguity: false,
+            strict: false,
+            cache: true,
+            failure_policy: FailurePolicy::default(),
+            address_database: DatabaseMode::default(),
+            pointer_paths: BTreeMap::new(),
+            string_references: BTreeMap::new(),
+            exports: BTreeMap::new(),
+            imports: BTreeMap::new(),
+        }
+    }
+}
+
+impl ResolverOptions {
+    /// Reads the options file from the given directory, if there is one.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(OPTIONS_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let file = std::fs::read(&path).with_context(|| format!("failed to read {:?}", path))?;
+        serde_json::from_slice(&file).with_context(|| format!("failed to parse {:?}", path))
+    }
+
+    pub fn count_matches(&self) -> bool {
+        self.detect_ambiguity || self.strict
+    }
+}
+
+/// What to do when signatures fail to resolve during init.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Stop loading if an address that Grebuloff can't run without fails to resolve.
+    #[default]
+    AbortOnCritical,
+    /// Stop loading if any signature fails to resolve.
+    AbortOnAny,
+    /// Always keep loading, even if that will crash later on.
+    Continue,
+}
+
+impl FailurePolicy {
+    /// Checks a resolution report against the policy, returning an error
+    /// suitable for showing to the user if loading should stop.
+    pub fn check(self, report: &ResolutionReport) -> Result<()> {
+        if self == FailurePolicy::Continue {
+            return Ok(());
+        }
+
+        if !report.critical_failures.is_empty() {
+            bail!(
+                "Grebuloff couldn't find some parts of the game that it needs to run ({}). \
+                The game may have been updated since this version of Grebuloff was released.",
+                report.critical_failures.join(", ")
+            );
+        }
+
+        let failures = report.failures().count();
+        if self == FailurePolicy::AbortOnAny && failures > 0 {
+            bail!(
+                "{} of {} signatures failed to resolve, and the resolver is configured \
+                to stop loading when any signature fails. See the resolution report for details.",
+                failures,
+                report.signatures.len()
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// When to take addresses from the address database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DatabaseMode {
+    /// Only for signatures that fail to resolve by scanning.
+    #[default]
+    Fallback,
+    /// Whenever the database has an address, scanning only for the rest.
+    Prefer,
+    /// Never.
+    Disabled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use grebuloff_rpc::resolver::{ResolutionStatus, SignatureKind, SignatureResolution};
+
+    fn report(status: ResolutionStatus, critical_failures: &[&str]) -> ResolutionReport {
+        ResolutionReport {
+            signatures: vec![SignatureResolution {
+                kind: SignatureKind::StaticAddress,
+                signature: "48 8B 0D ?? ?? ?? ??".to_string(),
+                status,
+                source: None,
+                address: None,
+                matches: None,
+                elapsed_us: 0,
+            }],
+            critical_failures: critical_failures.iter().map(|s| s.to_string()).collect(),
+            elapsed_ms: 0,
+            pointer_paths: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_failure_policy() {
+        let options: ResolverOptions =
+            serde_json::from_str(r#"{ "failure_policy": "abort_on_any" }"#).unwrap();
+        assert_eq!(options.failure_policy, FailurePolicy::AbortOnAny);
+        assert!(options.cache);
+
+        let not_found = report(ResolutionStatus::NotFound, &[]);
+        let null_pointer = report(ResolutionStatus::NullPointer, &[]);
+        let critical = report(ResolutionStatus::NotFound, &["Framework instance"]);
+
+        assert!(FailurePolicy::AbortOnCritical.check(&not_found).is_ok());
+        assert!(FailurePolicy::AbortOnCritical.check(&critical).is_err());
+        assert!(FailurePolicy::AbortOnAny.check(&not_found).is_err());
+        assert!(FailurePolicy::AbortOnAny.check(&null_pointer).is_ok());
+        assert!(FailurePolicy::Continue.check(&critical).is_ok());
+    }
+}
@@ -0,0 +1,95 @@
+use std::{collections::BTreeMap, ffi::c_void, mem, sync::OnceLock};
+
+use grebuloff_resolver::{module::ModuleImage, path::PointerPath};
+use grebuloff_rpc::resolver::PointerPathResolution;
+use log::{debug, warn};
+use windows::Win32::System::Memory::{
+    VirtualQuery, MEMORY_BASIC_INFORMATION, MEM_COMMIT, PAGE_GUARD, PAGE_NOACCESS,
+};
+
+use super::native;
+
+/// A pointer path declared in the resolver options.
+struct ConfiguredPath {
+    name: String,
+    text: String,
+    /// The parsed path and the address of its base, or why we couldn't get them.
+    resolved: Result<(PointerPath, usize), String>,
+}
+
+static PATHS: OnceLock<Vec<ConfiguredPath>> = OnceLock::new();
+
+/// Parses the pointer paths declared in the resolver options, and resolves their bases.
+/// Only the bases are resolved now, as the pointers along the paths may not be set yet.
+pub fn resolve_configured(module: &ModuleImage, paths: &BTreeMap<String, String>) {
+    let paths = paths
+        .iter()
+        .map(|(name, text)| {
+            let resolved = text
+                .parse::<PointerPath>()
+                .map_err(|e| e.to_string())
+                .and_then(|path| match native::resolve_path_base(module, &path) {
+                    Some(base) => Ok((path, base as usize)),
+                    None => Err("couldn't resolve the base of the path".to_string()),
+                });
+
+            match &resolved {
+                Ok((_, base)) => debug!("pointer path {}: base at {:X}", name, base),
+                Err(e) => warn!("pointer path {} ({}): {}", name, text, e),
+            }
+
+            ConfiguredPath {
+                name: name.clone(),
+                text: text.clone(),
+                resolved,
+            }
+        })
+        .collect();
+
+    if PATHS.set(paths).is_err() {
+        warn!("pointer paths were already resolved, ignoring new paths");
+    }
+}
+
+/// Evaluates every configured pointer path as things stand.
+pub unsafe fn evaluate_configured() -> Vec<PointerPathResolution> {
+    let Some(paths) = PATHS.get() else {
+        return Vec::new();
+    };
+
+    paths
+        .iter()
+        .map(|configured| {
+            let result = configured.resolved.clone().and_then(|(path, base)| {
+                path.evaluate(base, |address| read_pointer(address))
+                    .map_err(|e| e.to_string())
+            });
+
+            PointerPathResolution {
+                name: configured.name.clone(),
+                path: configured.text.clone(),
+                address: result.as_ref().ok().map(|&address| address as u64),
+                error: result.err(),
+            }
+        })
+        .collect()
+}
+
+/// Reads a pointer from our own process, as long as the memory is readable.
+unsafe fn read_pointer(address: usize) -> Option<usize> {
+    let mut info = MEMORY_BASIC_INFORMATION::default();
+    let size = VirtualQuery(
+        Some(address as *const c_void),
+        &mut info,
+        mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+    );
+    if size == 0 {
+        return None;
+    }
+
+    let readable = info.State == MEM_COMMIT
+        && info.Protect.0 & (PAGE_NOACCESS.0 | PAGE_GUARD.0) == 0
+        && address + mem::size_of::<usize>() <= info.BaseAddress as usize + info.RegionSize;
+
+    readable.then(|| std::ptr::read_unaligned(address as *const usize))
+}
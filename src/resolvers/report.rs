@@ -0,0 +1,105 @@
+use std::{cell::Cell, path::Path, sync::Mutex, time::Instant};
+
+use anyhow::{Context, Result};
+use ffxiv_client_structs::Signature;
+use grebuloff_resolver::{resolve, scanner::Pattern};
+use grebuloff_rpc::resolver::{
+    ResolutionReport, ResolutionSource, ResolutionStatus, SignatureKind, SignatureResolution,
+};
+
+/// The name of the file in the runtime directory that the resolution report is written to.
+pub const REPORT_FILE: &str = "resolver-report.json";
+
+static REPORT: Mutex<ResolutionReport> = Mutex::new(ResolutionReport {
+    signatures: Vec::new(),
+    critical_failures: Vec::new(),
+    elapsed_ms: 0,
+    pointer_paths: Vec::new(),
+});
+
+thread_local! {
+    /// How many times the signature being recorded on this thread matched, if it was scanned for.
+    static MATCHES: Cell<Option<u32>> = Cell::new(None);
+}
+
+/// Runs a resolver, recording the outcome in the report.
+///
+/// The resolver returns `None` if the signature wasn't found, or the address
+/// it resolved to otherwise, which may be null for pointers that aren't set yet.
+pub fn record(
+    kind: resolve::SignatureKind,
+    sig: &Signature,
+    resolver: impl FnOnce() -> Option<(*const u8, ResolutionSource)>,
+) -> *const u8 {
+    let signature = Pattern::from_byte_mask(&sig.bytes, &sig.mask).to_string();
+    record_as(self::kind(kind), signature, resolver)
+}
+
+/// Runs a resolver for anything that stands in for a signature, recording the
+/// outcome in the report as `signature`.
+pub fn record_as(
+    kind: SignatureKind,
+    signature: String,
+    resolver: impl FnOnce() -> Option<(*const u8, ResolutionSource)>,
+) -> *const u8 {
+    MATCHES.with(|matches| matches.set(None));
+    let start = Instant::now();
+    let result = resolver();
+    let elapsed = start.elapsed();
+    let matches = MATCHES.with(Cell::take);
+
+    let status = match result {
+        None => ResolutionStatus::NotFound,
+        Some((address, _)) if address.is_null() => ResolutionStatus::NullPointer,
+        Some(_) => ResolutionStatus::Resolved,
+    };
+
+    let resolution = SignatureResolution {
+        kind,
+        signature,
+        status,
+        source: result.map(|(_, source)| source),
+        address: result
+            .map(|(address, _)| address)
+            .filter(|address| !address.is_null())
+            .map(|address| address as u64),
+        matches,
+        elapsed_us: elapsed.as_micros() as u64,
+    };
+    REPORT.lock().unwrap().signatures.push(resolution);
+
+    result.map_or(std::ptr::null(), |(address, _)| address)
+}
+
+/// Notes how many times the signature being recorded matched, for the report.
+/// Called by resolvers that scan, from within `record`.
+pub fn record_matches(count: usize) {
+    MATCHES.with(|matches| matches.set(Some(count as u32)));
+}
+
+/// Converts a signature kind to its equivalent in the report.
+pub fn kind(kind: resolve::SignatureKind) -> SignatureKind {
+    match kind {
+        resolve::SignatureKind::VTable => SignatureKind::VTable,
+        resolve::SignatureKind::StaticAddress => SignatureKind::StaticAddress,
+        resolve::SignatureKind::MemberFunction => SignatureKind::MemberFunction,
+    }
+}
+
+/// Records the outcome of init, once every signature has been resolved.
+pub fn finish_init(critical_failures: Vec<String>, elapsed_ms: u64) {
+    let mut report = REPORT.lock().unwrap();
+    report.critical_failures = critical_failures;
+    report.elapsed_ms = elapsed_ms;
+}
+
+/// Returns a copy of the report as it stands.
+pub fn snapshot() -> ResolutionReport {
+    REPORT.lock().unwrap().clone()
+}
+
+pub fn save(dir: &Path, report: &ResolutionReport) -> Result<()> {
+    let path = dir.join(REPORT_FILE);
+    let file = serde_json::to_vec_pretty(report)?;
+    std::fs::write(&path, file).with_context(|| format!("failed to write {:?}", path))
+}
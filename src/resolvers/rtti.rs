@@ -0,0 +1,38 @@
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use grebuloff_resolver::{module::ModuleImage, rtti::Rtti};
+use log::{info, warn};
+
+use super::native;
+
+/// The classes in the game module with RTTI.
+static RTTI: OnceLock<Rtti> = OnceLock::new();
+
+/// Finds the classes in a module with RTTI, which takes a full pass over the
+/// module's data sections.
+fn parse(module: &ModuleImage) -> Rtti {
+    let start = Instant::now();
+    let rtti = match module.pe() {
+        Ok(pe) => Rtti::parse(&pe, module.base() as u64),
+        Err(e) => {
+            warn!("rtti: failed to parse module: {}", e);
+            Rtti::default()
+        }
+    };
+
+    info!(
+        "rtti: found {} classes in {:?}",
+        rtti.classes().len(),
+        start.elapsed()
+    );
+    rtti
+}
+
+/// Looks up the primary vtable of a class in the game module by its mangled or
+/// demangled name. The game's classes are parsed the first time they're needed.
+pub fn vtable(class: &str) -> Option<*const u8> {
+    let module = native::module()?;
+    let rva = RTTI.get_or_init(|| parse(module)).vtable(class)?;
+    Some(module.address(rva))
+}
@@ -0,0 +1,193 @@
+//! Scanning on behalf of scripts and addons, for data the LLRT doesn't resolve itself,
+//! and finding the callers of functions, for working out how they're used.
+
+use std::sync::{Mutex, OnceLock};
+
+use grebuloff_resolver::{
+    calls::{self, ReferenceKind},
+    module::{ModuleImage, SectionKind},
+    search::{self, Query, QueryKind, SearchCache},
+};
+use grebuloff_rpc::resolver::{
+    Caller, CallerKind, CallersRequest, CallersResult, ScanMatch, ScanPatternKind, ScanRequest,
+    ScanResult, ScanSection,
+};
+
+/// The results of earlier scans, to reuse for identical scans.
+static CACHE: OnceLock<Mutex<SearchCache>> = OnceLock::new();
+
+/// Scans a section of `module` for the requested pattern.
+pub fn scan(module: &ModuleImage, request: &ScanRequest) -> ScanResult {
+    let query = match Query::new(
+        &request.pattern,
+        query_kind(request.pattern_kind),
+        request.max_results as usize,
+    ) {
+        Ok(query) => query,
+        Err(e) => return failed(request, e),
+    };
+    let kind = section_kind(request.section);
+
+    let cache = CACHE.get_or_init(Default::default);
+    let cached = if request.use_cache {
+        cache.lock().unwrap().get(module, kind, &query)
+    } else {
+        None
+    };
+
+    let (found, cached) = match cached {
+        Some(found) => (found, true),
+        None => match search::search(module, kind, &query) {
+            Ok(found) => {
+                cache
+                    .lock()
+                    .unwrap()
+                    .insert(module, kind, query, found.clone());
+                (found, false)
+            }
+            Err(e) => return failed(request, e),
+        },
+    };
+
+    let base = module.base() as u64;
+    ScanResult {
+        id: request.id,
+        matches: found
+            .rvas
+            .iter()
+            .map(|&rva| ScanMatch {
+                rva: rva as u64,
+                address: base + rva as u64,
+            })
+            .collect(),
+        truncated: found.truncated,
+        cached,
+        error: None,
+    }
+}
+
+/// Returns the result of a scan that couldn't be run.
+pub fn failed(request: &ScanRequest, error: impl ToString) -> ScanResult {
+    ScanResult {
+        id: request.id,
+        error: Some(error.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Finds everything referring to the requested RVA in `module`.
+pub fn find_callers(module: &ModuleImage, request: &CallersRequest) -> CallersResult {
+    let base = module.base() as u64;
+    let references = module.pe().map_err(|e| e.to_string()).and_then(|pe| {
+        let vtable_base = request.include_vtables.then_some(base);
+        calls::find_callers(&pe, request.rva as usize, vtable_base).map_err(|e| e.to_string())
+    });
+
+    match references {
+        Ok(references) => CallersResult {
+            id: request.id,
+            callers: references
+                .into_iter()
+                .map(|reference| Caller {
+                    kind: match reference.kind {
+                        ReferenceKind::Call => CallerKind::Call,
+                        ReferenceKind::Jump => CallerKind::Jump,
+                        ReferenceKind::VTableSlot => CallerKind::VTableSlot,
+                    },
+                    rva: reference.rva as u64,
+                    address: base + reference.rva as u64,
+                    function: reference.function.map(|rva| rva as u64),
+                })
+                .collect(),
+            error: None,
+        },
+        Err(e) => CallersResult {
+            id: request.id,
+            error: Some(e),
+            ..Default::default()
+        },
+    }
+}
+
+fn section_kind(section: ScanSection) -> SectionKind {
+    match section {
+        ScanSection::Text => SectionKind::Text,
+        ScanSection::Data => SectionKind::Data,
+        ScanSection::Rdata => SectionKind::Rdata,
+    }
+}
+
+fn query_kind(kind: ScanPatternKind) -> QueryKind {
+    match kind {
+        ScanPatternKind::Signature => QueryKind::Signature,
+        ScanPatternKind::Text => QueryKind::Text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(pattern: &str, pattern_kind: ScanPatternKind, max_results: u32) -> ScanRequest {
+        ScanRequest {
+            id: 7,
+            pattern: pattern.to_string(),
+            pattern_kind,
+            section: ScanSection::Rdata,
+            max_results,
+            use_cache: true,
+        }
+    }
+
+    // matching and truncation are tested with the search itself, so this only covers
+    // what scanning adds. the cache is shared with every other scan, so the pattern
+    // here is one no other test uses
+    #[test]
+    fn test_scan() {
+        let mut image = vec![0u8; 0x100];
+        image[0xA0..0xA4].copy_from_slice(&[0x5A, 0xA5, 0x5A, 0xA5]);
+        let base = image.as_ptr() as u64;
+        let module = ModuleImage::with_sections(&image, 0..0x80, None, Some(0x80..0x100));
+
+        // matches are reported with their absolute address
+        let result = scan(
+            &module,
+            &request("5A A5 5A A5", ScanPatternKind::Signature, 1),
+        );
+        assert_eq!(result.id, 7);
+        assert_eq!(result.error, None);
+        assert_eq!(
+            result.matches,
+            [ScanMatch {
+                rva: 0xA0,
+                address: base + 0xA0
+            }]
+        );
+        assert!(!result.cached);
+
+        // the same scan again comes from the cache
+        let again = scan(
+            &module,
+            &request("5A A5 5A A5", ScanPatternKind::Signature, 1),
+        );
+        assert_eq!(again.matches, result.matches);
+        assert!(again.cached);
+    }
+
+    #[test]
+    fn test_scan_errors() {
+        let image = vec![0u8; 0x100];
+        let module = ModuleImage::with_sections(&image, 0..0x100, None, None);
+
+        // reported as messages, with no matches
+        let result = scan(&module, &request("", ScanPatternKind::Text, 1));
+        assert_eq!(result.error.as_deref(), Some("text is empty"));
+        assert!(result.matches.is_empty());
+
+        let result = scan(&module, &request("00", ScanPatternKind::Signature, 1));
+        assert_eq!(
+            result.error.as_deref(),
+            Some("the module has no .rdata section")
+        );
+    }
+}
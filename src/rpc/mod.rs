@@ -122,6 +122,7 @@ pub trait RpcServer {
             Some(RpcServerClientState::<Self::Clientbound> { send: send_tx }),
         )
         .await;
+        Self::on_connect(&our_send_tx)?;
 
         let mut buf = BytesMut::with_capacity(self.options().buffer_size);
 
@@ -273,6 +274,12 @@ pub trait RpcServer {
         send: mpsc::UnboundedSender<<Self as RpcServer>::Clientbound>,
         message: Self::Serverbound,
     ) -> Result<()>;
+
+    /// Called when a client connects, to queue anything it needs to catch up on
+    /// before any other messages are sent.
+    fn on_connect(_send: &mpsc::UnboundedSender<<Self as RpcServer>::Clientbound>) -> Result<()> {
+        Ok(())
+    }
 }
 
 #[cfg(test)]
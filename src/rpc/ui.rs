@@ -1,11 +1,11 @@
 use super::{RpcServer, RpcServerOptions};
-use crate::{get_execution_id, get_tokio_rt};
+use crate::{get_execution_id, get_tokio_rt, ui::UiMetrics};
 use anyhow::Result;
 use bytes::BytesMut;
 use grebuloff_rpc::ui::*;
-use log::debug;
+use log::{debug, error};
 use std::sync::OnceLock;
-use tokio::sync::mpsc;
+use tokio::{sync::mpsc, task};
 
 // 32MB buffer allows for 4K 32-bit RGBA images
 // TODO: make this configurable, or automatically sized based on the game window size
@@ -26,11 +26,68 @@ impl RpcServer for UiRpcServer {
     }
 
     fn process_incoming_message(
-        _send: tokio::sync::mpsc::UnboundedSender<<Self as RpcServer>::Clientbound>,
+        send: tokio::sync::mpsc::UnboundedSender<<Self as RpcServer>::Clientbound>,
         message: Self::Serverbound,
     ) -> anyhow::Result<()> {
         match message {
-            _ => unimplemented!(),
+            UiRpcServerboundMessage::ExportSnapshot(_) => {
+                // encoding a 4K snapshot takes long enough to hold up paints
+                task::spawn_blocking(|| {
+                    if let Err(e) = crate::ui::export_snapshot_png() {
+                        error!("failed to export UI snapshot: {}", e);
+                    }
+                });
+            }
+            UiRpcServerboundMessage::SetScalePolicy(msg) => {
+                crate::ui::set_scale_policy(msg.policy);
+            }
+            UiRpcServerboundMessage::SetUiScale(msg) => {
+                crate::ui::set_user_ui_scale(msg.scale);
+            }
+            UiRpcServerboundMessage::GetRejectedPaints(_) => {
+                let counts = crate::ui::rejected_paints();
+                send.send(UiRpcClientboundMessage::RejectedPaints(counts))?;
+            }
+            UiRpcServerboundMessage::GetResolutionReport(_) => {
+                let report = crate::resolvers::resolution_report();
+                send.send(UiRpcClientboundMessage::ResolutionReport(report))?;
+            }
+            UiRpcServerboundMessage::Scan(request) => {
+                // scanning a whole section takes a while, so do it off the pipe's task
+                task::spawn_blocking(move || {
+                    let result = crate::resolvers::scan(&request);
+                    if let Some(error) = &result.error {
+                        error!("scan {} failed: {}", request.id, error);
+                    }
+                    // the UI host may have disconnected in the meantime
+                    let _ = send.send(UiRpcClientboundMessage::ScanResult(result));
+                });
+            }
+            UiRpcServerboundMessage::FindCallers(request) => {
+                // like a scan, this walks all of .text
+                task::spawn_blocking(move || {
+                    let result = crate::resolvers::find_callers(&request);
+                    if let Some(error) = &result.error {
+                        error!("finding callers for {} failed: {}", request.id, error);
+                    }
+                    let _ = send.send(UiRpcClientboundMessage::Callers(result));
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn on_connect(send: &mpsc::UnboundedSender<<Self as RpcServer>::Clientbound>) -> Result<()> {
+        // the UI host may have been restarted, or connected late, and missed the
+        // messages sent as the game window changed
+        for message in crate::ui::window_state() {
+            send.send(message)?;
+        }
+
+        let metrics = crate::ui::metrics();
+        if metrics.has_size() {
+            send.send(Self::resize_message(metrics))?;
         }
 
         Ok(())
@@ -41,8 +98,13 @@ impl RpcServer for UiRpcServer {
         message: BytesMut,
     ) -> Result<()> {
         // UI only uses raw messages for paint, so process it directly
-        let paint = UiRpcServerboundPaint::from_raw(message)?;
-        crate::ui::update_buffer_on_paint(paint);
+        // malformed frames are counted and logged, and simply dropped -
+        // the next paint will replace them anyway
+        if let Err(e) =
+            UiRpcServerboundPaint::from_raw(message).and_then(crate::ui::update_buffer_on_paint)
+        {
+            crate::ui::record_rejected_paint(&e);
+        }
 
         Ok(())
     }
@@ -64,14 +126,32 @@ impl UiRpcServer {
         unsafe { UI_RPC_SERVER.get_or_init(Self::new) }
     }
 
-    pub fn resize(width: u32, height: u32) {
+    /// Queues a message to be sent to the UI host, if it is connected.
+    pub fn send(message: UiRpcClientboundMessage) {
+        get_tokio_rt().spawn(Self::queue_send(message));
+    }
+
+    pub fn resize(metrics: UiMetrics) {
         get_tokio_rt().spawn(async move {
-            debug!("informing UI of resize to {}x{}", width, height);
-            Self::queue_send(UiRpcClientboundMessage::Resize(UiRpcClientboundResize {
-                width,
-                height,
-            }))
-            .await
+            let (logical_width, logical_height) = metrics.logical_size();
+            debug!(
+                "informing UI of resize to {}x{} (logical {}x{}, scale {})",
+                metrics.width,
+                metrics.height,
+                logical_width,
+                logical_height,
+                metrics.scale_factor()
+            );
+            Self::queue_send(Self::resize_message(metrics)).await
         });
     }
+
+    fn resize_message(metrics: UiMetrics) -> UiRpcClientboundMessage {
+        UiRpcClientboundMessage::Resize(UiRpcClientboundResize {
+            width: metrics.width,
+            height: metrics.height,
+            dpi_scale: metrics.dpi_scale,
+            ui_scale: metrics.ui_scale,
+        })
+    }
 }
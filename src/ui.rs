@@ -1,13 +1,19 @@
-use crate::get_execution_id;
-use anyhow::{bail, Result};
+use crate::{get_execution_id, get_runtime_dir, rpc::ui::UiRpcServer};
+use anyhow::{anyhow, bail, Result};
 use bytes::Bytes;
-use grebuloff_rpc::ui::{ImageFormat, UiRpcServerboundPaint};
-use log::{error, info, warn};
+use grebuloff_rpc::ui::{
+    Anchor, PaintError, ScaleMode, ScalePolicy, UiRpcClientboundFocus, UiRpcClientboundMessage,
+    UiRpcClientboundMinimize, UiRpcClientboundRejectedPaints, UiRpcClientboundVisibility,
+    UiRpcServerboundPaint,
+};
+use log::{debug, error, info, trace, warn};
 use std::{
+    fs::File,
+    io::BufWriter,
     path::{Path, PathBuf},
     process::Stdio,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Mutex,
     },
 };
@@ -17,6 +23,29 @@ use tokio::{
 };
 
 static LATEST_BUFFER: Mutex<Option<UiBuffer>> = Mutex::new(None);
+static REJECTED_PAINTS: RejectedPaintCounters = RejectedPaintCounters::new();
+static SCALE_POLICY: Mutex<Option<ScalePolicy>> = Mutex::new(None);
+static UI_METRICS: Mutex<UiMetrics> = Mutex::new(UiMetrics {
+    width: 0,
+    height: 0,
+    dpi_scale: 1.0,
+    ui_scale: 1.0,
+});
+
+static WINDOW_FOCUSED: AtomicBool = AtomicBool::new(true);
+static WINDOW_MINIMIZED: AtomicBool = AtomicBool::new(false);
+static OVERLAY_VISIBLE: AtomicBool = AtomicBool::new(true);
+
+/// The DPI that corresponds to a scale factor of 1.0.
+const BASE_DPI: f32 = 96.0;
+
+/// After the first, only every this many rejected paints of a kind are logged, as a
+/// misbehaving HLRT will send a bad paint every frame.
+const REJECTED_PAINT_LOG_INTERVAL: u64 = 100;
+
+/// Bounds for the user's UI scale setting.
+const MIN_UI_SCALE: f32 = 0.5;
+const MAX_UI_SCALE: f32 = 4.0;
 
 pub async fn spawn_ui_host(runtime_dir: &PathBuf) -> Result<()> {
     loop {
@@ -80,26 +109,450 @@ pub fn poll_dirty() -> Option<UiBufferSnapshot> {
     lock.as_mut().map(|v| v.poll_dirty()).flatten()
 }
 
-pub fn update_buffer_on_paint(paint: UiRpcServerboundPaint) {
-    assert_eq!(
-        paint.format,
-        ImageFormat::BGRA8,
-        "only ImageFormat::BGRA8 is supported"
+/// Returns true if the overlay should be drawn (and thus its texture updated).
+pub fn should_render() -> bool {
+    OVERLAY_VISIBLE.load(Ordering::Relaxed) && !WINDOW_MINIMIZED.load(Ordering::Relaxed)
+}
+
+pub fn set_window_focused(focused: bool) {
+    if WINDOW_FOCUSED.swap(focused, Ordering::Relaxed) != focused {
+        debug!("game window focused: {}", focused);
+        UiRpcServer::send(UiRpcClientboundMessage::Focus(UiRpcClientboundFocus {
+            focused,
+        }));
+    }
+}
+
+pub fn set_window_minimized(minimized: bool) {
+    if WINDOW_MINIMIZED.swap(minimized, Ordering::Relaxed) != minimized {
+        debug!("game window minimized: {}", minimized);
+        UiRpcServer::send(UiRpcClientboundMessage::Minimize(
+            UiRpcClientboundMinimize { minimized },
+        ));
+    }
+}
+
+pub fn set_overlay_visible(visible: bool) {
+    if OVERLAY_VISIBLE.swap(visible, Ordering::Relaxed) != visible {
+        debug!("overlay visible: {}", visible);
+        UiRpcServer::send(UiRpcClientboundMessage::Visibility(
+            UiRpcClientboundVisibility { visible },
+        ));
+    }
+}
+
+/// The focus, minimize and visibility messages for the current state of the game
+/// window and overlay, for bringing a newly connected HLRT up to date.
+pub fn window_state() -> [UiRpcClientboundMessage; 3] {
+    [
+        UiRpcClientboundMessage::Focus(UiRpcClientboundFocus {
+            focused: WINDOW_FOCUSED.load(Ordering::Relaxed),
+        }),
+        UiRpcClientboundMessage::Minimize(UiRpcClientboundMinimize {
+            minimized: WINDOW_MINIMIZED.load(Ordering::Relaxed),
+        }),
+        UiRpcClientboundMessage::Visibility(UiRpcClientboundVisibility {
+            visible: OVERLAY_VISIBLE.load(Ordering::Relaxed),
+        }),
+    ]
+}
+
+pub fn toggle_overlay_visible() {
+    set_overlay_visible(!OVERLAY_VISIBLE.load(Ordering::Relaxed));
+}
+
+/// The size and scaling of the UI, as last communicated to the HLRT.
+///
+/// The UI is painted at the physical size of the game window, while its content
+/// is laid out at a logical size. To map a physical pixel (e.g. a mouse position)
+/// to a logical one, divide by `scale_factor()`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UiMetrics {
+    pub width: u32,
+    pub height: u32,
+    pub dpi_scale: f32,
+    pub ui_scale: f32,
+}
+
+impl UiMetrics {
+    /// Number of physical pixels per logical pixel.
+    pub fn scale_factor(&self) -> f32 {
+        self.dpi_scale * self.ui_scale
+    }
+
+    pub fn logical_size(&self) -> (u32, u32) {
+        let scale = self.scale_factor();
+        (
+            (self.width as f32 / scale).round() as u32,
+            (self.height as f32 / scale).round() as u32,
+        )
+    }
+
+    /// Returns true once the size of the game window is known.
+    pub fn has_size(&self) -> bool {
+        self.width != 0 && self.height != 0
+    }
+}
+
+/// Returns the size and scaling of the UI, as last communicated to the HLRT.
+pub fn metrics() -> UiMetrics {
+    *UI_METRICS.lock().unwrap()
+}
+
+/// Converts a DPI, as returned by `GetDpiForWindow` or sent with `WM_DPICHANGED`,
+/// to a scale factor. A DPI of 0 means it couldn't be determined.
+pub fn dpi_to_scale(dpi: u32) -> f32 {
+    match dpi {
+        0 => 1.0,
+        dpi => dpi as f32 / BASE_DPI,
+    }
+}
+
+/// Updates the physical size and DPI scale of the game window, and informs the HLRT.
+pub fn resize(width: u32, height: u32, dpi_scale: f32) {
+    let metrics = {
+        let mut metrics = UI_METRICS.lock().unwrap();
+        metrics.width = width;
+        metrics.height = height;
+        metrics.dpi_scale = dpi_scale;
+        *metrics
+    };
+
+    UiRpcServer::resize(metrics);
+}
+
+/// Updates the DPI scale of the game window when it moves to another display, and
+/// informs the HLRT if the game window size is known.
+pub fn set_dpi_scale(dpi_scale: f32) {
+    let metrics = {
+        let mut metrics = UI_METRICS.lock().unwrap();
+        if metrics.dpi_scale == dpi_scale {
+            return;
+        }
+
+        metrics.dpi_scale = dpi_scale;
+        *metrics
+    };
+
+    debug!("game window DPI scale changed to {}", dpi_scale);
+    if metrics.has_size() {
+        UiRpcServer::resize(metrics);
+    }
+}
+
+/// Updates the user's UI scale setting, and informs the HLRT if the game window size is known.
+pub fn set_user_ui_scale(scale: f32) {
+    if !scale.is_finite() {
+        warn!("ignoring invalid UI scale {}", scale);
+        return;
+    }
+
+    let metrics = {
+        let mut metrics = UI_METRICS.lock().unwrap();
+        metrics.ui_scale = scale.clamp(MIN_UI_SCALE, MAX_UI_SCALE);
+        *metrics
+    };
+
+    debug!("setting user UI scale to {}", metrics.ui_scale);
+    if metrics.has_size() {
+        UiRpcServer::resize(metrics);
+    }
+}
+
+pub fn scale_policy() -> ScalePolicy {
+    SCALE_POLICY.lock().unwrap().unwrap_or_default()
+}
+
+pub fn set_scale_policy(policy: ScalePolicy) {
+    debug!("setting UI scale policy to {:?}", policy);
+    let _ = SCALE_POLICY.lock().unwrap().insert(policy);
+}
+
+/// Adapts a snapshot to the given size using the current scale policy.
+/// Returns `None` if the snapshot should not be displayed.
+fn fit_snapshot(snapshot: UiBufferSnapshot, width: u32, height: u32) -> Option<UiBufferSnapshot> {
+    if snapshot.width == width && snapshot.height == height {
+        return Some(snapshot);
+    }
+
+    let policy = scale_policy();
+    trace!(
+        "adapting {}x{} UI snapshot to {}x{} ({:?})",
+        snapshot.width,
+        snapshot.height,
+        width,
+        height,
+        policy
     );
 
-    assert_eq!(
-        paint.data.len(),
-        paint
-            .format
-            .byte_size_of(paint.width as usize, paint.height as usize)
+    let src = (snapshot.data.as_ref(), snapshot.width, snapshot.height);
+    let data = match policy.mode {
+        ScaleMode::Skip => {
+            warn!("latest UI snapshot does not match our current UI size, skipping update");
+            return None;
+        }
+        ScaleMode::Nearest => scale_nearest(src, width, height),
+        ScaleMode::Bilinear => scale_bilinear(src, width, height),
+        ScaleMode::Letterbox => letterbox(src, width, height, policy.anchor),
+    };
+
+    Some(UiBufferSnapshot {
+        width,
+        height,
+        data: data.into(),
+    })
+}
+
+/// A borrowed 32-bit image: pixel data, width, and height.
+type ImageRef<'a> = (&'a [u8], u32, u32);
+
+fn scale_nearest((src, src_w, src_h): ImageRef, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    let mut out = Vec::with_capacity(dst_w as usize * dst_h as usize * 4);
+
+    for dy in 0..dst_h as u64 {
+        let sy = (dy * src_h as u64 / dst_h as u64) as usize;
+        let row = &src[sy * src_w as usize * 4..][..src_w as usize * 4];
+
+        for dx in 0..dst_w as u64 {
+            let sx = (dx * src_w as u64 / dst_w as u64) as usize;
+            out.extend_from_slice(&row[sx * 4..sx * 4 + 4]);
+        }
+    }
+
+    out
+}
+
+fn scale_bilinear((src, src_w, src_h): ImageRef, dst_w: u32, dst_h: u32) -> Vec<u8> {
+    // maps a destination coordinate to a source coordinate in 16.16 fixed point,
+    // sampling from pixel centres, returning the two source pixels and the weight
+    fn sample_points(d: u64, src_len: u32, dst_len: u32) -> (usize, usize, u64) {
+        let s =
+            ((2 * d + 1) * src_len as u64 * 0x10000 / (2 * dst_len as u64)).saturating_sub(0x8000);
+        let s0 = ((s >> 16) as usize).min(src_len as usize - 1);
+        let s1 = (s0 + 1).min(src_len as usize - 1);
+
+        (s0, s1, s & 0xFFFF)
+    }
+
+    let stride = src_w as usize * 4;
+    let mut out = Vec::with_capacity(dst_w as usize * dst_h as usize * 4);
+
+    for dy in 0..dst_h as u64 {
+        let (y0, y1, fy) = sample_points(dy, src_h, dst_h);
+        let (row0, row1) = (&src[y0 * stride..][..stride], &src[y1 * stride..][..stride]);
+
+        for dx in 0..dst_w as u64 {
+            let (x0, x1, fx) = sample_points(dx, src_w, dst_w);
+
+            for c in 0..4 {
+                let top = row0[x0 * 4 + c] as u64 * (0x10000 - fx) + row0[x1 * 4 + c] as u64 * fx;
+                let bottom =
+                    row1[x0 * 4 + c] as u64 * (0x10000 - fx) + row1[x1 * 4 + c] as u64 * fx;
+                let value = (top * (0x10000 - fy) + bottom * fy + (1 << 31)) >> 32;
+
+                out.push(value as u8);
+            }
+        }
+    }
+
+    out
+}
+
+fn letterbox((src, src_w, src_h): ImageRef, dst_w: u32, dst_h: u32, anchor: Anchor) -> Vec<u8> {
+    // horizontal and vertical alignment, where 0 = start, 1 = centre, 2 = end
+    let (align_x, align_y) = match anchor {
+        Anchor::TopLeft => (0, 0),
+        Anchor::Top => (1, 0),
+        Anchor::TopRight => (2, 0),
+        Anchor::Left => (0, 1),
+        Anchor::Center => (1, 1),
+        Anchor::Right => (2, 1),
+        Anchor::BottomLeft => (0, 2),
+        Anchor::Bottom => (1, 2),
+        Anchor::BottomRight => (2, 2),
+    };
+
+    // offset of the source image within the destination; negative if cropped
+    let offset_x = (dst_w as i64 - src_w as i64) * align_x / 2;
+    let offset_y = (dst_h as i64 - src_h as i64) * align_y / 2;
+
+    // anything not covered by the source image is left transparent
+    let mut out = vec![0u8; dst_w as usize * dst_h as usize * 4];
+
+    let x_start = offset_x.max(0);
+    let x_end = (offset_x + src_w as i64).min(dst_w as i64);
+    if x_start >= x_end {
+        return out;
+    }
+
+    let row_len = (x_end - x_start) as usize * 4;
+    for dy in offset_y.max(0)..(offset_y + src_h as i64).min(dst_h as i64) {
+        let sy = (dy - offset_y) as usize;
+        let sx = (x_start - offset_x) as usize;
+
+        let src_row = &src[(sy * src_w as usize + sx) * 4..][..row_len];
+        out[(dy as usize * dst_w as usize + x_start as usize) * 4..][..row_len]
+            .copy_from_slice(src_row);
+    }
+
+    out
+}
+
+/// Writes the contents of the latest UI buffer to a PNG file in the runtime directory,
+/// returning the path of the written file.
+pub fn export_snapshot_png() -> Result<PathBuf> {
+    let snapshot = {
+        let lock = LATEST_BUFFER.lock().unwrap();
+        lock.as_ref()
+            .map(UiBuffer::snapshot)
+            .ok_or_else(|| anyhow!("no UI buffer has been painted yet"))?
+    };
+
+    let path = get_runtime_dir().join(format!(
+        "ui-snapshot-{}.png",
+        chrono::Local::now().format("%Y%m%d-%H%M%S%.3f")
+    ));
+
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(&path)?),
+        snapshot.width,
+        snapshot.height,
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&bgra_premultiplied_to_rgba(&snapshot.data))?;
+    writer.finish()?;
+
+    info!(
+        "exported {}x{} UI snapshot to {}",
+        snapshot.width,
+        snapshot.height,
+        path.display()
     );
 
+    Ok(path)
+}
+
+/// Converts premultiplied BGRA pixels (as painted by Chromium) into
+/// straight-alpha RGBA pixels, as expected by PNG.
+fn bgra_premultiplied_to_rgba(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(data.len());
+
+    for px in data.chunks_exact(4) {
+        let (b, g, r, a) = (px[0], px[1], px[2], px[3]);
+
+        let unpremultiply = |c: u8| match a {
+            0 => 0,
+            255 => c,
+            _ => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8,
+        };
+
+        out.extend_from_slice(&[unpremultiply(r), unpremultiply(g), unpremultiply(b), a]);
+    }
+
+    out
+}
+
+/// Stores a paint from the HLRT, to be presented with the next frame. Rejected paints
+/// are left for the caller to record.
+pub fn update_buffer_on_paint(paint: UiRpcServerboundPaint) -> Result<(), PaintError> {
+    paint.validate()?;
+
+    let snapshot = UiBufferSnapshot {
+        width: paint.width.into(),
+        height: paint.height.into(),
+        data: paint.data,
+    };
+
+    // adapt paints that don't match the game window now, rather than on the render
+    // thread, so presenting only has to copy them
+    let Some(snapshot) = fit_to_window(&metrics(), snapshot) else {
+        return Ok(());
+    };
+
     let mut lock = LATEST_BUFFER.lock().unwrap();
     let _ = lock.insert(UiBuffer::new_dirty(
-        paint.width.into(),
-        paint.height.into(),
-        paint.data,
+        snapshot.width,
+        snapshot.height,
+        snapshot.data,
     ));
+
+    Ok(())
+}
+
+/// Fits a paint to the game window, or returns it as it is until the window's size
+/// is known. Returns `None` if the scale policy skips paints that don't fit.
+fn fit_to_window(metrics: &UiMetrics, snapshot: UiBufferSnapshot) -> Option<UiBufferSnapshot> {
+    if !metrics.has_size() {
+        return Some(snapshot);
+    }
+
+    fit_snapshot(snapshot, metrics.width, metrics.height)
+}
+
+/// Counts a paint message that failed validation, logging the first and every
+/// `REJECTED_PAINT_LOG_INTERVAL`th of each kind.
+pub fn record_rejected_paint(error: &PaintError) {
+    let counter = match error {
+        PaintError::BadHeader { .. } => &REJECTED_PAINTS.bad_header,
+        PaintError::UnknownFormat(_) => &REJECTED_PAINTS.unknown_format,
+        PaintError::ZeroDimensions { .. } | PaintError::OversizedDimensions { .. } => {
+            &REJECTED_PAINTS.bad_dimensions
+        }
+        PaintError::SizeMismatch { .. } => &REJECTED_PAINTS.size_mismatch,
+    };
+
+    let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+    if count != 1 && count % REJECTED_PAINT_LOG_INTERVAL != 0 {
+        return;
+    }
+
+    warn!(
+        "rejected paint from HLRT: {} ({} for this reason, {} in total)",
+        error,
+        count,
+        REJECTED_PAINTS.total()
+    );
+}
+
+/// Counters for paint messages from the HLRT that were rejected, by reason.
+struct RejectedPaintCounters {
+    bad_header: AtomicU64,
+    unknown_format: AtomicU64,
+    bad_dimensions: AtomicU64,
+    size_mismatch: AtomicU64,
+}
+
+impl RejectedPaintCounters {
+    const fn new() -> Self {
+        Self {
+            bad_header: AtomicU64::new(0),
+            unknown_format: AtomicU64::new(0),
+            bad_dimensions: AtomicU64::new(0),
+            size_mismatch: AtomicU64::new(0),
+        }
+    }
+
+    fn snapshot(&self) -> UiRpcClientboundRejectedPaints {
+        UiRpcClientboundRejectedPaints {
+            bad_header: self.bad_header.load(Ordering::Relaxed),
+            unknown_format: self.unknown_format.load(Ordering::Relaxed),
+            bad_dimensions: self.bad_dimensions.load(Ordering::Relaxed),
+            size_mismatch: self.size_mismatch.load(Ordering::Relaxed),
+        }
+    }
+
+    fn total(&self) -> u64 {
+        let counts = self.snapshot();
+        counts.bad_header + counts.unknown_format + counts.bad_dimensions + counts.size_mismatch
+    }
+}
+
+/// Returns how many paints have been rejected, by reason.
+pub fn rejected_paints() -> UiRpcClientboundRejectedPaints {
+    REJECTED_PAINTS.snapshot()
 }
 
 pub struct UiBufferSnapshot {
@@ -112,30 +565,192 @@ struct UiBuffer {
     width: u32,
     height: u32,
     dirty: AtomicBool,
-    data: Option<Bytes>,
+    data: Bytes,
 }
 
 impl UiBuffer {
     pub fn poll_dirty(&mut self) -> Option<UiBufferSnapshot> {
         if self.dirty.swap(false, Ordering::Relaxed) {
-            if let Some(data) = self.data.take() {
-                return Some(UiBufferSnapshot {
-                    width: self.width,
-                    height: self.height,
-                    data,
-                });
-            }
+            return Some(self.snapshot());
         }
 
         None
     }
 
+    /// Returns a snapshot of the buffer without affecting its dirty state.
+    pub fn snapshot(&self) -> UiBufferSnapshot {
+        UiBufferSnapshot {
+            width: self.width,
+            height: self.height,
+            data: self.data.clone(),
+        }
+    }
+
     fn new_dirty(width: u32, height: u32, data: Bytes) -> Self {
         Self {
             width,
             height,
             dirty: AtomicBool::new(true),
-            data: Some(data),
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a test image where each pixel is `[x, y, x + y, 0xFF]`.
+    fn gradient(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                out.extend_from_slice(&[x as u8, y as u8, (x + y) as u8, 0xFF]);
+            }
         }
+        out
+    }
+
+    fn pixel(data: &[u8], width: u32, x: u32, y: u32) -> &[u8] {
+        let i = (y * width + x) as usize * 4;
+        &data[i..i + 4]
+    }
+
+    #[test]
+    fn test_scale_nearest() {
+        let src = gradient(2, 2);
+        let out = scale_nearest((&src, 2, 2), 4, 4);
+        assert_eq!(out.len(), 4 * 4 * 4);
+        assert_eq!(pixel(&out, 4, 0, 0), &[0, 0, 0, 0xFF]);
+        assert_eq!(pixel(&out, 4, 1, 1), &[0, 0, 0, 0xFF]);
+        assert_eq!(pixel(&out, 4, 2, 1), &[1, 0, 1, 0xFF]);
+        assert_eq!(pixel(&out, 4, 3, 3), &[1, 1, 2, 0xFF]);
+
+        let out = scale_nearest((&gradient(4, 4), 4, 4), 2, 2);
+        assert_eq!(
+            out,
+            [0, 0, 0, 0xFF, 2, 0, 2, 0xFF, 0, 2, 2, 0xFF, 2, 2, 4, 0xFF]
+        );
+    }
+
+    #[test]
+    fn test_scale_bilinear() {
+        // same size is an identity transform
+        let src = gradient(5, 3);
+        assert_eq!(scale_bilinear((&src, 5, 3), 5, 3), src);
+
+        // a flat colour stays flat
+        let flat = [0x40, 0x80, 0xC0, 0xFF].repeat(3 * 3);
+        let out = scale_bilinear((&flat, 3, 3), 7, 5);
+        assert!(out.chunks_exact(4).all(|px| px == [0x40, 0x80, 0xC0, 0xFF]));
+
+        // upscaling 0 -> 200 horizontally interpolates between the two
+        let src = [0, 0, 0, 0, 200, 0, 0, 0];
+        let out = scale_bilinear((&src, 2, 1), 4, 1);
+        let reds: Vec<u8> = out.chunks_exact(4).map(|px| px[0]).collect();
+        assert_eq!(reds, [0, 50, 150, 200]);
+    }
+
+    #[test]
+    fn test_letterbox() {
+        let src = gradient(2, 2);
+
+        // growing: the source is placed at the anchor, the rest is transparent
+        let out = letterbox((&src, 2, 2), 4, 4, Anchor::BottomRight);
+        assert_eq!(pixel(&out, 4, 0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(&out, 4, 2, 2), &[0, 0, 0, 0xFF]);
+        assert_eq!(pixel(&out, 4, 3, 3), &[1, 1, 2, 0xFF]);
+
+        let out = letterbox((&src, 2, 2), 4, 4, Anchor::Center);
+        assert_eq!(pixel(&out, 4, 1, 1), &[0, 0, 0, 0xFF]);
+        assert_eq!(pixel(&out, 4, 3, 1), &[0, 0, 0, 0]);
+
+        // shrinking: the source is cropped around the anchor
+        let src = gradient(4, 4);
+        let out = letterbox((&src, 4, 4), 2, 2, Anchor::TopLeft);
+        assert_eq!(
+            out,
+            [0, 0, 0, 0xFF, 1, 0, 1, 0xFF, 0, 1, 1, 0xFF, 1, 1, 2, 0xFF]
+        );
+
+        let out = letterbox((&src, 4, 4), 2, 2, Anchor::Center);
+        assert_eq!(pixel(&out, 2, 0, 0), &[1, 1, 2, 0xFF]);
+
+        let out = letterbox((&src, 4, 4), 2, 3, Anchor::Right);
+        assert_eq!(pixel(&out, 2, 0, 0), &[2, 0, 2, 0xFF]);
+        assert_eq!(pixel(&out, 2, 1, 2), &[3, 2, 5, 0xFF]);
+    }
+
+    #[test]
+    fn test_paint_fitted_to_window() {
+        let snapshot = || UiBufferSnapshot {
+            width: 2,
+            height: 1,
+            data: gradient(2, 1).into(),
+        };
+        let mut metrics = UiMetrics {
+            width: 0,
+            height: 0,
+            dpi_scale: 1.0,
+            ui_scale: 1.0,
+        };
+
+        // kept as it is until the window's size is known
+        let fitted = fit_to_window(&metrics, snapshot()).unwrap();
+        assert_eq!((fitted.width, fitted.height), (2, 1));
+
+        // then stored at the window's size, so presenting only has to copy it
+        metrics.width = 4;
+        metrics.height = 2;
+        let fitted = fit_to_window(&metrics, snapshot()).unwrap();
+        assert_eq!((fitted.width, fitted.height), (4, 2));
+        assert_eq!(fitted.data.len(), 4 * 2 * 4);
+    }
+
+    #[test]
+    fn test_metrics_scaling() {
+        let metrics = UiMetrics {
+            width: 3840,
+            height: 2160,
+            dpi_scale: 1.5,
+            ui_scale: 2.0,
+        };
+        assert_eq!(metrics.scale_factor(), 3.0);
+        assert_eq!(metrics.logical_size(), (1280, 720));
+        assert!(metrics.has_size());
+
+        // odd sizes round to the nearest logical pixel
+        let metrics = UiMetrics {
+            width: 1001,
+            height: 0,
+            dpi_scale: 1.25,
+            ui_scale: 1.0,
+        };
+        assert_eq!(metrics.logical_size(), (801, 0));
+        assert!(!metrics.has_size());
+    }
+
+    #[test]
+    fn test_dpi_to_scale() {
+        assert_eq!(dpi_to_scale(96), 1.0);
+        assert_eq!(dpi_to_scale(144), 1.5);
+        assert_eq!(dpi_to_scale(0), 1.0);
+    }
+
+    #[test]
+    fn test_bgra_to_rgba_swizzle() {
+        let rgba = bgra_premultiplied_to_rgba(&[0x10, 0x20, 0x30, 0xFF, 0x01, 0x02, 0x03, 0xFF]);
+        assert_eq!(rgba, [0x30, 0x20, 0x10, 0xFF, 0x03, 0x02, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn test_bgra_to_rgba_unpremultiply() {
+        // 50% alpha, premultiplied
+        let rgba = bgra_premultiplied_to_rgba(&[0x00, 0x40, 0x80, 0x80]);
+        assert_eq!(rgba, [0xFF, 0x80, 0x00, 0x80]);
+
+        // fully transparent pixels have no meaningful colour
+        let rgba = bgra_premultiplied_to_rgba(&[0x12, 0x34, 0x56, 0x00]);
+        assert_eq!(rgba, [0x00, 0x00, 0x00, 0x00]);
     }
 }